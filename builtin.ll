@@ -5,6 +5,10 @@ declare i8* @malloc(i64 %size)
 declare i8* @realloc(i8* %ptr, i64 %size)
 declare void @free(i8* %ptr)
 declare i32 @puts(i8* %ptr)
+declare i8* @memcpy(i8* %dst, i8* %src, i64 %n)
+declare i64 @strlen(i8* %str)
+declare i32 @getchar()
+declare i8* @calloc(i64 %count, i64 %size)
 
 declare void @tower_main(i8** %bp_ptr, i8** %sp_ptr, i8** %ep_ptr)
 
@@ -87,4 +91,392 @@ define void @__hello(i8** %bp_ptr, i8** %sp_ptr, i8** %ep_ptr) {
 	call i32 @puts(i8* %strp)
 
 	ret void
-}
\ No newline at end of file
+}
+
+; Vectors and strings are represented as { data, len, cap }, where len and cap are counted in elements.
+; All of the helpers take a pointer to the representation, which may be unaligned as it usually lives on the tower stack.
+
+define void @__internal_vec_push(i8* %vec, i8* %elem, i64 %elem_size) {
+entry:
+	%hdr = bitcast i8* %vec to { i8*, i64, i64 }*
+	%data_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 0
+	%len_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 1
+	%cap_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 2
+	%data = load i8*, i8** %data_ptr, align 1
+	%len = load i64, i64* %len_ptr, align 1
+	%cap = load i64, i64* %cap_ptr, align 1
+
+	%full = icmp uge i64 %len, %cap
+	br i1 %full, label %grow, label %store
+
+grow:
+	%cap_doubled = mul i64 %cap, 2
+	%cap_empty = icmp eq i64 %cap, 0
+	%new_cap = select i1 %cap_empty, i64 4, i64 %cap_doubled
+	%new_bytes = mul i64 %new_cap, %elem_size
+	%new_data = call i8* @realloc(i8* %data, i64 %new_bytes)
+	store i8* %new_data, i8** %data_ptr, align 1
+	store i64 %new_cap, i64* %cap_ptr, align 1
+	br label %store
+
+store:
+	%data_final = phi i8* [ %data, %entry ], [ %new_data, %grow ]
+	%offset = mul i64 %len, %elem_size
+	%slot = getelementptr i8, i8* %data_final, i64 %offset
+	call i8* @memcpy(i8* %slot, i8* %elem, i64 %elem_size)
+	%len_new = add i64 %len, 1
+	store i64 %len_new, i64* %len_ptr, align 1
+	ret void
+}
+
+; Copies the last element into out and removes it. Returns false if the vector is empty
+define i1 @__internal_vec_pop(i8* %vec, i8* %out, i64 %elem_size) {
+entry:
+	%hdr = bitcast i8* %vec to { i8*, i64, i64 }*
+	%data_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 0
+	%len_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 1
+	%data = load i8*, i8** %data_ptr, align 1
+	%len = load i64, i64* %len_ptr, align 1
+
+	%empty = icmp eq i64 %len, 0
+	br i1 %empty, label %pop-empty, label %pop-ok
+
+pop-empty:
+	ret i1 0
+
+pop-ok:
+	%len_new = sub i64 %len, 1
+	%offset = mul i64 %len_new, %elem_size
+	%slot = getelementptr i8, i8* %data, i64 %offset
+	call i8* @memcpy(i8* %out, i8* %slot, i64 %elem_size)
+	store i64 %len_new, i64* %len_ptr, align 1
+	ret i1 1
+}
+
+; Returns a pointer to the element at index, or null if index is out of bounds
+define i8* @__internal_vec_get(i8* %vec, i64 %index, i64 %elem_size) {
+entry:
+	%hdr = bitcast i8* %vec to { i8*, i64, i64 }*
+	%data_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 0
+	%len_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 1
+	%data = load i8*, i8** %data_ptr, align 1
+	%len = load i64, i64* %len_ptr, align 1
+
+	%in_bounds = icmp ult i64 %index, %len
+	br i1 %in_bounds, label %get-ok, label %get-oob
+
+get-oob:
+	ret i8* null
+
+get-ok:
+	%offset = mul i64 %index, %elem_size
+	%slot = getelementptr i8, i8* %data, i64 %offset
+	ret i8* %slot
+}
+
+; Appends each element of src to vec, then frees src
+define void @__internal_vec_concat(i8* %vec, i8* %src, i64 %elem_size) {
+entry:
+	%hdr = bitcast i8* %src to { i8*, i64, i64 }*
+	%data_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 0
+	%len_ptr = getelementptr { i8*, i64, i64 }, { i8*, i64, i64 }* %hdr, i32 0, i32 1
+	%data = load i8*, i8** %data_ptr, align 1
+	%len = load i64, i64* %len_ptr, align 1
+	br label %loop
+
+loop:
+	%i = phi i64 [ 0, %entry ], [ %i_next, %body ]
+	%done = icmp uge i64 %i, %len
+	br i1 %done, label %concat-ret, label %body
+
+body:
+	%offset = mul i64 %i, %elem_size
+	%slot = getelementptr i8, i8* %data, i64 %offset
+	call void @__internal_vec_push(i8* %vec, i8* %slot, i64 %elem_size)
+	%i_next = add i64 %i, 1
+	br label %loop
+
+concat-ret:
+	call void @free(i8* %data)
+	ret void
+}
+
+; Appends the bytes of the null-terminated str to the string
+define void @__internal_string_push_str(i8* %string, i8* %str) {
+entry:
+	%len = call i64 @strlen(i8* %str)
+	br label %loop
+
+loop:
+	%i = phi i64 [ 0, %entry ], [ %i_next, %body ]
+	%done = icmp uge i64 %i, %len
+	br i1 %done, label %push-ret, label %body
+
+body:
+	%byte_ptr = getelementptr i8, i8* %str, i64 %i
+	call void @__internal_vec_push(i8* %string, i8* %byte_ptr, i64 1)
+	%i_next = add i64 %i, 1
+	br label %loop
+
+push-ret:
+	ret void
+}
+
+; Appends a line read from stdin to the string, excluding the newline
+define void @__internal_read_line(i8* %string) {
+entry:
+	%byte_ptr = alloca i8
+	br label %loop
+
+loop:
+	%c = call i32 @getchar()
+	%eof = icmp slt i32 %c, 0
+	%newline = icmp eq i32 %c, 10
+	%done = or i1 %eof, %newline
+	br i1 %done, label %read-ret, label %body
+
+body:
+	%byte = trunc i32 %c to i8
+	store i8 %byte, i8* %byte_ptr
+	call void @__internal_vec_push(i8* %string, i8* %byte_ptr, i64 1)
+	br label %loop
+
+read-ret:
+	ret void
+}
+
+; Maps are represented as { entries, len, cap, used }, where cap is zero or a power of two and used counts both full and removed
+; entries. Each entry is [state: i8][key][value], where the state is 0 for empty, 1 for full and 2 for removed.
+; As keys are hashed and compared structurally, the map helpers take the hash and equality functions for the key type.
+
+; FNV-1a hash of len bytes
+define i64 @__internal_hash_bytes(i8* %data, i64 %len) {
+entry:
+	br label %loop
+
+loop:
+	%i = phi i64 [ 0, %entry ], [ %i_next, %body ]
+	%hash = phi i64 [ -3750763034362895579, %entry ], [ %hash_next, %body ]
+	%done = icmp uge i64 %i, %len
+	br i1 %done, label %hash-ret, label %body
+
+body:
+	%byte_ptr = getelementptr i8, i8* %data, i64 %i
+	%byte = load i8, i8* %byte_ptr
+	%byte_ext = zext i8 %byte to i64
+	%mixed = xor i64 %hash, %byte_ext
+	%hash_next = mul i64 %mixed, 1099511628211
+	%i_next = add i64 %i, 1
+	br label %loop
+
+hash-ret:
+	ret i64 %hash
+}
+
+; Returns a pointer to the full entry for key, or null if there is none
+define i8* @__internal_map_find(i8* %map, i8* %key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn, i1 (i8*, i8*)* %eq_fn) {
+entry:
+	%hdr = bitcast i8* %map to { i8*, i64, i64, i64 }*
+	%entries_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 0
+	%cap_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 2
+	%entries = load i8*, i8** %entries_ptr, align 1
+	%cap = load i64, i64* %cap_ptr, align 1
+
+	%no_entries = icmp eq i64 %cap, 0
+	br i1 %no_entries, label %not-found, label %start
+
+start:
+	%hash = call i64 %hash_fn(i8* %key)
+	%mask = sub i64 %cap, 1
+	%kv_size = add i64 %key_size, %value_size
+	%entry_size = add i64 %kv_size, 1
+	%first = and i64 %hash, %mask
+	br label %probe
+
+probe:
+	%i = phi i64 [ %first, %start ], [ %next, %continue ]
+	%offset = mul i64 %i, %entry_size
+	%slot = getelementptr i8, i8* %entries, i64 %offset
+	%state = load i8, i8* %slot
+	%is_empty = icmp eq i8 %state, 0
+	br i1 %is_empty, label %not-found, label %check
+
+check:
+	%is_full = icmp eq i8 %state, 1
+	br i1 %is_full, label %compare, label %continue
+
+compare:
+	%slot_key = getelementptr i8, i8* %slot, i64 1
+	%matches = call i1 %eq_fn(i8* %slot_key, i8* %key)
+	br i1 %matches, label %found, label %continue
+
+continue:
+	%inc = add i64 %i, 1
+	%next = and i64 %inc, %mask
+	br label %probe
+
+found:
+	ret i8* %slot
+
+not-found:
+	ret i8* null
+}
+
+; Returns the first entry that isn't full in the probe sequence of key. The map must have spare capacity
+define i8* @__internal_map_vacant(i8* %map, i8* %key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn) {
+entry:
+	%hdr = bitcast i8* %map to { i8*, i64, i64, i64 }*
+	%entries_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 0
+	%cap_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 2
+	%entries = load i8*, i8** %entries_ptr, align 1
+	%cap = load i64, i64* %cap_ptr, align 1
+
+	%hash = call i64 %hash_fn(i8* %key)
+	%mask = sub i64 %cap, 1
+	%kv_size = add i64 %key_size, %value_size
+	%entry_size = add i64 %kv_size, 1
+	%first = and i64 %hash, %mask
+	br label %probe
+
+probe:
+	%i = phi i64 [ %first, %entry ], [ %next, %continue ]
+	%offset = mul i64 %i, %entry_size
+	%slot = getelementptr i8, i8* %entries, i64 %offset
+	%state = load i8, i8* %slot
+	%is_full = icmp eq i8 %state, 1
+	br i1 %is_full, label %continue, label %vacant
+
+continue:
+	%inc = add i64 %i, 1
+	%next = and i64 %inc, %mask
+	br label %probe
+
+vacant:
+	ret i8* %slot
+}
+
+; Doubles the capacity of the map, rehashing its entries and dropping removed ones
+define void @__internal_map_grow(i8* %map, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn) {
+entry:
+	%hdr = bitcast i8* %map to { i8*, i64, i64, i64 }*
+	%entries_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 0
+	%len_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 1
+	%cap_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 2
+	%used_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 3
+	%entries = load i8*, i8** %entries_ptr, align 1
+	%len = load i64, i64* %len_ptr, align 1
+	%cap = load i64, i64* %cap_ptr, align 1
+
+	%kv_size = add i64 %key_size, %value_size
+	%entry_size = add i64 %kv_size, 1
+	%cap_doubled = mul i64 %cap, 2
+	%cap_empty = icmp eq i64 %cap, 0
+	%new_cap = select i1 %cap_empty, i64 8, i64 %cap_doubled
+	%new_entries = call i8* @calloc(i64 %new_cap, i64 %entry_size)
+
+	store i8* %new_entries, i8** %entries_ptr, align 1
+	store i64 %new_cap, i64* %cap_ptr, align 1
+	store i64 %len, i64* %used_ptr, align 1
+	br label %loop
+
+loop:
+	%i = phi i64 [ 0, %entry ], [ %i_next, %next ]
+	%done = icmp uge i64 %i, %cap
+	br i1 %done, label %grow-ret, label %body
+
+body:
+	%offset = mul i64 %i, %entry_size
+	%old_slot = getelementptr i8, i8* %entries, i64 %offset
+	%state = load i8, i8* %old_slot
+	%is_full = icmp eq i8 %state, 1
+	br i1 %is_full, label %move, label %next
+
+move:
+	%old_key = getelementptr i8, i8* %old_slot, i64 1
+	%new_slot = call i8* @__internal_map_vacant(i8* %map, i8* %old_key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn)
+	call i8* @memcpy(i8* %new_slot, i8* %old_slot, i64 %entry_size)
+	br label %next
+
+next:
+	%i_next = add i64 %i, 1
+	br label %loop
+
+grow-ret:
+	call void @free(i8* %entries)
+	ret void
+}
+
+; Inserts the key and value into the map, replacing the value if the key is already present
+define void @__internal_map_insert(i8* %map, i8* %key, i8* %value, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn, i1 (i8*, i8*)* %eq_fn) {
+entry:
+	%existing = call i8* @__internal_map_find(i8* %map, i8* %key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn, i1 (i8*, i8*)* %eq_fn)
+	%exists = icmp ne i8* %existing, null
+	br i1 %exists, label %replace, label %insert
+
+replace:
+	%value_offset = add i64 %key_size, 1
+	%existing_value = getelementptr i8, i8* %existing, i64 %value_offset
+	call i8* @memcpy(i8* %existing_value, i8* %value, i64 %value_size)
+	ret void
+
+insert:
+	%hdr = bitcast i8* %map to { i8*, i64, i64, i64 }*
+	%len_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 1
+	%cap_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 2
+	%used_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 3
+	%cap = load i64, i64* %cap_ptr, align 1
+	%used = load i64, i64* %used_ptr, align 1
+
+	; Keep the map at most 3/4 used, so that probing always reaches an empty entry
+	%used_next = add i64 %used, 1
+	%used_scaled = mul i64 %used_next, 4
+	%cap_scaled = mul i64 %cap, 3
+	%too_full = icmp ugt i64 %used_scaled, %cap_scaled
+	br i1 %too_full, label %grow, label %place
+
+grow:
+	call void @__internal_map_grow(i8* %map, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn)
+	br label %place
+
+place:
+	%slot = call i8* @__internal_map_vacant(i8* %map, i8* %key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn)
+	%state = load i8, i8* %slot
+	%was_empty = icmp eq i8 %state, 0
+	%used_inc = zext i1 %was_empty to i64
+	%used_current = load i64, i64* %used_ptr, align 1
+	%used_new = add i64 %used_current, %used_inc
+	store i64 %used_new, i64* %used_ptr, align 1
+
+	store i8 1, i8* %slot
+	%slot_key = getelementptr i8, i8* %slot, i64 1
+	call i8* @memcpy(i8* %slot_key, i8* %key, i64 %key_size)
+	%slot_value_offset = add i64 %key_size, 1
+	%slot_value = getelementptr i8, i8* %slot, i64 %slot_value_offset
+	call i8* @memcpy(i8* %slot_value, i8* %value, i64 %value_size)
+
+	%len = load i64, i64* %len_ptr, align 1
+	%len_new = add i64 %len, 1
+	store i64 %len_new, i64* %len_ptr, align 1
+	ret void
+}
+
+; Removes the entry for key from the map. Returns false if there was none
+define i1 @__internal_map_remove(i8* %map, i8* %key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn, i1 (i8*, i8*)* %eq_fn) {
+entry:
+	%existing = call i8* @__internal_map_find(i8* %map, i8* %key, i64 %key_size, i64 %value_size, i64 (i8*)* %hash_fn, i1 (i8*, i8*)* %eq_fn)
+	%exists = icmp ne i8* %existing, null
+	br i1 %exists, label %remove, label %remove-none
+
+remove-none:
+	ret i1 0
+
+remove:
+	store i8 2, i8* %existing
+
+	%hdr = bitcast i8* %map to { i8*, i64, i64, i64 }*
+	%len_ptr = getelementptr { i8*, i64, i64, i64 }, { i8*, i64, i64, i64 }* %hdr, i32 0, i32 1
+	%len = load i64, i64* %len_ptr, align 1
+	%len_new = sub i64 %len, 1
+	store i64 %len_new, i64* %len_ptr, align 1
+	ret i1 1
+}
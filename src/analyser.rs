@@ -6,13 +6,13 @@ pub mod error;
 
 use std::fmt::Display;
 
-use error::{AnalysisError, AnalysisErrorKind};
+use error::{AnalysisError, AnalysisErrorKind, AnalysisWarning, AnalysisWarningKind};
 use stack_effect::StackEffect;
 use tree::{TypedTree, TypedTreeNode};
-use ttype::Type;
+use ttype::{OpaqueTypeKind, Type};
 use value::Value;
 
-use crate::{brk, interpreter::builtin::BuiltinWord, parser::{result::ScanResult::{self, Unrecognised, Valid, WithErr}, tree::{Literal, ParseTree, ParseTreeNode}}};
+use crate::{brk, interpreter::{self, builtin::BuiltinWord}, parser::{result::ScanResult::{self, Unrecognised, Valid, WithErr}, tree::{Attribute, Literal, ParseTree, ParseTreeNode, Span}}};
 
 // NOTE: I don't like this
 #[derive(PartialEq, Clone, Debug)]
@@ -220,184 +220,292 @@ fn calc_stack_effects(parse_tree: &ParseTreeNode, tles: &im::OrdMap<String, Type
 						Unrecognised => {
 							()
 						}
+						// Items which fail to parse, and any that use them, aren't analysed any further
+						WithErr(e) if e.is_invalid() => {
+							typed_elems.insert(name.to_string(), TypedTree::Invalid.wrap(node.span));
+							i -= 1;
+							to_analyse.remove(i);
+							any_new_resolved = true;
+						}
 						WithErr(e) => return WithErr(e.clone())
 					}
 				}
 				// If no new top-level elements have been resolved since last loop over remaining elements to resolve, then
 				// there is recursion
 				if !any_new_resolved {
-					return WithErr(AnalysisError::new(AnalysisErrorKind::FunctionDependencyLoop { fn_names: to_analyse.iter().map(|n| n.0.clone()).collect() }, parse_tree.cursor));
+					return WithErr(AnalysisError::new(AnalysisErrorKind::FunctionDependencyLoop { fn_names: to_analyse.iter().map(|n| n.0.clone()).collect() }, parse_tree.span));
 				}
 			}
 
 			TypedTree::Module { name: name.to_string(), elems: typed_elems.into_iter().collect() }
 		},
-		ParseTree::Function { name, body } => {
+		ParseTree::Function { name, body, exported, comptime, attributes, doc } => {
 			let mut effect = StackEffect::none();
 			let mut typed_body: im::Vector<TypedTreeNode> = im::Vector::new();
 
 			for elem in body {
+				// The typed tree of elem, if it depends on the types on the stack - e.g. the concrete builtin and its instantiated stack
+				// effect if elem is a builtin or overloaded word, or the type accessed by a field access
+				let mut resolved = None;
+
 				let new_effect = match &elem.tree {
+					// The stack effect so far at the return is kept in the function's effect, to be checked where the function it ends is known
+					ParseTree::Identifier(ident) if ident == "return" => {
+						resolved = Some(TypedTree::Return);
+
+						StackEffect::new_return(elem.span)
+					}
 					ParseTree::Identifier(ident) => {
 						if ident.starts_with("__") {
 							if let Some(builtin) = builtins.get(ident) {
-								builtin.effect.clone()
+								let builtin_effect = brk!(quotation_effect(ident, builtin.effect.instantiate(&effect).unwrap_or(builtin.effect.clone()), elem.span));
+								brk!(check_copies(builtin, &builtin_effect, elem.span));
+								brk!(check_zeroes(builtin, &builtin_effect, elem.span));
+								resolved = Some(TypedTree::BuiltinWord { name: ident.clone(), effect: builtin_effect.clone() });
+								builtin_effect
 							} else {
-								return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: ident.clone() }, elem.cursor))
+								return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: ident.clone() }, elem.span))
 							}
-						} else {
-							let func_node = if let Some(func_node) = tles.get(ident) {
-								func_node
-							} else if parse_tree_tles.contains_key(ident) {
-								// If we don't know the effect of a used function (but it exists), return Unrecognised to skip evaluating this function for now
-								return Unrecognised;
-							} else {
-								// If that function doesn't exist, however, we error
-								return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: ident.to_string() }, elem.cursor));
-							};
+						} else if let Some(func_node) = tles.get(ident) {
+							match &func_node.tree {
+								// Calling a function by name catches any returns within it
+								TypedTree::Function { effect, .. } => {
+									brk!(check_returns(ident, effect));
+									effect.without_returns()
+								}
+								TypedTree::Extern { effect, .. } => effect.clone(),
+								// Constants are replaced with the value their const fn was evaluated to
+								TypedTree::Constant { name: _, ty, value, doc: _ } => {
+									resolved = Some(TypedTree::Literal { ty: ty.clone(), value: value.clone() });
 
-							let ident_effect = match &func_node.tree {
-								TypedTree::Function { effect, .. } => effect,
-								TypedTree::Type(ty) => return WithErr(AnalysisError::new(AnalysisErrorKind::TypeIsNotFunction { tname: ty.name() }, elem.cursor)),
+									StackEffect::new_pushed(im::vector![ty.clone()])
+								}
+								TypedTree::Type { ty, .. } => return WithErr(AnalysisError::new(AnalysisErrorKind::TypeIsNotFunction { tname: ty.name() }, elem.span)),
+								TypedTree::Invalid => return WithErr(AnalysisError::new(AnalysisErrorKind::Invalid, elem.span)),
 								_ => unreachable!()
-							};
-
-							ident_effect.clone()
+							}
+						} else if parse_tree_tles.contains_key(ident) {
+							// If we don't know the effect of a used function (but it exists), return Unrecognised to skip evaluating this function for now
+							return Unrecognised;
+						} else {
+							match resolve_overload(ident, &effect, builtins, elem.span) {
+								Valid((builtin_name, builtin_effect)) => {
+									let builtin_effect = brk!(quotation_effect(&builtin_name, builtin_effect, elem.span));
+									brk!(check_copies(&builtins[&builtin_name], &builtin_effect, elem.span));
+									brk!(check_zeroes(&builtins[&builtin_name], &builtin_effect, elem.span));
+									resolved = Some(TypedTree::BuiltinWord { name: builtin_name, effect: builtin_effect.clone() });
+									builtin_effect
+								}
+								WithErr(e) => return WithErr(e),
+								// If that function doesn't exist, however, we error
+								Unrecognised => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: ident.to_string() }, elem.span))
+							}
 						}
 					},
 					ParseTree::Literal(literal) => {
+						brk!(check_literal_supported(literal, elem.span));
+
 						match literal {
 							Literal::FnPtr(fn_name) => match tles.get(fn_name) {
 								Some(f) => {
 									match &f.tree {
-										TypedTree::Function { name: _, effect, body: _ } => effect.clone(),
-										_ => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.cursor))
+										// Function references push a reference to the function, rather than calling it
+										TypedTree::Function { name: _, effect, body: _, exported: _, attributes: _, doc: _ } | TypedTree::Extern { name: _, library: _, effect } => StackEffect::new_pushed(im::vector![Type::new_fnref(fn_name.clone(), effect.clone())]),
+										TypedTree::Invalid => return WithErr(AnalysisError::new(AnalysisErrorKind::Invalid, elem.span)),
+										_ => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.span))
 									}
 								},
 								None => {
 									if let Some(_) = parse_tree_tles.get(fn_name) {
 										return Unrecognised; // Skip this literal for now until we know the function's stack effect
 									} else {
-										return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.cursor))
+										return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.span))
 									}
 								}
 							},
+							Literal::Array(elems) => {
+								brk!(check_array_literal(elems, elem.span));
+
+								StackEffect::from_lit(literal).expect("Expected StackEffect::from_lit to produce stack effect")
+							}
 							_ => StackEffect::from_lit(literal).expect("Expected Value::from_lit to produce value")
 						}
 					},
 					ParseTree::Constructor(ident) => { // FIXME: Code duplication - this and the outer match ParseTree::Constructor case
-						let ctype = {
-							if let Some(ty) = Type::from_name(ident) {
-								ty
-							} else {
-								if let Some(type_node) = tles.get(ident) {
-									match &type_node.tree {
-										TypedTree::Type(ty) => ty.clone(),
-										_ => unreachable!()
-									}
-								} else if parse_tree_tles.contains_key(ident) {
-									// If we don't know the type of a used type name (but it exists), return Unrecognised to skip evaluating this type for now
-									return Unrecognised;
-								} else {
-									return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchType { tname: ident.to_string() }, elem.cursor))
-								}
-							}
-						};
+						let ctype = brk!(resolve_type(ident, tles, parse_tree_tles, elem.span));
 
 						let effect = match &ctype {
 							Type::Transparent { name: _, fields, sum_type } => { // TODO: Handle sum types (enums)
 								StackEffect::new_constructor(ctype.clone(), fields)
 							}
-							_ => return WithErr(AnalysisError::new(AnalysisErrorKind::UnconstructableType { tname: ctype.name() }, elem.cursor))
+							// Heap types are constructed empty
+							Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String | OpaqueTypeKind::Map(_, _) } => StackEffect::new_pushed(im::vector![ctype.clone()]),
+							_ => return WithErr(AnalysisError::new(AnalysisErrorKind::UnconstructableType { tname: ctype.name() }, elem.span))
 						};
 
 						effect
 					}
 					ParseTree::FieldAccess(field_name) => {
 						let (struct_ty, field_ty) = if let Some(top_type) = effect.last_pushed() {
-							match top_type {
+							// Fields can also be accessed through a reference to a struct
+							match top_type.deref() {
 								struct_ty @ Type::Transparent { name: _, fields, sum_type } => {
 									if !sum_type {
 										if let Some(field_ty) = fields.get(field_name) {
-											(struct_ty, field_ty)
+											// Field accesses leave the struct on the stack, so copy the field
+											if !field_ty.is_copyable() {
+												return WithErr(AnalysisError::new(AnalysisErrorKind::CannotCopyOwned { ty: field_ty.clone() }, elem.span));
+											}
+
+											(top_type, field_ty)
 										} else {
-											return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchField { ty: struct_ty.clone(), fname: field_name.clone() }, elem.cursor));
+											return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchField { ty: struct_ty.clone(), fname: field_name.clone() }, elem.span));
 										}
 									} else {
-										return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchField { ty: struct_ty.clone(), fname: field_name.clone() }, elem.cursor));
+										return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchField { ty: struct_ty.clone(), fname: field_name.clone() }, elem.span));
 									}
 								}
-								ty => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchField { ty: ty.clone(), fname: field_name.clone() }, elem.cursor))
+								ty => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchField { ty: ty.clone(), fname: field_name.clone() }, elem.span))
 							}
 						} else {
 							// TODO: When we have functions with declared stack effects, we can handle this more intelligently
-							return WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, elem.cursor))
+							return WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, elem.span))
 						};
 
+						resolved = Some(TypedTree::FieldAccess { name: field_name.clone(), of: struct_ty.clone() });
+
 						StackEffect::new_field_access(struct_ty.clone(), field_ty.clone())
 					}
+					ParseTree::Invalid => return WithErr(AnalysisError::new(AnalysisErrorKind::Invalid, elem.span)),
 					_ => unreachable!()
 				};
 
-				typed_body.push_back(brk!(calc_stack_effects(elem, tles, parse_tree_tles, builtins)));
+				typed_body.push_back(match resolved {
+					Some(tree) => tree.wrap(elem.span),
+					None => brk!(calc_stack_effects(elem, tles, parse_tree_tles, builtins))
+				});
 
-				effect = match effect.combine(&new_effect, elem.cursor) {
+				effect = match effect.combine(&new_effect, elem.span) {
 					Ok(effect) => effect,
 					Err(e) => return WithErr(e)
 				};
 			}
 
-			TypedTree::Function { name: name.to_string(), effect, body: typed_body }
+			// Functions run other than by a call by name catch their own returns - main and exported functions, which are called by the
+			// program's entry point or from C, and const fns, which are evaluated during analysis
+			if name == "main" || *exported || *comptime {
+				brk!(check_returns(name, &effect));
+				effect = effect.without_returns();
+			}
+
+			// Exported functions are called from C, so their arguments and result have to be representable in C
+			if *exported {
+				// The C-ABI wrapper of main would clash with the tower_main function the compiled program's entry point calls
+				if name == "main" {
+					return WithErr(AnalysisError::new(AnalysisErrorKind::CannotExportMain, parse_tree.span));
+				}
+
+				// The wrapper is named after the function, so symbolic words like + can't be exported
+				if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+					return WithErr(AnalysisError::new(AnalysisErrorKind::InvalidExportName { fname: name.clone() }, parse_tree.span));
+				}
+
+				for ty in effect.popped().iter().chain(effect.pushed()) {
+					brk!(check_c_type(ty, parse_tree.span));
+				}
+
+				if effect.pushed().len() > 1 {
+					return WithErr(AnalysisError::new(AnalysisErrorKind::MultipleCReturns { fname: name.clone() }, parse_tree.span));
+				}
+			}
+
+			let function = TypedTree::Function { name: name.to_string(), effect: effect.clone(), body: typed_body, exported: *exported, attributes: attributes.clone(), doc: doc.clone() };
+
+			if *comptime {
+				brk!(evaluate_const_fn(name, &effect, &function.wrap(parse_tree.span), tles, builtins))
+			} else {
+				function
+			}
 		},
-		ParseTree::Struct { name, fields } => {
+		ParseTree::Struct { name, fields, attributes: _, doc, field_docs } => {
 			let mut typed_fields = im::OrdMap::new();
 
 			for (fname, ftype) in fields {
-				let typed_ftype = {
-					if let Some(ty) = Type::from_name(ftype) {
-						ty
-					} else {
-						if let Some(type_node) = tles.get(ftype) {
-							match &type_node.tree {
-								TypedTree::Type(ty) => ty.clone(),
-								_ => unreachable!()
-							}
-						} else if parse_tree_tles.contains_key(ftype) {
-							// If we don't know the type of a used type name (but it exists), return Unrecognised to skip evaluating this type for now
-							return Unrecognised;
-						} else {
-							return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchType { tname: ftype.to_string() }, parse_tree.cursor))
-						}
-					}
-				};
+				let typed_ftype = brk!(resolve_type(&ftype.value, tles, parse_tree_tles, ftype.span));
 
 				typed_fields.insert(fname.to_string(), typed_ftype);
 			}
 
-			TypedTree::Type(Type::new_struct(name.to_string(), &typed_fields))
+			TypedTree::Type { ty: Type::new_struct(name.to_string(), &typed_fields), doc: doc.clone(), field_docs: field_docs.clone() }
 		},
 		ParseTree::Enum { name, fields } => todo!(), // TODO
+		ParseTree::StaticAssert { message, body } => {
+			// The assertion is analysed like a function pushing a bool, then evaluated like a const fn
+			let assertion = ParseTree::Function { name: "static_assert".to_string(), body: body.clone(), exported: false, comptime: false, attributes: im::Vector::new(), doc: None }.wrap(parse_tree.span);
+			let func = brk!(calc_stack_effects(&assertion, tles, parse_tree_tles, builtins));
+
+			if let TypedTree::Function { name: _, effect, body: _, exported: _, attributes: _, doc: _ } = &func.tree {
+				// The assertion is evaluated directly, so catches its own returns
+				brk!(check_returns("static_assert", effect));
+
+				if effect.without_returns() != StackEffect::new_pushed(im::vector![Type::new_bool()]) {
+					return WithErr(AnalysisError::new(AnalysisErrorKind::InvalidStaticAssertEffect { effect: effect.without_returns() }, parse_tree.span));
+				}
+			}
+
+			let held = brk!(evaluate("static_assert", &func, tles, builtins));
+			if held.as_bytes() != Some(&[1]) {
+				return WithErr(AnalysisError::new(AnalysisErrorKind::StaticAssertFailed { message: message.clone() }, parse_tree.span));
+			}
+
+			TypedTree::StaticAssert { message: message.clone() }
+		},
+		ParseTree::Extern { name, library, params, returns } => {
+			if returns.len() > 1 {
+				return WithErr(AnalysisError::new(AnalysisErrorKind::MultipleCReturns { fname: name.clone() }, returns[1].span));
+			}
+
+			let mut popped = im::Vector::new();
+			let mut pushed = im::Vector::new();
+
+			// The last parameter is on top of the stack, so is popped first
+			for tname in params.iter().rev() {
+				popped.push_back(brk!(resolve_extern_type(&tname.value, tles, parse_tree_tles, tname.span)));
+			}
+			for tname in returns {
+				pushed.push_back(brk!(resolve_extern_type(&tname.value, tles, parse_tree_tles, tname.span)));
+			}
+
+			TypedTree::Extern { name: name.clone(), library: library.clone(), effect: StackEffect::new(popped, pushed) }
+		},
+		ParseTree::Cfg { condition: _, name, item: _ } => return WithErr(AnalysisError::new(AnalysisErrorKind::UnappliedCfg { name: name.clone() }, parse_tree.span)),
 		ParseTree::Identifier(s) => {
 			if s.starts_with("__") {
-				TypedTree::BuiltinWord(s.clone())
+				match builtins.get(s) {
+					Some(builtin) => TypedTree::BuiltinWord { name: s.clone(), effect: builtin.effect.clone() },
+					None => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: s.clone() }, parse_tree.span))
+				}
 			} else {
 				TypedTree::Word(s.clone())
 			}
 		},
 		ParseTree::Literal(literal) => {
+			brk!(check_literal_supported(literal, parse_tree.span));
+
 			let (ty, value) = match literal {
 				Literal::FnPtr(fn_name) => match tles.get(fn_name) {
 					Some(f) => {
 						match &f.tree {
-							TypedTree::Function { name: _, effect, body: _ } => (Type::new_fnref(fn_name.clone(), effect.clone()), Value::new_fn(fn_name.clone(), effect.clone())),
-							_ => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.cursor))
+							TypedTree::Function { name: _, effect, body: _, exported: _, attributes: _, doc: _ } | TypedTree::Extern { name: _, library: _, effect } => (Type::new_fnref(fn_name.clone(), effect.clone()), Value::new_fn(fn_name.clone(), effect.clone())),
+							TypedTree::Invalid => return WithErr(AnalysisError::new(AnalysisErrorKind::Invalid, parse_tree.span)),
+							_ => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.span))
 						}
 					},
 					None => {
 						if let Some(_) = parse_tree_tles.get(fn_name) {
 							return Unrecognised; // Skip this literal for now until we know the function's stack effect
 						} else {
-							return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.cursor))
+							return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchFunction { fname: fn_name.clone() }, parse_tree.span))
 						}
 					}
 				},
@@ -407,43 +515,384 @@ fn calc_stack_effects(parse_tree: &ParseTreeNode, tles: &im::OrdMap<String, Type
 			TypedTree::Literal { ty, value }
 		},
 		ParseTree::Constructor(ident) => {
-			let ctype = {
-				if let Some(ty) = Type::from_name(ident) {
-					ty
-				} else {
-					if let Some(type_node) = tles.get(ident) {
-						match &type_node.tree {
-							TypedTree::Type(ty) => ty.clone(),
-							_ => unreachable!()
-						}
-					} else if parse_tree_tles.contains_key(ident) {
-						// If we don't know the type of a used type name (but it exists), return Unrecognised to skip evaluating this type for now
-						return Unrecognised;
-					} else {
-						return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchType { tname: ident.to_string() }, parse_tree.cursor))
-					}
-				}
-			};
+			let ctype = brk!(resolve_type(ident, tles, parse_tree_tles, parse_tree.span));
 
 			let effect = match &ctype {
 				Type::Transparent { name: _, fields, sum_type } => { // TODO: Handle sum types (enums)
 					StackEffect::new_constructor(ctype.clone(), fields)
 				}
-				_ => return WithErr(AnalysisError::new(AnalysisErrorKind::UnconstructableType { tname: ctype.name() }, parse_tree.cursor))
+				Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String | OpaqueTypeKind::Map(_, _) } => StackEffect::new_pushed(im::vector![ctype.clone()]),
+				_ => return WithErr(AnalysisError::new(AnalysisErrorKind::UnconstructableType { tname: ctype.name() }, parse_tree.span))
 			};
 
 			TypedTree::Constructor { ty: ctype, effect }
 		}
-		ParseTree::FieldAccess(field_name) => { // NOTE: We kinda need context to work out what type this field access operates on. Do we even want that at this point?
-			TypedTree::FieldAccess { name: field_name.clone() }
+		ParseTree::FieldAccess(_) => {
+			// Field accesses need the type on top of the stack, so are only analysed as part of a function body
+			return WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, parse_tree.span));
 		},
+		ParseTree::Invalid => return WithErr(AnalysisError::new(AnalysisErrorKind::Invalid, parse_tree.span))
 	};
 
 	Valid(
-		tree.wrap(parse_tree.file_path.to_string(), parse_tree.cursor)
+		tree.wrap(parse_tree.span)
 	)
 }
 
+/// Runs the analysed const fn func, named name and with stack effect effect, with the interpreter, returning the constant holding the
+/// value it pushes. tles are the top-level elements analysed so far, which include every function func calls
+fn evaluate_const_fn(name: &str, effect: &StackEffect, func: &TypedTreeNode, tles: &im::OrdMap<String, TypedTreeNode>, builtins: &im::OrdMap<String, BuiltinWord>) -> AnalysisResult<TypedTree> {
+	if !effect.popped().is_empty() || effect.pushed().len() != 1 {
+		return WithErr(AnalysisError::new(AnalysisErrorKind::InvalidConstFnEffect { fname: name.to_string(), effect: effect.clone() }, func.span));
+	}
+
+	let value = brk!(evaluate(&format!("const fn {name}"), func, tles, builtins));
+
+	if !value.is_literal() {
+		return WithErr(AnalysisError::new(AnalysisErrorKind::NonLiteralConstant { fname: name.to_string(), ty: effect.pushed()[0].clone() }, func.span));
+	}
+
+	// The constant keeps the doc comments of its const fn
+	let doc = match &func.tree {
+		TypedTree::Function { doc, .. } => doc.clone(),
+		_ => None
+	};
+
+	Valid(TypedTree::Constant { name: name.to_string(), ty: effect.pushed()[0].clone(), value, doc })
+}
+
+/// Runs the analysed function func with the interpreter, returning the value it leaves on top of the stack. what describes func in
+/// errors, e.g. `const fn table`
+fn evaluate(what: &str, func: &TypedTreeNode, tles: &im::OrdMap<String, TypedTreeNode>, builtins: &im::OrdMap<String, BuiltinWord>) -> AnalysisResult<Value> {
+	brk!(check_pure(what, func, tles, builtins, &mut Vec::new()));
+
+	match interpreter::interp_fn(func, tles, builtins) {
+		Ok(mut stack) => Valid(stack.pop().expect("Expected evaluated function to push a value")),
+		Err(err) => WithErr(AnalysisError::new(AnalysisErrorKind::EvaluationFailed { what: what.to_string(), err: err.to_string() }, err.span()))
+	}
+}
+
+/// Checks that running the function func, as what is evaluated, can't do I/O or call C functions, which would happen during analysis.
+/// This includes the functions it calls and those it references, which it may call as quotations. checked holds the names of the
+/// functions already checked
+fn check_pure(what: &str, func: &TypedTreeNode, tles: &im::OrdMap<String, TypedTreeNode>, builtins: &im::OrdMap<String, BuiltinWord>, checked: &mut Vec<String>) -> AnalysisResult<()> {
+	let body = match &func.tree {
+		TypedTree::Function { body, .. } => body,
+		_ => return Valid(())
+	};
+
+	for node in body {
+		let mut called = Vec::new();
+
+		match &node.tree {
+			TypedTree::BuiltinWord { name, effect: _ } if builtins.get(name).is_some_and(|builtin| builtin.impure) => {
+				let word = builtins[name].overloads.clone().unwrap_or(name.clone());
+				return WithErr(AnalysisError::new(AnalysisErrorKind::ImpureEvaluation { what: what.to_string(), word }, node.span));
+			}
+			TypedTree::Word(name) => called.push(name.clone()),
+			TypedTree::Literal { ty: _, value } => value.fn_refs(&mut called),
+			_ => ()
+		}
+
+		for name in called {
+			if checked.contains(&name) {
+				continue;
+			}
+			checked.push(name.clone());
+
+			match tles.get(&name) {
+				Some(TypedTreeNode { span: _, tree: TypedTree::Extern { .. } }) => {
+					return WithErr(AnalysisError::new(AnalysisErrorKind::ImpureEvaluation { what: what.to_string(), word: name }, node.span));
+				}
+				Some(callee) => brk!(check_pure(what, callee, tles, builtins, checked)),
+				None => ()
+			}
+		}
+	}
+
+	Valid(())
+}
+
+/// Resolves the type named tname (as produced by the parser, e.g. `u32`, `&Point`, `[u8; 4]`), looking up user-defined types in tles.
+/// Returns Unrecognised if tname names a type that exists but has not been analysed yet
+fn resolve_type(tname: &str, tles: &im::OrdMap<String, TypedTreeNode>, parse_tree_tles: &im::OrdMap<String, ParseTreeNode>, span: Span) -> AnalysisResult<Type> {
+	if let Some(inner) = tname.strip_prefix("&mut ") {
+		return resolve_type(inner, tles, parse_tree_tles, span).map(Type::new_mut_ref);
+	}
+
+	if let Some(inner) = tname.strip_prefix('&') {
+		return resolve_type(inner, tles, parse_tree_tles, span).map(Type::new_ref);
+	}
+
+	if let Some((elem, len)) = tname.strip_prefix('[').and_then(|t| t.strip_suffix(']')).and_then(|t| t.rsplit_once(';')) {
+		let len = match len.trim().parse() {
+			Ok(len) => len,
+			Err(_) => return WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchType { tname: tname.to_string() }, span))
+		};
+
+		return resolve_type(elem.trim(), tles, parse_tree_tles, span).map(|ty| Type::new_array(ty, Some(len)));
+	}
+
+	if let Some((name, params)) = tname.strip_suffix('>').and_then(|t| t.split_once('<')) {
+		let mut param_types = Vec::new();
+		for param in split_type_params(params) {
+			param_types.push(brk!(resolve_type(param, tles, parse_tree_tles, span)));
+		}
+
+		return match (name, param_types.as_slice()) {
+			("Vec", [elem]) => Valid(Type::new_vec(elem.clone())),
+			("Map", [key, _]) if !key.is_hashable() => WithErr(AnalysisError::new(AnalysisErrorKind::UnhashableType { ty: key.clone() }, span)),
+			("Map", [key, value]) => Valid(Type::new_map(key.clone(), value.clone())),
+			_ => WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchType { tname: tname.to_string() }, span))
+		};
+	}
+
+	if let Some(ty) = Type::from_name(tname) {
+		Valid(ty)
+	} else if let Some(type_node) = tles.get(tname) {
+		match &type_node.tree {
+			TypedTree::Type { ty, .. } => Valid(ty.clone()),
+			TypedTree::Invalid => WithErr(AnalysisError::new(AnalysisErrorKind::Invalid, span)),
+			_ => WithErr(AnalysisError::new(AnalysisErrorKind::FunctionIsNotType { fname: tname.to_string() }, span))
+		}
+	} else if parse_tree_tles.contains_key(tname) {
+		// If we don't know the type of a used type name (but it exists), return Unrecognised to skip evaluating this type for now
+		Unrecognised
+	} else {
+		WithErr(AnalysisError::new(AnalysisErrorKind::NoSuchType { tname: tname.to_string() }, span))
+	}
+}
+
+/// Resolves the type named tname used in the signature of an extern function, returning an error if it has no C equivalent
+fn resolve_extern_type(tname: &str, tles: &im::OrdMap<String, TypedTreeNode>, parse_tree_tles: &im::OrdMap<String, ParseTreeNode>, span: Span) -> AnalysisResult<Type> {
+	let ty = brk!(resolve_type(tname, tles, parse_tree_tles, span));
+
+	brk!(check_c_type(&ty, span));
+
+	Valid(ty)
+}
+
+/// Checks that values of the type can be passed to and returned from C functions, returning an error located at span if not.
+/// Integers and bools are passed as C integers of the same size, and `&str`s as null-terminated `const char*`s
+fn check_c_type(ty: &Type, span: Span) -> AnalysisResult<()> {
+	match ty {
+		Type::Opaque { size: Some(size), kind: OpaqueTypeKind::UnsignedInt | OpaqueTypeKind::SignedInt } if *size <= 8 => Valid(()),
+		Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => Valid(()),
+		Type::Reference { to, mutable: false } if matches!(to.as_ref(), Type::Opaque { size: _, kind: OpaqueTypeKind::Str }) => Valid(()),
+		_ => WithErr(AnalysisError::new(AnalysisErrorKind::UnsupportedCType { ty: ty.clone() }, span))
+	}
+}
+
+/// Splits the comma-separated type parameters of a generic type name, e.g. `u32, Vec<u8>`, ignoring commas nested in other type names
+fn split_type_params(params: &str) -> Vec<&str> {
+	let mut split = Vec::new();
+	let mut depth = 0;
+	let mut start = 0;
+
+	for (i, c) in params.char_indices() {
+		match c {
+			'<' | '[' => depth += 1,
+			'>' | ']' => depth -= 1,
+			',' if depth == 0 => {
+				split.push(params[start..i].trim());
+				start = i + 1;
+			}
+			_ => ()
+		}
+	}
+	split.push(params[start..].trim());
+
+	split
+}
+
+/// Checks that each return within the function fname, with stack effect effect, leaves the stack as the function does when it ends
+/// normally, as a call to it by name ends at them. Words after a return are never run, so must leave the stack as it was at the return
+fn check_returns(fname: &str, effect: &StackEffect) -> AnalysisResult<()> {
+	for (ret, span) in effect.returns() {
+		if !effect.without_returns().is_returned_by(ret) {
+			return WithErr(AnalysisError::new(AnalysisErrorKind::MismatchedReturn { fname: fname.to_string(), expected: effect.without_returns(), found: ret.clone() }, *span));
+		}
+	}
+
+	Valid(())
+}
+
+/// Checks that the literal, and every literal within it, is of a kind that can be analysed and run, returning an error located at span
+/// naming the first that isn't
+fn check_literal_supported(literal: &Literal, span: Span) -> AnalysisResult<()> {
+	match literal {
+		Literal::F64(_) | Literal::F32(_) => WithErr(AnalysisError::new(AnalysisErrorKind::UnsupportedLiteral { literal: literal.clone() }, span)),
+		Literal::Array(elems) => {
+			for elem in elems {
+				brk!(check_literal_supported(elem, span));
+			}
+
+			Valid(())
+		}
+		_ => Valid(())
+	}
+}
+
+/// Checks that the elements of an array literal are all of the same type, returning an error located at span if not
+fn check_array_literal(elems: &[Literal], span: Span) -> AnalysisResult<()> {
+	let elem_ty = match elems.first() {
+		Some(first) => match Type::from_lit(first) {
+			Some(ty) => ty,
+			None => return WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, span))
+		},
+		None => return WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, span))
+	};
+
+	for elem in elems {
+		if let Literal::Array(inner) = elem {
+			brk!(check_array_literal(inner, span));
+		}
+
+		match Type::from_lit(elem) {
+			Some(ty) if ty == elem_ty => (),
+			Some(ty) => return WithErr(AnalysisError::new(AnalysisErrorKind::IncompatibleTypes { source: ty, dest: elem_ty }, span)),
+			None => return WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, span))
+		}
+	}
+
+	Valid(())
+}
+
+/// Picks the builtin implementing the overloaded word `word` that accepts the types on top of the stack left by `effect`, returning
+/// its name and stack effect. Returns Unrecognised if `word` is not an overloaded builtin word
+fn resolve_overload(word: &str, effect: &StackEffect, builtins: &im::OrdMap<String, BuiltinWord>, span: Span) -> AnalysisResult<(String, StackEffect)> {
+	let mut candidates = builtins.iter().filter(|(_, builtin)| builtin.overloads.as_deref() == Some(word)).peekable();
+
+	if candidates.peek().is_none() {
+		return Unrecognised;
+	}
+
+	// Unsized values, e.g. a str behind a &str, can't be pushed onto the stack
+	match candidates.find_map(|(builtin_name, builtin)| Some((builtin_name, builtin.effect.instantiate(effect).filter(|effect| effect.pushed().iter().all(Type::is_sized))?))) {
+		Some((builtin_name, builtin_effect)) => Valid((builtin_name.clone(), builtin_effect)),
+		None => WithErr(AnalysisError::new(AnalysisErrorKind::NoMatchingOverload { word: word.to_string(), stack: effect.pushed().iter().rev().cloned().collect() }, span))
+	}
+}
+
+/// Works out the stack effect of the quotation builtins `__call`, `__curry`, `__compose` and `__try` from the stack effects of the
+/// quotations they are instantiated with in effect, which can't be expressed with generics. The effects of other builtins are returned
+/// unchanged
+fn quotation_effect(builtin_name: &str, effect: StackEffect, span: Span) -> AnalysisResult<StackEffect> {
+	let word = match builtin_name {
+		"__call" => "call",
+		"__curry" => "curry",
+		"__compose" => "compose",
+		"__try" => "try",
+		_ => return Valid(effect)
+	};
+
+	let quotation = |ty: &Type| match ty {
+		Type::Generic { name: _ } => WithErr(AnalysisError::new(AnalysisErrorKind::CannotInferType, span)),
+		ty => match ty.as_quotation() {
+			Some(quotation_effect) => Valid(quotation_effect.clone()),
+			None => WithErr(AnalysisError::new(AnalysisErrorKind::NotAQuotation { word: word.to_string(), ty: ty.clone() }, span))
+		}
+	};
+
+	let quotation_ty = &effect.popped()[0];
+	let quotation_effect = brk!(quotation(quotation_ty));
+
+	match word {
+		// Calls the quotation on top of the stack, which then pops its arguments from beneath it
+		"call" => match StackEffect::new_popped(im::vector![quotation_ty.clone()]).combine(&quotation_effect, span) {
+			Ok(call_effect) => Valid(call_effect),
+			Err(e) => WithErr(e)
+		},
+		// Captures the value beneath the quotation as the quotation's first argument
+		"curry" => {
+			let value_ty = &effect.popped()[1];
+
+			let mut popped = quotation_effect.popped().clone();
+			match popped.pop_front() {
+				Some(arg_ty) if value_ty.coerces_to(&arg_ty) => (),
+				Some(arg_ty) => return WithErr(AnalysisError::new(AnalysisErrorKind::IncompatibleTypes { source: value_ty.clone(), dest: arg_ty }, span)),
+				None => return WithErr(AnalysisError::new(AnalysisErrorKind::NothingToCurry { ty: quotation_ty.clone() }, span))
+			}
+
+			// The closure pushes the captured value before calling the quotation, so returns within the quotation happen after that
+			let closure_ty = match StackEffect::new_pushed(im::vector![value_ty.clone()]).combine(&quotation_effect, span) {
+				Ok(closure_effect) => Type::new_closure(closure_effect),
+				Err(e) => return WithErr(e)
+			};
+
+			Valid(StackEffect::new(im::vector![quotation_ty.clone(), value_ty.clone()], im::vector![closure_ty]))
+		}
+		// Calls the quotation beneath the top one, then the top one
+		"compose" => {
+			let first_ty = &effect.popped()[1];
+			let first_effect = brk!(quotation(first_ty));
+
+			let closure_ty = match first_effect.combine(&quotation_effect, span) {
+				Ok(composed_effect) => Type::new_closure(composed_effect),
+				Err(e) => return WithErr(e)
+			};
+
+			Valid(StackEffect::new(im::vector![quotation_ty.clone(), first_ty.clone()], im::vector![closure_ty]))
+		}
+		// Calls the body quotation beneath the handler quotation. If the body throws, the stack is restored to how it was before the
+		// body was called, so the handler takes the body's arguments and the thrown String, and has to leave the same values as the body
+		"try" => {
+			let body_ty = &effect.popped()[1];
+			let body_effect = brk!(quotation(body_ty));
+
+			let mut handler_popped = body_effect.popped().clone();
+			handler_popped.push_front(Type::new_string());
+			let expected_handler_effect = StackEffect::new(handler_popped, body_effect.pushed().clone());
+
+			if quotation_effect.without_returns() != expected_handler_effect {
+				return WithErr(AnalysisError::new(AnalysisErrorKind::MismatchedHandler { body: body_effect, handler: quotation_effect }, span));
+			}
+
+			// Returns within either quotation unwind through the try. The handler is called on the body's arguments and the String
+			let try_effect = match StackEffect::new_popped(im::vector![quotation_ty.clone(), body_ty.clone()]).combine(&body_effect, span) {
+				Ok(try_effect) => try_effect,
+				Err(e) => return WithErr(e)
+			};
+			let mut handler_args: im::Vector<Type> = body_effect.popped().iter().rev().cloned().collect();
+			handler_args.push_back(Type::new_string());
+
+			match StackEffect::new(try_effect.popped().clone(), handler_args).combine(&quotation_effect, span) {
+				Ok(handler_effect) => Valid(try_effect.with_returns_of(&handler_effect)),
+				Err(e) => WithErr(e)
+			}
+		}
+		_ => unreachable!()
+	}
+}
+
+/// Checks that the builtin, instantiated with effect, does not copy any owned values, returning an error located at span if it does.
+/// A builtin marked as copying copies a type if it pushes more values of the type than it pops
+fn check_copies(builtin: &BuiltinWord, effect: &StackEffect, span: Span) -> AnalysisResult<()> {
+	if !builtin.copies {
+		return Valid(());
+	}
+
+	let count = |types: &im::Vector<Type>, ty: &Type| types.iter().filter(|t| *t == ty).count();
+
+	match effect.pushed().iter().find(|ty| !ty.is_copyable() && count(effect.pushed(), ty) > count(effect.popped(), ty)) {
+		Some(ty) => WithErr(AnalysisError::new(AnalysisErrorKind::CannotCopyOwned { ty: ty.clone() }, span)),
+		None => Valid(())
+	}
+}
+
+/// Checks that the builtin, instantiated with effect, is only used with maps whose value type has a zero value if it pushes one for
+/// a missing key, returning an error located at span if it isn't
+fn check_zeroes(builtin: &BuiltinWord, effect: &StackEffect, span: Span) -> AnalysisResult<()> {
+	if !builtin.zeroes {
+		return Valid(());
+	}
+
+	match effect.popped().iter().filter_map(Type::as_map).find(|(_, value_ty)| Value::zeroed(value_ty).is_none()) {
+		Some((_, value_ty)) => WithErr(AnalysisError::new(AnalysisErrorKind::NoZeroValue { ty: value_ty.clone() }, span)),
+		None => Valid(())
+	}
+}
+
 /// Performs semantic analysis
 pub fn analyse(parse_tree: &ParseTreeNode, builtins: &im::OrdMap<String, BuiltinWord>) -> AnalysisResult<TypedTreeNode> {
 	// TODO: ALSO need to do monomorphisation and figure out generics
@@ -454,6 +903,58 @@ pub fn analyse(parse_tree: &ParseTreeNode, builtins: &im::OrdMap<String, Builtin
 	typed_tree
 }
 
+/// Returns warnings about the uses of items with attributes in the module parse_tree - calls to or references of deprecated functions,
+/// constructions of deprecated structs, and results of must_use functions that are dropped straight after the call. The must_use check
+/// only looks at the word after the call, rather than following the result through the stack effects of the words after it
+pub fn warnings(parse_tree: &ParseTreeNode) -> Vec<AnalysisWarning> {
+	let elems = match &parse_tree.tree {
+		ParseTree::Module { name: _, elems } => elems,
+		_ => return Vec::new()
+	};
+
+	let attributes_of = |name: &str| match elems.get(name).map(|elem| &elem.tree) {
+		Some(ParseTree::Function { attributes, .. } | ParseTree::Struct { attributes, .. }) => Some(attributes),
+		_ => None
+	};
+
+	let mut warnings = Vec::new();
+
+	for (_, elem) in elems {
+		let body = match &elem.tree {
+			ParseTree::Function { body, .. } | ParseTree::StaticAssert { message: _, body } => body,
+			_ => continue
+		};
+
+		for (i, node) in body.iter().enumerate() {
+			let name = match &node.tree {
+				ParseTree::Identifier(name) | ParseTree::Constructor(name) | ParseTree::Literal(Literal::FnPtr(name)) => name,
+				_ => continue
+			};
+
+			for attribute in attributes_of(name).into_iter().flatten() {
+				match attribute {
+					Attribute::Deprecated(message) => {
+						warnings.push(AnalysisWarning::new(AnalysisWarningKind::Deprecated { name: name.clone(), message: message.clone() }, node.span));
+					}
+					Attribute::MustUse if matches!(node.tree, ParseTree::Identifier(_)) => {
+						let dropped = match body.get(i + 1).map(|next| &next.tree) {
+							Some(ParseTree::Identifier(next)) => next == "drop" || next.starts_with("__drop"),
+							_ => false
+						};
+
+						if dropped {
+							warnings.push(AnalysisWarning::new(AnalysisWarningKind::UnusedResult { fname: name.clone() }, body[i + 1].span));
+						}
+					}
+					_ => ()
+				}
+			}
+		}
+	}
+
+	warnings
+}
+
 // fn add_instructions(program: &mut OrdMap<String, AnnotatedASTNode>, effects: &mut OrdMap<NodeId, StackEffect>, node_id: &mut NodeId) {
 // 	let instructions: im::OrdMap<String, (Instruction, StackEffect)> = instructions();
 
@@ -461,4 +962,181 @@ pub fn analyse(parse_tree: &ParseTreeNode, builtins: &im::OrdMap<String, Builtin
 // 		program.insert(instruct_name.to_string(), AnnotatedASTNode::new(ASTNode::Instruction(instruct_body), node_id.inc()));
 // 		effects.insert(*node_id, instruct_effect);
 // 	}
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+	use crate::{interpreter::builtin::builtin_functions, parser::{self, result::ScanResult::{Unrecognised, Valid, WithErr}, source_map::SourceMap, tree::{Literal, Span}}};
+
+	use super::{analyse, check_literal_supported, tree::{TypedTree, TypedTreeNode}, value::Value, warnings};
+
+	/// Analyses the content, returning the message of the error it fails with, if any
+	fn analysis_error(content: &str) -> Option<String> {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tree, errors) = parser::parse(&sources, file);
+		assert!(errors.is_empty(), "Expected test content to parse");
+
+		match analyse(&tree, &builtin_functions()) {
+			Valid(_) => None,
+			WithErr(e) => Some(e.to_string()),
+			Unrecognised => panic!("Expected test content to be recognised")
+		}
+	}
+
+	#[test]
+	fn float_literals_are_rejected() {
+		let mut sources = SourceMap::new();
+		let span = Span::point(sources.add_file("test.tower", ""), 0);
+
+		let error = match check_literal_supported(&Literal::Array(vec![Literal::F64(1.5)]), span) {
+			WithErr(e) => e.to_string(),
+			_ => panic!("Expected float literal to be rejected")
+		};
+		assert_eq!(error, "float literal 1.5f64 is not supported, as floats are not yet implemented");
+		assert!(matches!(check_literal_supported(&Literal::Array(vec![Literal::U32(1)]), span), Valid(())));
+		assert!(!Value::from_lit(&Literal::F32(0.5)).expect("Expected float value").is_literal());
+	}
+
+	#[test]
+	fn return_matches_function_effect() {
+		assert_eq!(analysis_error("fn one { 1u return }\nfn keep { return 1u __add_u32 }\nfn main { one keep drop }\n"), None);
+		assert_eq!(analysis_error("fn one { 1u return 2u }\nfn main { one drop drop }\n"), Some("function one returns with stack effect ( -> u32 ), which does not match its stack effect ( -> u32, u32 )".to_string()));
+	}
+
+	#[test]
+	fn return_unwinds_through_quotations() {
+		let fns = "fn parse { \"bad\" to_string throw 5u }\nfn fallback { __string_len drop drop 7u return }\n";
+		assert_eq!(analysis_error(&format!("{fns}fn get {{ &parse &fallback try 1u add }}\nfn main {{ get drop }}\n")), None);
+		assert_eq!(analysis_error(&format!("{fns}fn early {{ \"e\" to_string &fallback curry call 3u __add_u32 }}\nfn main {{ early drop }}\n")), None);
+		// The return within fallback leaves one u32, but get ends with two
+		assert!(analysis_error(&format!("{fns}fn get {{ &parse &fallback try 1u }}\nfn main {{ get drop drop }}\n")).is_some_and(|e| e.starts_with("function get returns")));
+	}
+
+	#[test]
+	fn const_fn_literals() {
+		assert_eq!(analysis_error("struct P { x: u32 }\nconst fn p { 1u -> P }\nconst fn s { \"a\\0b\" }\nfn g { 1u }\nconst fn f { &g }\nfn main { p drop s drop f drop }\n"), None);
+		assert!(analysis_error("const fn m { -> Map<u32, u32> }\nfn main { m drop }\n").is_some_and(|e| e.contains("cannot be embedded")));
+	}
+
+	#[test]
+	fn evaluation_must_be_pure() {
+		assert_eq!(analysis_error("fn sq { 3u 3u __mul_u32 }\nconst fn nine { sq }\nstatic_assert { nine 9u __eq_u32 }\nfn main { nine drop }\n"), None);
+
+		let impure = |content: &str| analysis_error(content).unwrap_or_default();
+		assert!(impure("const fn one { 1u dup println }\nfn main { one drop }\n").starts_with("const fn one cannot use println"));
+		assert!(impure("fn hi { \"hi\" println }\nfn one { hi 1u }\nconst fn c { one }\nfn main { c drop }\n").starts_with("const fn c cannot use println"));
+		assert!(impure("static_assert { read_line __string_len drop drop 1u 1u __eq_u32 }\nfn main { }\n").starts_with("static_assert cannot use read_line"));
+
+		let ext = "extern \"libc.so.6\" fn abs (i32 -> i32)\n";
+		assert!(impure(&format!("{ext}const fn a {{ 1i abs }}\nfn main {{ a drop }}\n")).starts_with("const fn a cannot use abs"));
+		assert!(impure(&format!("{ext}const fn a {{ &abs }}\nfn main {{ a drop }}\n")).starts_with("const fn a cannot use abs"));
+	}
+
+	#[test]
+	fn attribute_warnings() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "@deprecated(\"use b\") fn a { 1u }\n@must_use fn b { 2u }\n@deprecated struct P { x: u32 }\nfn main { a b drop b __drop_u32 &a drop 3u -> P drop }\n");
+		let (tree, _) = parser::parse(&sources, file);
+
+		let warnings: Vec<String> = warnings(&tree).iter().map(|w| w.to_string()).collect();
+		assert_eq!(warnings, vec![
+			"use of deprecated item a: use b",
+			"result of function b is immediately dropped, but it is marked must_use",
+			"result of function b is immediately dropped, but it is marked must_use",
+			"use of deprecated item a: use b",
+			"use of deprecated item P"
+		]);
+	}
+
+	#[test]
+	fn invalid_items_are_placeholders() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "fn a { ( }\nfn b { 1u }\nfn main { b drop }\nfn c { a }\n");
+		let (tree, errors) = parser::parse(&sources, file);
+		assert_eq!(errors.len(), 1);
+
+		// The rest of the file is still analysed, while items using a placeholder become placeholders too, as their error was reported
+		let elems = match analyse(&tree, &builtin_functions()) {
+			Valid(TypedTreeNode { span: _, tree: TypedTree::Module { name: _, elems } }) => elems,
+			_ => panic!("Expected the rest of the file to analyse")
+		};
+		assert!(matches!(elems["b"].tree, TypedTree::Function { .. }));
+		assert!(matches!(elems["main"].tree, TypedTree::Function { .. }));
+		assert!(elems["a"].tree == TypedTree::Invalid && elems["c"].tree == TypedTree::Invalid);
+	}
+
+	#[test]
+	fn errors_underline_whole_word() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "fn main { 1u missing_word }\n");
+		let (tree, _) = parser::parse(&sources, file);
+
+		let error = match analyse(&tree, &builtin_functions()) {
+			WithErr(e) => e,
+			_ => panic!("Expected missing_word to be an error")
+		};
+
+		let mut out = Vec::new();
+		error.print_error(&sources, &mut out).unwrap();
+		assert!(String::from_utf8(out).unwrap().ends_with("  |              ^^^^^^^^^^^^\n"));
+	}
+
+	#[test]
+	fn overloads_need_known_types() {
+		assert_eq!(analysis_error("fn main { 1u 2u add \"hi\" println println }\n"), None);
+		assert_eq!(analysis_error("fn p { println }\nfn main { }\n"), Some("no implementation of println accepts the types on top of the stack: []".to_string()));
+		assert_eq!(analysis_error("fn main { 1u 2u8 add drop }\n"), Some("no implementation of add accepts the types on top of the stack: [u8, u32]".to_string()));
+	}
+
+	#[test]
+	fn only_mutable_references_are_stored_to() {
+		assert_eq!(analysis_error("fn main { 5u cell 7u store drop }\n"), None);
+		assert_eq!(analysis_error("fn main { 5u box 7u store drop }\n"), Some("no implementation of store accepts the types on top of the stack: [u32, &u32]".to_string()));
+	}
+
+	#[test]
+	fn owned_values_must_be_cloned() {
+		let copied = Some("value of owned type String cannot be implicitly copied - use clone to copy it explicitly".to_string());
+
+		assert_eq!(analysis_error("fn main { \"a\" to_string clone drop drop \"a\" dup drop drop }\n"), None);
+		assert_eq!(analysis_error("fn main { \"a\" to_string dup drop drop }\n"), copied);
+		assert_eq!(analysis_error("fn main { -> Vec<String> \"a\" to_string push 0u get drop drop }\n"), copied);
+	}
+
+	#[test]
+	fn cfg_items_must_be_applied() {
+		assert_eq!(analysis_error("@cfg(debug) fn f { }\nfn main { }\n"), Some("cfg item f must be enabled or removed with the backend's cfg options before analysis".to_string()));
+	}
+
+	#[test]
+	fn map_lookups_need_a_zero_value() {
+		let no_zero = Some("map value type &str has no zero value to push for a missing key".to_string());
+
+		assert_eq!(analysis_error("fn main { -> Map<u32, u32> 1u get drop drop 1u remove drop drop drop }\n"), None);
+		assert_eq!(analysis_error("fn main { -> Map<u32, &str> 1u \"a\" insert 1u contains drop drop }\n"), None);
+		assert_eq!(analysis_error("fn main { -> Map<u32, &str> 1u get drop drop drop }\n"), no_zero);
+		assert_eq!(analysis_error("fn main { -> Map<u32, &str> 1u __map_remove drop drop drop }\n"), no_zero);
+	}
+
+	#[test]
+	fn curry_needs_an_argument() {
+		assert_eq!(analysis_error("fn plus { __add_u32 }\nfn main { 1u &plus curry drop }\n"), None);
+		assert_eq!(analysis_error("fn one { 1u }\nfn main { 1u &one curry drop }\n"), Some("cannot curry a value into quotation of type &one ( -> u32 ), as it pops nothing".to_string()));
+	}
+
+	#[test]
+	fn try_handler_matches_body() {
+		let body = "fn b { 1u __add_u32 }\n";
+
+		assert_eq!(analysis_error(&format!("{body}fn h {{ __string_len drop drop 1u __add_u32 }}\nfn main {{ 1u &b &h try drop }}\n")), None);
+		assert_eq!(analysis_error(&format!("{body}fn h {{ __string_len drop drop }}\nfn main {{ 1u &b &h try drop }}\n")), Some("try handler of type ( String -> ) does not match body of type ( u32 -> u32 ) (the handler must pop the body's arguments and a String error, and push what the body pushes)".to_string()));
+	}
+
+	#[test]
+	fn static_assertions() {
+		assert_eq!(analysis_error("const fn answer { 42u }\nstatic_assert { answer 42u __eq_u32 }\nfn main { }\n"), None);
+		assert_eq!(analysis_error("static_assert \"bad\" { 1u 2u __eq_u32 }\nfn main { }\n"), Some("static assertion failed - bad".to_string()));
+		assert_eq!(analysis_error("static_assert { 1u 2u __eq_u32 }\nfn main { }\n"), Some("static assertion failed".to_string()));
+		assert_eq!(analysis_error("static_assert { 1u }\nfn main { }\n"), Some("static_assert must push exactly one bool, but has stack effect ( -> u32 )".to_string()));
+	}
+}
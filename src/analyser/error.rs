@@ -1,35 +1,40 @@
 use std::{fmt::Display, io::{self, Write}};
 
-use crate::parser::scanner::Scanner;
+use crate::parser::{source_map::SourceMap, tree::{Literal, Span}};
 
-use super::ttype::Type;
+use super::{stack_effect::StackEffect, ttype::Type};
 
 #[derive(Clone)]
 pub struct AnalysisError {
 	kind: AnalysisErrorKind,
-	cursor: usize,
+	span: Span,
 }
 
 impl AnalysisError {
 	// TODO: Perhaps take multiple cursors? Or think about how to handle multiple errors caught at the same time, such as recursive functions
-	pub fn new(kind: AnalysisErrorKind, cursor: usize) -> Self {
+	pub fn new(kind: AnalysisErrorKind, span: Span) -> Self {
 		AnalysisError {
 			kind,
-			cursor
+			span
 		}
 	}
 
-	/// Pretty-prints the error, including context retrieved from the scanner
-	pub fn print_error(&self, scanner: &Scanner, file_name: &str, mut writer: impl Write) -> Result<(), io::Error> {
+	/// Returns whether the error is caused by a syntax error, rather than being a problem in its own right
+	pub fn is_invalid(&self) -> bool {
+		matches!(self.kind, AnalysisErrorKind::Invalid)
+	}
+
+	/// Pretty-prints the error, including context retrieved from the file it is in
+	pub fn print_error(&self, sources: &SourceMap, mut writer: impl Write) -> Result<(), io::Error> {
 		// BUG: Alignment is off when there are multi-byte or multi code point characters such as ✨ in the context line before the cursor
 
-		let context = scanner.get_context(self.cursor);
-		let (col, row) = scanner.get_col_row(self.cursor);
+		let context = sources.get_context(self.span.file, self.span.start);
+		let (col, row) = sources.get_col_row(self.span.file, self.span.start);
 		let row_str = format!("{row}");
 		let num_tabs = context.chars().filter(|&c| c == '\t').count();
-		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".to_string() ].join("");
+		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".repeat(sources.get_underline_len(self.span)) ].join("");
 
-		writeln!(writer, "Analysis Error at {file_name}:{col}:{row} - {self}")?;
+		writeln!(writer, "Analysis Error at {}:{col}:{row} - {self}", sources.path(self.span.file))?;
 		writeln!(writer, "{} | ", " ".repeat(row_str.len()))?;
 		writeln!(writer, "{row} | {}", context.replace("\t", "    "))?;
 		writeln!(writer, "{cursor_indicator}")?;
@@ -38,6 +43,67 @@ impl AnalysisError {
 	}
 }
 
+/// A problem found during analysis which doesn't stop the program from being run
+#[derive(Clone, PartialEq)]
+pub struct AnalysisWarning {
+	kind: AnalysisWarningKind,
+	span: Span,
+}
+
+impl AnalysisWarning {
+	pub fn new(kind: AnalysisWarningKind, span: Span) -> Self {
+		AnalysisWarning {
+			kind,
+			span
+		}
+	}
+
+	/// Pretty-prints the warning, including context retrieved from the file it is in
+	pub fn print_warning(&self, sources: &SourceMap, mut writer: impl Write) -> Result<(), io::Error> {
+		let context = sources.get_context(self.span.file, self.span.start);
+		let (col, row) = sources.get_col_row(self.span.file, self.span.start);
+		let row_str = format!("{row}");
+		let num_tabs = context.chars().filter(|&c| c == '\t').count();
+		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".repeat(sources.get_underline_len(self.span)) ].join("");
+
+		writeln!(writer, "Warning at {}:{col}:{row} - {self}", sources.path(self.span.file))?;
+		writeln!(writer, "{} | ", " ".repeat(row_str.len()))?;
+		writeln!(writer, "{row} | {}", context.replace("\t", "    "))?;
+		writeln!(writer, "{cursor_indicator}")?;
+
+		Ok(())
+	}
+}
+
+impl Display for AnalysisWarning {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			AnalysisWarningKind::Deprecated { name, message: Some(message) } => {
+				write!(f, "use of deprecated item {name}: {message}")
+			}
+			AnalysisWarningKind::Deprecated { name, message: None } => {
+				write!(f, "use of deprecated item {name}")
+			}
+			AnalysisWarningKind::UnusedResult { fname } => {
+				write!(f, "result of function {fname} is immediately dropped, but it is marked must_use")
+			}
+		}
+	}
+}
+
+#[derive(Clone, PartialEq)]
+pub enum AnalysisWarningKind {
+	/// A deprecated function is called or referenced, or a deprecated struct is constructed
+	Deprecated {
+		name: String,
+		message: Option<String>
+	},
+	/// The result of a must_use function is dropped straight after it is called
+	UnusedResult {
+		fname: String
+	}
+}
+
 impl Display for AnalysisError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match &self.kind {
@@ -56,6 +122,12 @@ impl Display for AnalysisError {
 			AnalysisErrorKind::NoSuchType { tname } => {
 				write!(f, "type {tname} was not found in scope")
 			}
+			AnalysisErrorKind::CannotCopyOwned { ty } => {
+				write!(f, "value of owned type {ty} cannot be implicitly copied - use clone to copy it explicitly")
+			}
+			AnalysisErrorKind::UnhashableType { ty } => {
+				write!(f, "type {ty} cannot be used as a map key (only integers, bools, strings and structs or arrays of them can be)")
+			}
 			AnalysisErrorKind::UnconstructableType { tname } => {
 				write!(f, "type {tname} cannot be constructed (is not a struct or enum variant)")
 			}
@@ -65,9 +137,75 @@ impl Display for AnalysisError {
 			AnalysisErrorKind::CannotInferType => {
 				write!(f, "cannot infer type")
 			},
+			AnalysisErrorKind::NotAQuotation { word, ty } => {
+				write!(f, "{word} expects a quotation (function reference or closure), but found type {ty}")
+			}
+			AnalysisErrorKind::NothingToCurry { ty } => {
+				write!(f, "cannot curry a value into quotation of type {ty}, as it pops nothing")
+			}
+			AnalysisErrorKind::MismatchedHandler { body, handler } => {
+				write!(f, "try handler of type {handler} does not match body of type {body} (the handler must pop the body's arguments and a String error, and push what the body pushes)")
+			}
+			AnalysisErrorKind::MismatchedReturn { fname, expected, found } => {
+				write!(f, "function {fname} returns with stack effect {found}, which does not match its stack effect {expected}")
+			}
+			AnalysisErrorKind::InvalidConstFnEffect { fname, effect } => {
+				write!(f, "const fn {fname} must pop nothing and push exactly one value, but has stack effect {effect}")
+			}
+			AnalysisErrorKind::EvaluationFailed { what, err } => {
+				write!(f, "evaluating {what} failed - {err}")
+			}
+			AnalysisErrorKind::NoZeroValue { ty } => {
+				write!(f, "map value type {ty} has no zero value to push for a missing key")
+			}
+			AnalysisErrorKind::ImpureEvaluation { what, word } => {
+				write!(f, "{what} cannot use {word}, as it is evaluated during analysis, which can't do I/O or call C functions")
+			}
+			AnalysisErrorKind::InvalidStaticAssertEffect { effect } => {
+				write!(f, "static_assert must push exactly one bool, but has stack effect {effect}")
+			}
+			AnalysisErrorKind::StaticAssertFailed { message: Some(message) } => {
+				write!(f, "static assertion failed - {message}")
+			}
+			AnalysisErrorKind::StaticAssertFailed { message: None } => {
+				write!(f, "static assertion failed")
+			}
+			AnalysisErrorKind::NonLiteralConstant { fname, ty } => {
+				write!(f, "const fn {fname} produces a value of type {ty}, which cannot be embedded in the program (floats, enums, maps, closures and references other than &str cannot be)")
+			}
+			AnalysisErrorKind::UnsupportedLiteral { literal: Literal::F64(value) } => {
+				write!(f, "float literal {value}f64 is not supported, as floats are not yet implemented")
+			}
+			AnalysisErrorKind::UnsupportedLiteral { literal: Literal::F32(value) } => {
+				write!(f, "float literal {value}f32 is not supported, as floats are not yet implemented")
+			}
+			AnalysisErrorKind::UnsupportedLiteral { literal } => {
+				write!(f, "literal {literal:?} is not supported")
+			}
+			AnalysisErrorKind::UnsupportedCType { ty } => {
+				write!(f, "type {ty} cannot be passed to or returned from C functions (only integers up to 64 bits, bools and &str can be)")
+			}
+			AnalysisErrorKind::CannotExportMain => {
+				write!(f, "main cannot be exported, as it is the entry point of the compiled program")
+			}
+			AnalysisErrorKind::InvalidExportName { fname } => {
+				write!(f, "function {fname} cannot be exported, as its name is not a valid C identifier")
+			}
+			AnalysisErrorKind::MultipleCReturns { fname } => {
+				write!(f, "function {fname} is called from or calls C, so cannot return more than one value")
+			}
+			AnalysisErrorKind::NoMatchingOverload { word, stack } => {
+				write!(f, "no implementation of {word} accepts the types on top of the stack: [{}]", stack.iter().map(|ty| format!("{ty}")).collect::<Vec<String>>().join(", "))
+			}
+			AnalysisErrorKind::UnappliedCfg { name } => {
+				write!(f, "cfg item {name} must be enabled or removed with the backend's cfg options before analysis")
+			}
 			AnalysisErrorKind::FunctionDependencyLoop { fn_names } => {
 				write!(f, "cannot calculate stack effects of recursive, effectively recursive or recursive-dependent functions: [{}]", fn_names.join(", "))
 			}
+			AnalysisErrorKind::Invalid => {
+				write!(f, "item failed to parse")
+			}
 		}
 	}
 }
@@ -98,8 +236,98 @@ pub enum AnalysisErrorKind {
 		fname: String
 	},
 	CannotInferType,
+	/// A value of the owned type would be implicitly copied, aliasing the heap memory it owns
+	CannotCopyOwned {
+		ty: Type
+	},
+	/// The type cannot be used as a map key, as it can't be hashed
+	UnhashableType {
+		ty: Type
+	},
+	/// The word expects a quotation on top of the stack, but the type was found instead
+	NotAQuotation {
+		word: String,
+		ty: Type
+	},
+	/// A value is curried into a quotation that doesn't pop any values
+	NothingToCurry {
+		ty: Type
+	},
+	/// The handler quotation passed to try doesn't take the body quotation's arguments and the thrown error, or leaves different values
+	MismatchedHandler {
+		body: StackEffect,
+		handler: StackEffect
+	},
+	/// The stack effect of the function up to a return differs from the stack effect of the whole function
+	MismatchedReturn {
+		fname: String,
+		expected: StackEffect,
+		found: StackEffect
+	},
+	/// A const fn pops values or doesn't push exactly one value, so can't be evaluated to a constant
+	InvalidConstFnEffect {
+		fname: String,
+		effect: StackEffect
+	},
+	/// Running the const fn or static assertion described by what during analysis produced the runtime error err
+	EvaluationFailed {
+		what: String,
+		err: String
+	},
+	/// A map word that pushes a zero value for a missing key is used with a map whose value type has none
+	NoZeroValue {
+		ty: Type
+	},
+	/// A const fn or static assertion uses a builtin that does I/O, or an extern function, directly or through the functions it uses
+	ImpureEvaluation {
+		what: String,
+		word: String
+	},
+	/// A static assertion pops values or doesn't push exactly one bool
+	InvalidStaticAssertEffect {
+		effect: StackEffect
+	},
+	/// A static assertion evaluated to false
+	StaticAssertFailed {
+		message: Option<String>
+	},
+	/// A const fn produced a value of the type that can't be embedded in the program as a literal
+	NonLiteralConstant {
+		fname: String,
+		ty: Type
+	},
+	/// A literal of a kind the parser recognises but which can't yet be analysed, interpreted or compiled, e.g. a float
+	UnsupportedLiteral {
+		literal: Literal
+	},
+	/// The type has no C equivalent, so can't be used in the signature of an extern or exported function
+	UnsupportedCType {
+		ty: Type
+	},
+	/// main is marked with `export`
+	CannotExportMain,
+	/// An exported function's name can't be used in the name of its C-ABI wrapper
+	InvalidExportName {
+		fname: String
+	},
+	/// C functions return at most one value, so extern and exported functions can't return more
+	MultipleCReturns {
+		fname: String
+	},
+	/// None of the builtins implementing an overloaded word accept the types on top of the stack
+	NoMatchingOverload {
+		word: String,
+		stack: Vec<Type>
+	},
+	/// A cfg item is analysed without first being resolved by `parser::cfg::apply` with the options of the backend it's for
+	UnappliedCfg {
+		name: String
+	},
 	// E.g. Cannot infer types of recursive functions
 	FunctionDependencyLoop {
 		fn_names: Vec<String>
-	}
+	},
+	/// The item contains or uses a placeholder for something that failed to parse. This is never reported, as the syntax error already
+	/// has been - the item is analysed as TypedTree::Invalid instead
+	Invalid
 }
\ No newline at end of file
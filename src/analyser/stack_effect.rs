@@ -1,18 +1,26 @@
 use std::fmt::{Display, Write};
 
-use crate::parser::tree::Literal;
+use crate::parser::tree::{Literal, Span};
 
 use super::{error::{AnalysisError, AnalysisErrorKind}, ttype::Type};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct StackEffect {
 	pushed: im::Vector<Type>,
-	popped: im::Vector<Type>
+	popped: im::Vector<Type>,
+	/// The stack effect from the start of this effect up to each `return` within it that isn't caught by a call by name, along with the
+	/// span of the `return`. These unwind through quotation calls to end the function that was called by name
+	returns: im::Vector<(StackEffect, Span)>
 }
 
 impl StackEffect {
 	pub fn new(popped: im::Vector<Type>, pushed: im::Vector<Type>) -> Self {
-		StackEffect { popped, pushed }
+		StackEffect { popped, pushed, returns: im::Vector::new() }
+	}
+
+	/// The stack effect of a `return` at span, which leaves the stack as it is
+	pub fn new_return(span: Span) -> Self {
+		StackEffect { popped: im::Vector::new(), pushed: im::Vector::new(), returns: im::vector![(StackEffect::none(), span)] }
 	}
 
 	pub fn new_popped(popped: im::Vector<Type>) -> Self {
@@ -24,7 +32,7 @@ impl StackEffect {
 	}
 
 	pub fn none() -> Self {
-		StackEffect { popped: im::Vector::new(), pushed: im::Vector::new() }
+		StackEffect::new(im::Vector::new(), im::Vector::new())
 	}
 
 	pub fn new_constructor(of: Type, fields: &im::OrdMap<String, Type>) -> StackEffect {
@@ -45,6 +53,68 @@ impl StackEffect {
 		self.pushed.last()
 	}
 
+	pub fn pushed(&self) -> &im::Vector<Type> {
+		&self.pushed
+	}
+
+	pub fn popped(&self) -> &im::Vector<Type> {
+		&self.popped
+	}
+
+	pub fn returns(&self) -> &im::Vector<(StackEffect, Span)> {
+		&self.returns
+	}
+
+	/// Returns this effect without the returns within it, as when it is the effect of calling a function by name, which catches them
+	pub fn without_returns(&self) -> StackEffect {
+		StackEffect::new(self.popped.clone(), self.pushed.clone())
+	}
+
+	/// Returns this effect with the returns within other, which starts where this effect does, added to those within it
+	pub fn with_returns_of(mut self, other: &StackEffect) -> StackEffect {
+		self.returns.append(other.returns.clone());
+		self
+	}
+
+	/// Returns whether the stack being left as ret leaves at a return is the same as it being left as this effect leaves it. ret can pop
+	/// fewer values than this effect, if it leaves the rest as they are
+	pub fn is_returned_by(&self, ret: &StackEffect) -> bool {
+		if ret.popped.len() > self.popped.len() {
+			return false;
+		}
+
+		// The values beneath those ret pops are left untouched, so are both popped and pushed back
+		let untouched = self.popped.clone().split_off(ret.popped.len());
+		let mut popped = ret.popped.clone();
+		popped.append(untouched.clone());
+		let mut pushed: im::Vector<Type> = untouched.into_iter().rev().collect();
+		pushed.append(ret.pushed.clone());
+
+		popped == self.popped && pushed == self.pushed
+	}
+
+	/// If every type this effect pops is known to be pushed by prev and can be used as the popped type, i.e. this effect can be applied
+	/// to the stack left by prev without popping anything prev did not push, returns this effect with any generic or unsized types
+	/// replaced by the concrete types they are bound to by prev. Otherwise returns None
+	pub fn instantiate(&self, prev: &StackEffect) -> Option<StackEffect> {
+		if self.popped.len() > prev.pushed.len() {
+			return None;
+		}
+
+		let mut bindings = Vec::new();
+
+		for (popped, pushed) in self.popped.iter().zip(prev.pushed.iter().rev()) {
+			if !popped.bind(pushed, &mut bindings) {
+				return None;
+			}
+		}
+
+		Some(StackEffect::new(
+			self.popped.iter().map(|ty| ty.substitute(&bindings)).collect(),
+			self.pushed.iter().map(|ty| ty.substitute(&bindings)).collect()
+		))
+	}
+
 	/// Returns the stack effect of the passed-in literal, or None if the literal requires context to work out the type (e.g. FnPtr)
 	pub fn from_lit(lit: &Literal) -> Option<StackEffect> {
 		Some(match lit {
@@ -61,13 +131,21 @@ impl StackEffect {
 			Literal::F64(_) => todo!(),
 			Literal::F32(_) => todo!(),
 			Literal::Bool(_) => StackEffect::new_pushed(im::vector![Type::new_bool()]),
+			Literal::Char(_) => StackEffect::new_pushed(im::vector![Type::new_char()]),
 			Literal::String(s) => StackEffect::new_pushed(im::vector![Type::new_strref(Some(s.len()))]),
+			Literal::ByteString(_) | Literal::Array(_) => StackEffect::new_pushed(im::vector![Type::from_lit(lit)?]),
 			Literal::FnPtr(_) => return None,
 		})
 	}
 
 	// TODO: Finish crafting the type system & implement this
-	pub fn combine(mut self, next: &StackEffect, cursor: usize) -> Result<StackEffect, AnalysisError> {
+	pub fn combine(mut self, next: &StackEffect, span: Span) -> Result<StackEffect, AnalysisError> {
+		// The returns within next happen after this effect
+		let before = self.without_returns();
+		for (ret, ret_span) in &next.returns {
+			self.returns.push_back((before.clone().combine(ret, span)?, *ret_span));
+		}
+
 		let mut next = next.clone();
 		while self.pushed.len() > 0 && next.popped.len() > 0 {
 			let pushed = self.pushed.pop_back().unwrap();
@@ -75,7 +153,7 @@ impl StackEffect {
 			if pushed.coerces_to(&popped) {
 				() // good, true
 			} else {
-				return Err(AnalysisError::new(AnalysisErrorKind::IncompatibleTypes { source: pushed, dest: popped }, cursor))
+				return Err(AnalysisError::new(AnalysisErrorKind::IncompatibleTypes { source: pushed, dest: popped }, span))
 			}
 		}
 
@@ -112,6 +190,13 @@ impl Display for StackEffect {
 			pushed_sb.push(' ')
 		}
 
-        write!(f, "({} -> {})", popped_sb, pushed_sb)
+        write!(f, "({} -> {})", popped_sb, pushed_sb)?;
+
+		// Returns change where a quotation can be called, so are part of its type
+		for (ret, _) in &self.returns {
+			write!(f, " returning {ret}")?;
+		}
+
+		Ok(())
     }
 }
\ No newline at end of file
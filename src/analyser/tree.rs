@@ -1,9 +1,10 @@
+use crate::parser::tree::{Attribute, Span};
+
 use super::{stack_effect::StackEffect, ttype::Type, value::Value};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TypedTreeNode {
-	pub file_path: String,
-	pub cursor: usize,
+	pub span: Span,
 	pub tree: TypedTree
 }
 
@@ -17,10 +18,42 @@ pub enum TypedTree {
 		name: String,
 		effect: StackEffect,
 		body: im::Vector<TypedTreeNode>,
+		/// Whether the function is marked with `export`, to be callable from C
+		exported: bool,
+		attributes: im::Vector<Attribute>,
+		/// The text of the `##` doc comments before the function, for tools to show alongside its effect
+		doc: Option<String>
+	},
+	/// A struct type, along with the text of the `##` doc comments before it and before each documented field
+	Type {
+		ty: Type,
+		doc: Option<String>,
+		field_docs: im::OrdMap<String, String>
+	},
+	/// A C function named name, loaded from the dynamic library library
+	Extern {
+		name: String,
+		library: String,
+		effect: StackEffect
+	},
+	/// A `const fn` named name, which has been evaluated to value
+	Constant {
+		name: String,
+		ty: Type,
+		value: Value,
+		/// The text of the `##` doc comments before the const fn
+		doc: Option<String>
+	},
+	/// A static assertion with the optional message, which held when evaluated during analysis
+	StaticAssert {
+		message: Option<String>
 	},
-	Type(Type),
 	Word(String),
-	BuiltinWord(String),
+	/// A call to the named builtin, with its stack effect instantiated for the types it is called with
+	BuiltinWord {
+		name: String,
+		effect: StackEffect
+	},
 	Literal {
 		ty: Type,
 		value: Value
@@ -31,14 +64,19 @@ pub enum TypedTree {
 	},
 	FieldAccess {
 		name: String,
-	}
+		/// The type the field is accessed on, which is either a struct or a reference to one
+		of: Type
+	},
+	/// The `return` word, which ends the function it is used in
+	Return,
+	/// An item which failed to parse, or which uses one that did. Only produced when there are syntax errors, so never run
+	Invalid
 }
 
 impl TypedTree {
-	pub fn wrap(self, file_path: impl Into<String>, cursor: usize) -> TypedTreeNode {
+	pub fn wrap(self, span: Span) -> TypedTreeNode {
 		TypedTreeNode {
-			file_path: file_path.into(),
-			cursor,
+			span,
 			tree: self
 		}
 	}
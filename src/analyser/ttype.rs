@@ -18,6 +18,8 @@ pub enum Type {
 	},
 	Reference {
 		to: Box<Type>,
+		/// Whether the referenced value can be mutated through this reference (`&mut T`) or not (`&T`)
+		mutable: bool
 	},
 	Generic {
 		name: String,
@@ -25,6 +27,10 @@ pub enum Type {
 	Function {
 		name: String,
 		effect: StackEffect
+	},
+	/// A quotation that carries captured values with it, as produced by `curry` and `compose`. Calling it has the stack effect effect
+	Closure {
+		effect: StackEffect
 	}
 }
 
@@ -34,8 +40,17 @@ pub enum OpaqueTypeKind {
 	SignedInt,
 	Float,
 	Bool,
+	/// A unicode scalar value, stored as its 32-bit code point
+	Char,
 	Str,
-	Array
+	/// A fixed-size array of the contained element type. The size of an array type is its number of elements
+	Array(Box<Type>),
+	/// A growable, heap-allocated vector of the contained element type
+	Vector(Box<Type>),
+	/// A growable, heap-allocated UTF-8 string
+	String,
+	/// A heap-allocated hash map from the first contained type to the second
+	Map(Box<Type>, Box<Type>)
 }
 
 impl Type {
@@ -43,6 +58,10 @@ impl Type {
 		Type::Opaque { size: Some(bits / 8), kind: OpaqueTypeKind::SignedInt }
 	}
 
+	pub fn new_float(bits: usize) -> Type {
+		Type::Opaque { size: Some(bits / 8), kind: OpaqueTypeKind::Float }
+	}
+
 	pub fn new_uint(bits: usize) -> Type {
 		Type::Opaque { size: Some(bits / 8), kind: OpaqueTypeKind::UnsignedInt }
 	}
@@ -51,12 +70,44 @@ impl Type {
 		Type::Opaque { size: Some(1), kind: OpaqueTypeKind::Bool }
 	}
 
+	pub fn new_char() -> Type {
+		Type::Opaque { size: Some(4), kind: OpaqueTypeKind::Char }
+	}
+
 	pub fn new_str(len_bytes: usize) -> Type {
 		Type::Opaque { size: Some(len_bytes), kind: OpaqueTypeKind::Str }
 	}
 
 	pub fn new_strref(len_bytes: Option<usize>) -> Type {
-		Type::Reference { to: Box::new(Type::Opaque { size: len_bytes, kind: OpaqueTypeKind::Str }) }
+		Type::new_ref(Type::Opaque { size: len_bytes, kind: OpaqueTypeKind::Str })
+	}
+
+	pub fn new_ref(to: Type) -> Type {
+		Type::Reference { to: Box::new(to), mutable: false }
+	}
+
+	pub fn new_mut_ref(to: Type) -> Type {
+		Type::Reference { to: Box::new(to), mutable: true }
+	}
+
+	pub fn new_array(elem: Type, len: Option<usize>) -> Type {
+		Type::Opaque { size: len, kind: OpaqueTypeKind::Array(Box::new(elem)) }
+	}
+
+	pub fn new_vec(elem: Type) -> Type {
+		Type::Opaque { size: None, kind: OpaqueTypeKind::Vector(Box::new(elem)) }
+	}
+
+	pub fn new_map(key: Type, value: Type) -> Type {
+		Type::Opaque { size: None, kind: OpaqueTypeKind::Map(Box::new(key), Box::new(value)) }
+	}
+
+	pub fn new_string() -> Type {
+		Type::Opaque { size: None, kind: OpaqueTypeKind::String }
+	}
+
+	pub fn new_generic(name: impl Into<String>) -> Type {
+		Type::Generic { name: name.into() }
 	}
 
 	pub fn new_struct(name: String, fields: &im::OrdMap<String, Type>) -> Type {
@@ -68,7 +119,11 @@ impl Type {
 	}
 
 	pub fn new_fnref(name: String, effect: StackEffect) -> Type {
-		Type::Reference { to: Box::new(Type::Function { name, effect }) }
+		Type::new_ref(Type::Function { name, effect })
+	}
+
+	pub fn new_closure(effect: StackEffect) -> Type {
+		Type::Closure { effect }
 	}
 
 	pub fn from_lit(lit: &Literal) -> Option<Type> {
@@ -83,17 +138,69 @@ impl Type {
 			Literal::I32(_) => Type::new_int(32),
 			Literal::I16(_) => Type::new_int(16),
 			Literal::I8(_) => Type::new_int(8),
-			Literal::F64(_) => todo!(),
-			Literal::F32(_) => todo!(),
+			Literal::F64(_) => Type::new_float(64),
+			Literal::F32(_) => Type::new_float(32),
 			Literal::Bool(_) => Type::new_bool(),
+			Literal::Char(_) => Type::new_char(),
 			Literal::String(val) => Type::new_strref(Some(val.len())),
+			Literal::ByteString(bytes) => Type::new_array(Type::new_uint(8), Some(bytes.len())),
+			Literal::Array(elems) => {
+				let elem_ty = Type::from_lit(elems.first()?)?;
+
+				for elem in elems {
+					if Type::from_lit(elem)? != elem_ty {
+						return None;
+					}
+				}
+
+				Type::new_array(elem_ty, Some(elems.len()))
+			}
 			_ => return None,
 		})
 	}
 
+	/// Returns the element type of this type if it is a vector type
+	pub fn as_vec(&self) -> Option<&Type> {
+		if let Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(elem) } = self {
+			Some(elem)
+		} else {
+			None
+		}
+	}
+
+	/// Returns the key and value types of this type if it is a map type
+	pub fn as_map(&self) -> Option<(&Type, &Type)> {
+		if let Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key, value) } = self {
+			Some((key, value))
+		} else {
+			None
+		}
+	}
+
+	/// Returns the element type and length of this type if it is an array type
+	pub fn as_array(&self) -> Option<(&Type, &Option<usize>)> {
+		if let Type::Opaque { size, kind: OpaqueTypeKind::Array(elem) } = self {
+			Some((elem, size))
+		} else {
+			None
+		}
+	}
+
+	/// Returns the stack effect of calling this type if it is a quotation, i.e. a function reference or a closure
+	pub fn as_quotation(&self) -> Option<&StackEffect> {
+		match self {
+			Type::Reference { to, mutable: false } => match to.as_ref() {
+				Type::Function { name: _, effect } => Some(effect),
+				_ => None
+			},
+			Type::Closure { effect } => Some(effect),
+			_ => None
+		}
+	}
+
 	pub fn deref<'a>(&'a self) -> &'a Type {
 		match self {
-			Self::Reference { to } => &to,
+			Self::Reference { to, mutable: _ } => &to,
 			_ => &self
 		}
 	}
@@ -114,19 +221,97 @@ impl Type {
 		}
 	}
 
+	/// Returns whether values of this type can be hashed structurally, and so used as map keys
+	pub fn is_hashable(&self) -> bool {
+		match self {
+			Type::Opaque { size: _, kind } => match kind {
+				OpaqueTypeKind::UnsignedInt | OpaqueTypeKind::SignedInt | OpaqueTypeKind::Bool | OpaqueTypeKind::Char | OpaqueTypeKind::String => true,
+				OpaqueTypeKind::Array(elem) => elem.is_hashable(),
+				_ => false
+			},
+			Type::Reference { to, mutable: false } => to.as_opaque().is_some_and(|(_, kind)| *kind == OpaqueTypeKind::Str),
+			Type::Transparent { name: _, fields, sum_type: false } => fields.iter().all(|(_, ftype)| ftype.is_hashable()),
+			_ => false
+		}
+	}
+
+	/// Returns whether values of this type can be copied freely. Other types are owned, as they own heap memory - vectors, strings,
+	/// maps, references from `box`/`cell` and closures - and have to be explicitly cloned, and are freed when dropped.
+	/// String literal and function references point to static memory, so are copyable
+	pub fn is_copyable(&self) -> bool {
+		match self {
+			Type::Opaque { size: _, kind } => match kind {
+				OpaqueTypeKind::Array(elem) => elem.is_copyable(),
+				OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String | OpaqueTypeKind::Map(_, _) => false,
+				_ => true
+			},
+			Type::Reference { to, mutable: false } => matches!(to.as_ref(), Type::Opaque { size: _, kind: OpaqueTypeKind::Str } | Type::Function { name: _, effect: _ }),
+			Type::Reference { to: _, mutable: true } => false,
+			Type::Transparent { name: _, fields, sum_type: _ } => fields.iter().all(|(_, ftype)| ftype.is_copyable()),
+			Type::Generic { name: _ } | Type::Function { name: _, effect: _ } => true,
+			Type::Closure { effect: _ } => false
+		}
+	}
+
+	/// Returns the size and alignment in bytes of values of this type when compiled, which follow C's layout rules for a 64-bit target,
+	/// or None if the type is unsized or has no layout yet
+	pub fn layout(&self) -> Option<(usize, usize)> {
+		Some(match self {
+			Type::Opaque { size, kind } => match kind {
+				OpaqueTypeKind::UnsignedInt | OpaqueTypeKind::SignedInt | OpaqueTypeKind::Float | OpaqueTypeKind::Bool | OpaqueTypeKind::Char => ((*size)?, (*size)?),
+				OpaqueTypeKind::Str => ((*size)?, 1),
+				OpaqueTypeKind::Array(elem) => {
+					let (elem_size, elem_align) = elem.layout()?;
+					(elem_size * (*size)?, elem_align)
+				}
+				// { data, len, cap } and { entries, len, cap, used }
+				OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String => (24, 8),
+				OpaqueTypeKind::Map(_, _) => (32, 8)
+			},
+			Type::Reference { to: _, mutable: _ } | Type::Closure { effect: _ } | Type::Function { name: _, effect: _ } => (8, 8),
+			Type::Transparent { name: _, fields, sum_type: false } => {
+				let mut size: usize = 0;
+				let mut align = 1;
+
+				for (_, ftype) in fields {
+					let (field_size, field_align) = ftype.layout()?;
+					size = size.next_multiple_of(field_align) + field_size;
+					align = align.max(field_align);
+				}
+
+				(size.next_multiple_of(align), align)
+			}
+			_ => return None
+		})
+	}
+
+	/// Returns whether the size of values of this type is known, and so they can be stored on the stack
+	pub fn is_sized(&self) -> bool {
+		!matches!(self, Type::Opaque { size: None, kind: OpaqueTypeKind::Str | OpaqueTypeKind::Array(_) })
+	}
+
 	pub fn coerces_to(&self, other: &Type) -> bool { // TODO: This needs a lot of work
 		if self == other {
 			true
 		} else {
 			match self {
-				Self::Reference { to } => {
+				Self::Reference { to, mutable } => {
 					let this_ref_to = to;
-					let other_ref_to = if let Type::Reference { to } = other {
+					let other_ref_to = if let Type::Reference { to, mutable: other_mutable } = other {
+						// A mutable reference can be used as an immutable one, but not the other way around
+						if *other_mutable && !mutable {
+							return false;
+						}
+
 						to
 					} else {
 						return false;
 					};
 
+					if this_ref_to == other_ref_to {
+						return true;
+					}
+
 					match (this_ref_to.as_ref().clone(), other_ref_to.as_ref().clone()) {
 						(Type::Opaque { size, kind }, Type::Opaque { size: other_size, kind: other_kind }) => {
 							if size.is_some() && other_size.is_none() && kind == other_kind {
@@ -143,11 +328,78 @@ impl Type {
 		}
 	}
 
+	/// Matches this type, which may contain generics or types of unspecified size, against the concrete type, recording in bindings
+	/// the concrete type each of those generic/unsized types are bound to. Returns false if concrete cannot be used where this type is expected
+	pub fn bind(&self, concrete: &Type, bindings: &mut Vec<(Type, Type)>) -> bool {
+		if let Some(i) = bindings.iter().position(|(pattern, _)| pattern == self) {
+			let bound = &bindings[i].1;
+
+			// If the pattern was first bound to a more specific type (e.g. &str(byte_len: 1) vs &str), the more general one is kept
+			if bound.coerces_to(concrete) && !concrete.coerces_to(bound) {
+				bindings[i].1 = concrete.clone();
+				return true;
+			}
+
+			return concrete.coerces_to(bound);
+		}
+
+		match (self, concrete) {
+			(Type::Generic { name: _ }, _) => {
+				bindings.push((self.clone(), concrete.clone()));
+				true
+			}
+			(Type::Reference { to, mutable }, Type::Reference { to: concrete_to, mutable: concrete_mutable }) => mutable == concrete_mutable && to.bind(concrete_to, bindings),
+			(Type::Opaque { size, kind: OpaqueTypeKind::Array(elem) }, Type::Opaque { size: concrete_size, kind: OpaqueTypeKind::Array(concrete_elem) }) => {
+				if size.is_some() && size != concrete_size {
+					return false;
+				}
+
+				if !elem.bind(concrete_elem, bindings) {
+					return false;
+				}
+
+				if size.is_none() {
+					bindings.push((self.clone(), concrete.clone()));
+				}
+
+				true
+			}
+			(Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(elem) }, Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(concrete_elem) }) => elem.bind(concrete_elem, bindings),
+			(Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key, value) }, Type::Opaque { size: _, kind: OpaqueTypeKind::Map(concrete_key, concrete_value) }) => {
+				key.bind(concrete_key, bindings) && value.bind(concrete_value, bindings)
+			}
+			(Type::Opaque { size: None, kind }, Type::Opaque { size: Some(_), kind: concrete_kind }) if kind == concrete_kind => {
+				bindings.push((self.clone(), concrete.clone()));
+				true
+			}
+			_ => concrete.coerces_to(self)
+		}
+	}
+
+	/// Replaces the generic/unsized types bound by Type::bind in this type with the concrete types they were bound to
+	pub fn substitute(&self, bindings: &[(Type, Type)]) -> Type {
+		if let Some((_, bound)) = bindings.iter().find(|(pattern, _)| pattern == self) {
+			return bound.clone();
+		}
+
+		match self {
+			Type::Reference { to, mutable } => Type::Reference { to: Box::new(to.substitute(bindings)), mutable: *mutable },
+			Type::Opaque { size, kind: OpaqueTypeKind::Array(elem) } => Type::new_array(elem.substitute(bindings), *size),
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(elem) } => Type::new_vec(elem.substitute(bindings)),
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key, value) } => Type::new_map(key.substitute(bindings), value.substitute(bindings)),
+			_ => self.clone()
+		}
+	}
+
 	pub fn from_name(name: impl AsRef<str>) -> Option<Type> {
 		let name = name.as_ref();
 
-		if name.starts_with("&") {
-			return Some(Type::Reference { to: Box::new(Type::from_name(&name[1..])?) });
+		if let Some(to) = name.strip_prefix("&mut ") {
+			return Some(Type::new_mut_ref(Type::from_name(to)?));
+		}
+
+		if let Some(to) = name.strip_prefix('&') {
+			return Some(Type::new_ref(Type::from_name(to)?));
 		}
 
 		match name {
@@ -162,6 +414,9 @@ impl Type {
 			"i16" => Some(Type::new_int(16)),
 			"i8" => Some(Type::new_int(8)),
 			"bool" => Some(Type::new_bool()),
+			"char" => Some(Type::new_char()),
+			"String" => Some(Type::new_string()),
+			"str" => Some(Type::Opaque { size: None, kind: OpaqueTypeKind::Str }),
 			_ => None
 		}
 	}
@@ -176,19 +431,38 @@ impl Type {
 							OpaqueTypeKind::SignedInt => format!("i{}", size * 8),
 							OpaqueTypeKind::Float => format!("f{}", size * 8),
 							OpaqueTypeKind::Bool => "bool".to_string(),
-							OpaqueTypeKind::Str => todo!(),
-							OpaqueTypeKind::Array => todo!(),
+							OpaqueTypeKind::Char => "char".to_string(),
+							// The length of a string slice is not part of its name, as it can't be named
+							OpaqueTypeKind::Str => "str".to_string(),
+							OpaqueTypeKind::Array(elem) => format!("[{}; {size}]", elem.name()),
+							OpaqueTypeKind::Vector(elem) => format!("Vec<{}>", elem.name()),
+							OpaqueTypeKind::String => "String".to_string(),
+							OpaqueTypeKind::Map(key, value) => format!("Map<{}, {}>", key.name(), value.name())
 						}
 					}
 					None => {
-						todo!()
+						// Scalar types always have a size, but are named after their kind alone if they don't
+						match kind {
+							OpaqueTypeKind::UnsignedInt => "u".to_string(),
+							OpaqueTypeKind::SignedInt => "i".to_string(),
+							OpaqueTypeKind::Float => "f".to_string(),
+							OpaqueTypeKind::Bool => "bool".to_string(),
+							OpaqueTypeKind::Str => "str".to_string(),
+							OpaqueTypeKind::Array(elem) => format!("[{}]", elem.name()),
+							OpaqueTypeKind::Vector(elem) => format!("Vec<{}>", elem.name()),
+							OpaqueTypeKind::String => "String".to_string(),
+							OpaqueTypeKind::Map(key, value) => format!("Map<{}, {}>", key.name(), value.name()),
+							OpaqueTypeKind::Char => "char".to_string()
+						}
 					}
 				}
 			},
 			Type::Transparent { name, fields: _, sum_type: _ } => name.clone(),
-			Type::Reference { to } => todo!(), // TODO: All of these
-			Type::Generic { name } => todo!(),
-			Type::Function { name, effect } => todo!(),
+			Type::Reference { to, mutable: false } => format!("&{}", to.name()),
+			Type::Reference { to, mutable: true } => format!("&mut {}", to.name()),
+			Type::Generic { name } => name.clone(),
+			Type::Function { name, effect: _ } => name.clone(),
+			Type::Closure { effect } => format!("closure {effect}"),
 		}
 	}
 }
@@ -204,12 +478,20 @@ impl Display for Type {
 							OpaqueTypeKind::SignedInt => format!("i{}", size * 8),
 							OpaqueTypeKind::Float => format!("f{}", size * 8),
 							OpaqueTypeKind::Bool => format!("bool"),
+							OpaqueTypeKind::Char => "char".to_string(),
 							OpaqueTypeKind::Str => format!("str(byte_len: {})", size),
-							OpaqueTypeKind::Array => todo!()
+							OpaqueTypeKind::Array(elem) => format!("[{elem}; {size}]"),
+							OpaqueTypeKind::Vector(elem) => format!("Vec<{elem}>"),
+							OpaqueTypeKind::String => "String".to_string(),
+							OpaqueTypeKind::Map(key, value) => format!("Map<{key}, {value}>")
 						}
 					} else {
 						match kind {
 							OpaqueTypeKind::Str => format!("str"),
+							OpaqueTypeKind::Array(elem) => format!("[{elem}]"),
+							OpaqueTypeKind::Vector(elem) => format!("Vec<{elem}>"),
+							OpaqueTypeKind::String => "String".to_string(),
+							OpaqueTypeKind::Map(key, value) => format!("Map<{key}, {value}>"),
 							_ => unreachable!()
 						}
 					}
@@ -220,9 +502,11 @@ impl Display for Type {
 						true => format!("enum {name} {{ {} }}", fields.iter().map(|(fname, ftype)| format!("{fname} {ftype}")).collect::<Vec<String>>().join(", ")),
 					}
 				}
-				Self::Reference { to } => format!("&{to}"),
+				Self::Reference { to, mutable: false } => format!("&{to}"),
+				Self::Reference { to, mutable: true } => format!("&mut {to}"),
 				Self::Generic { name } => name.to_string(),
-				Self::Function { name, effect } => format!("{name} {effect}")
+				Self::Function { name, effect } => format!("{name} {effect}"),
+				Self::Closure { effect } => format!("closure {effect}")
 			}
 		})
 	}
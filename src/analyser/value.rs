@@ -1,4 +1,4 @@
-use std::{fmt::Display, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, hash::{Hash, Hasher}, rc::Rc};
 
 use crate::{analyser::ttype::OpaqueTypeKind, parser::tree::Literal};
 
@@ -14,11 +14,33 @@ pub struct Value { // TODO: A lot of thought needs to go into this - The current
 pub enum ValueInner {
 	Bytes(Vec<u8>),
 	Struct(im::Vector<Value>),
+	Array(im::Vector<Value>),
+	/// A heap-allocated vector, shared between all copies of the value
+	Vector {
+		elems: Rc<RefCell<Vec<Value>>>
+	},
+	/// A heap-allocated string, shared between all copies of the value
+	String {
+		bytes: Rc<RefCell<Vec<u8>>>
+	},
+	/// A heap-allocated hash map, shared between all copies of the value
+	Map {
+		entries: Rc<RefCell<HashMap<Value, Value>>>
+	},
 	Reference {
 		to: Rc<Value>
 	},
+	/// A mutable reference cell, shared between all copies of the reference
+	MutReference {
+		to: Rc<RefCell<Value>>
+	},
 	Function {
 		fn_name: String
+	},
+	/// A closure, which when called pushes the captured values and then calls each of the quotations in calls in order
+	Closure {
+		captured: im::Vector<Value>,
+		calls: im::Vector<Value>
 	}
 }
 
@@ -32,11 +54,18 @@ impl Value {
 
 	pub fn new_reference(to: Value) -> Value {
 		Value {
-			ty: Type::Reference { to: Box::new(to.ty.clone()) },
+			ty: Type::new_ref(to.ty.clone()),
 			inner: ValueInner::Reference { to: Rc::new(to) }
 		}
 	}
 
+	pub fn new_mut_reference(to: Value) -> Value {
+		Value {
+			ty: Type::new_mut_ref(to.ty.clone()),
+			inner: ValueInner::MutReference { to: Rc::new(RefCell::new(to)) }
+		}
+	}
+
 	pub fn new_fn(fn_name: String, effect: StackEffect) -> Value {
 		Value {
 			ty: Type::Function { name: fn_name.clone(), effect },
@@ -44,6 +73,13 @@ impl Value {
 		}
 	}
 
+	pub fn new_closure(effect: StackEffect, captured: im::Vector<Value>, calls: im::Vector<Value>) -> Value {
+		Value {
+			ty: Type::new_closure(effect),
+			inner: ValueInner::Closure { captured, calls }
+		}
+	}
+
 	pub fn new_struct(ty: Type, values: im::Vector<Value>) -> Value {
 		Value {
 			ty,
@@ -51,6 +87,75 @@ impl Value {
 		}
 	}
 
+	pub fn new_array(elem_ty: Type, values: im::Vector<Value>) -> Value {
+		Value {
+			ty: Type::new_array(elem_ty, Some(values.len())),
+			inner: ValueInner::Array(values)
+		}
+	}
+
+	pub fn new_vec(elem_ty: Type, values: Vec<Value>) -> Value {
+		Value {
+			ty: Type::new_vec(elem_ty),
+			inner: ValueInner::Vector { elems: Rc::new(RefCell::new(values)) }
+		}
+	}
+
+	pub fn new_string(string: impl Into<String>) -> Value {
+		Value {
+			ty: Type::new_string(),
+			inner: ValueInner::String { bytes: Rc::new(RefCell::new(string.into().into_bytes())) }
+		}
+	}
+
+	pub fn new_map(key_ty: Type, value_ty: Type) -> Value {
+		Value {
+			ty: Type::new_map(key_ty, value_ty),
+			inner: ValueInner::Map { entries: Rc::new(RefCell::new(HashMap::new())) }
+		}
+	}
+
+	/// Produces the zero value of the type ty - zeroed bytes, or an empty vector, string or map - which is what compiled code produces
+	/// by zero-initialising a value of the type. Returns None for types without one, such as references
+	pub fn zeroed(ty: &Type) -> Option<Value> {
+		Some(match ty {
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Array(elem_ty) } => {
+				let (_, len) = ty.as_array()?;
+
+				Value::new_array(elem_ty.as_ref().clone(), (0..(*len)?).map(|_| Value::zeroed(elem_ty)).collect::<Option<im::Vector<Value>>>()?)
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(elem_ty) } => Value::new_vec(elem_ty.as_ref().clone(), Vec::new()),
+			Type::Opaque { size: _, kind: OpaqueTypeKind::String } => Value::new_string(""),
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key_ty, value_ty) } => Value::new_map(key_ty.as_ref().clone(), value_ty.as_ref().clone()),
+			Type::Opaque { size: Some(size), kind: OpaqueTypeKind::UnsignedInt | OpaqueTypeKind::SignedInt | OpaqueTypeKind::Float | OpaqueTypeKind::Bool | OpaqueTypeKind::Char } => {
+				Value::from_typed_bytes(ty.clone(), vec![0; *size])
+			}
+			Type::Transparent { name: _, fields, sum_type: false } => {
+				Value::new_struct(ty.clone(), fields.iter().map(|(_, ftype)| Value::zeroed(ftype)).collect::<Option<im::Vector<Value>>>()?)
+			}
+			_ => return None
+		})
+	}
+
+	/// Returns a copy of this value that shares no heap contents with it, so that mutating one does not affect the other
+	pub fn deep_clone(&self) -> Value {
+		let inner = match &self.inner {
+			ValueInner::Struct(values) => ValueInner::Struct(values.iter().map(Value::deep_clone).collect()),
+			ValueInner::Array(values) => ValueInner::Array(values.iter().map(Value::deep_clone).collect()),
+			ValueInner::Vector { elems } => ValueInner::Vector { elems: Rc::new(RefCell::new(elems.borrow().iter().map(Value::deep_clone).collect())) },
+			ValueInner::String { bytes } => ValueInner::String { bytes: Rc::new(RefCell::new(bytes.borrow().clone())) },
+			ValueInner::Map { entries } => ValueInner::Map { entries: Rc::new(RefCell::new(entries.borrow().iter().map(|(k, v)| (k.deep_clone(), v.deep_clone())).collect())) },
+			ValueInner::MutReference { to } => ValueInner::MutReference { to: Rc::new(RefCell::new(to.borrow().deep_clone())) },
+			ValueInner::Closure { captured, calls } => ValueInner::Closure { captured: captured.iter().map(Value::deep_clone).collect(), calls: calls.iter().map(Value::deep_clone).collect() },
+			inner => inner.clone()
+		};
+
+		Value {
+			ty: self.ty.clone(),
+			inner
+		}
+	}
+
 	/// Produces a Value from the passed-in literal. Returns None if the literal requires context (e.g. Literal::FnPtr)
 	pub fn from_lit(lit: &Literal) -> Option<Value> {
 		Some(match lit {
@@ -67,11 +172,43 @@ impl Value {
 			Literal::F64(val) => Value::from_typed_bytes(Type::from_lit(lit)?, val.to_ne_bytes()),
 			Literal::F32(val) => Value::from_typed_bytes(Type::from_lit(lit)?, val.to_ne_bytes()),
 			Literal::Bool(val) => Value::from_typed_bytes(Type::from_lit(lit)?, [*val as u8]),
+			Literal::Char(val) => Value::from_typed_bytes(Type::from_lit(lit)?, (*val as u32).to_ne_bytes()),
 			Literal::String(val) => Value::new_reference(Value::from_typed_bytes(Type::new_str(val.len()), val.bytes())),
+			Literal::ByteString(bytes) => Value::new_array(Type::new_uint(8), bytes.iter().map(|byte| Value::from_typed_bytes(Type::new_uint(8), [*byte])).collect()),
+			Literal::Array(elems) => {
+				let elem_ty = Type::from_lit(lit)?.as_array()?.0.clone();
+
+				Value::new_array(elem_ty, elems.iter().map(Value::from_lit).collect::<Option<im::Vector<Value>>>()?)
+			}
 			_ => return None,
 		})
 	}
 
+	/// Whether the value can be embedded in a program as a literal, i.e. contains no floats, enums, maps, closures or references other than to strings
+	pub fn is_literal(&self) -> bool {
+		match &self.inner {
+			// Floats and enums can't yet be compiled
+			ValueInner::Bytes(_) => !matches!(self.ty, Type::Opaque { size: _, kind: OpaqueTypeKind::Float }),
+			ValueInner::Struct(values) => !matches!(self.ty, Type::Transparent { name: _, fields: _, sum_type: true }) && values.iter().all(Value::is_literal),
+			ValueInner::String { bytes: _ } | ValueInner::Function { fn_name: _ } => true,
+			ValueInner::Array(values) => values.iter().all(Value::is_literal),
+			ValueInner::Vector { elems } => elems.borrow().iter().all(Value::is_literal),
+			ValueInner::Reference { to } => matches!(to.inner, ValueInner::Bytes(_)),
+			ValueInner::Map { entries: _ } | ValueInner::MutReference { to: _ } | ValueInner::Closure { captured: _, calls: _ } => false
+		}
+	}
+
+	/// Adds the names of the functions referenced within this value to names
+	pub fn fn_refs(&self, names: &mut Vec<String>) {
+		match &self.inner {
+			ValueInner::Function { fn_name } => names.push(fn_name.clone()),
+			ValueInner::Struct(values) | ValueInner::Array(values) => values.iter().for_each(|value| value.fn_refs(names)),
+			ValueInner::Vector { elems } => elems.borrow().iter().for_each(|value| value.fn_refs(names)),
+			ValueInner::Reference { to } => to.fn_refs(names),
+			_ => ()
+		}
+	}
+
 	pub fn deref<'a>(&'a self) -> &'a Value {
 		match &self.inner {
 			ValueInner::Reference { to } => &to,
@@ -79,6 +216,14 @@ impl Value {
 		}
 	}
 
+	pub fn as_char(&self) -> Option<char> {
+		if *self.ty.as_opaque()?.1 != OpaqueTypeKind::Char {
+			return None;
+		}
+
+		char::from_u32(u32::from_ne_bytes(self.as_bytes()?.try_into().ok()?))
+	}
+
 	pub fn as_strref(&self) -> Option<String> {
 		if *self.ty.deref().as_opaque()?.1 != OpaqueTypeKind::Str {
 			return None;
@@ -92,6 +237,13 @@ impl Value {
 		}
 	}
 
+	pub fn as_string(&self) -> Option<String> {
+		match &self.inner {
+			ValueInner::String { bytes } => String::from_utf8(bytes.borrow().clone()).ok(),
+			_ => None
+		}
+	}
+
 	pub fn as_bytes(&self) -> Option<&[u8]> {
 		match &self.inner {
 			ValueInner::Bytes(b) => Some(&b),
@@ -100,6 +252,26 @@ impl Value {
 	}
 }
 
+impl Eq for Value {}
+
+/// Values are hashed structurally, so that equal values hash equally regardless of whether their contents are shared
+impl Hash for Value {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		match &self.inner {
+			ValueInner::Bytes(bytes) => bytes.hash(state),
+			ValueInner::Struct(values) | ValueInner::Array(values) => values.iter().for_each(|value| value.hash(state)),
+			ValueInner::Vector { elems } => elems.borrow().iter().for_each(|elem| elem.hash(state)),
+			ValueInner::String { bytes } => bytes.borrow().hash(state),
+			// Maps are unordered, so only their length can be hashed consistently
+			ValueInner::Map { entries } => entries.borrow().len().hash(state),
+			ValueInner::Reference { to } => to.hash(state),
+			ValueInner::MutReference { to } => to.borrow().hash(state),
+			ValueInner::Function { fn_name } => fn_name.hash(state),
+			ValueInner::Closure { captured, calls } => captured.iter().chain(calls.iter()).for_each(|value| value.hash(state))
+		}
+	}
+}
+
 impl Display for Value {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { // TODO: ?
 		write!(f, "Value(of_type: {}, value: (unable to be displayed))", self.ty)
@@ -3,7 +3,7 @@ use std::{collections::BTreeMap, ffi::{CStr, CString}, marker::PhantomData, mem}
 use im::OrdMap;
 use llvm_sys::{core::*, error_handling::{LLVMEnablePrettyStackTrace, LLVMInstallFatalErrorHandler}, execution_engine::{LLVMCreateExecutionEngineForModule, LLVMDisposeExecutionEngine, LLVMGetFunctionAddress, LLVMLinkInMCJIT, LLVMRunFunctionAsMain}, ir_reader::LLVMParseIRInContext, prelude::*, target::{LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget}, LLVMBuilder, LLVMContext, LLVMModule};
 
-use crate::analyser::{tree::{TypedTree, TypedTreeNode}, ttype::{OpaqueTypeKind, Type}};
+use crate::{analyser::{stack_effect::StackEffect, tree::{TypedTree, TypedTreeNode}, ttype::{OpaqueTypeKind, Type}, value::{Value, ValueInner}}, parser::tree::Attribute};
 
 const LLVM_ADDRESS_SPACE_GENERIC: u32 = 0;
 const LLVM_FALSE: i32 = 0;
@@ -164,102 +164,2446 @@ struct ModuleContext<'a> {
 impl<'a> ModuleContext<'a> {
 	pub fn compile_module(&mut self, module: &TypedTreeNode) {
 		if let TypedTree::Module { name: _, elems } = &module.tree {
+			for (_, enode) in elems {
+				if let TypedTree::Extern { .. } = enode.tree {
+					self.compile_extern(enode);
+				}
+			}
+
 			let mut functions_left: Vec<TypedTreeNode> = elems.iter().filter_map(|(_, enode)| if let TypedTree::Function { .. } = enode.tree { Some(enode.clone()) } else { None }).collect();
 
-			while !functions_left.is_empty() {
-				let mut to_remove = Vec::new();
-				for (i, f) in functions_left.iter().enumerate() {
-					if self.compile_function(f) {
-						to_remove.push(i);
+			while !functions_left.is_empty() {
+				let mut to_remove = Vec::new();
+				for (i, f) in functions_left.iter().enumerate() {
+					if self.compile_function(f) {
+						to_remove.push(i);
+					}
+				}
+				assert_ne!(to_remove.len(), 0);
+				for i in to_remove {
+					functions_left.remove(i);
+				}
+			}
+		}
+	}
+
+	/// Declares the C function of the passed in extern, and adds a wrapper function named `__extern_{name}` to the module that pops its
+	/// arguments off the tower stack and pushes its return value, so it can be called like any other tower function. The library the
+	/// function is from has to be linked in when linking the compiled module
+	pub fn compile_extern(&mut self, extern_fn: &TypedTreeNode) {
+		if let TypedTree::Extern { name, library: _, effect } = &extern_fn.tree {
+			unsafe {
+				let c_fntype = self.c_fn_type(effect);
+				let c_fn = self.get_or_declare(name, c_fntype);
+
+				let fntype = self.llvm_type(&Type::Function { name: name.to_string(), effect: effect.clone() });
+				let fnvalue = LLVMAddFunction(self.module, cstrv!(format!("__extern_{name}")), fntype);
+
+				let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+				LLVMPositionBuilderAtEnd(self.builder, block);
+
+				let stack_ptrs = [
+					LLVMGetParam(fnvalue, 0),
+					LLVMGetParam(fnvalue, 1),
+					LLVMGetParam(fnvalue, 2)
+				];
+
+				// The last parameter is on top of the stack, so is popped first
+				let mut args = Vec::new();
+				for ty in effect.popped() {
+					let arg = self.build_pop(&stack_ptrs, ty);
+
+					args.push(match ty {
+						Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => LLVMBuildZExt(self.builder, arg, LLVMInt8TypeInContext(self.context), cstr!("c_bool\0")),
+						_ => arg
+					});
+				}
+				args.reverse();
+
+				match effect.pushed().front() {
+					Some(ty) => {
+						let ret = LLVMBuildCall2(self.builder, c_fntype, c_fn, args.as_mut_ptr(), args.len() as u32, cstr!("ret\0"));
+
+						let ret = match ty {
+							Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntNE, ret, LLVMConstInt(LLVMInt8TypeInContext(self.context), 0, LLVM_FALSE), cstr!("ret_bool\0")),
+							// Null strings abort, as the interpreter raises an error for them
+							Type::Reference { to: _, mutable: _ } => {
+								let is_null = LLVMBuildIsNull(self.builder, ret, cstr!("is_null\0"));
+								self.build_abort_if(fnvalue, is_null);
+
+								ret
+							}
+							_ => ret
+						};
+
+						self.build_push(&stack_ptrs, ty, ret);
+					}
+					None => {
+						LLVMBuildCall2(self.builder, c_fntype, c_fn, args.as_mut_ptr(), args.len() as u32, cstr!("\0"));
+					}
+				}
+
+				LLVMBuildRetVoid(self.builder);
+
+				self.functions.insert(name.to_string(), (fntype, fnvalue));
+			}
+		} else {
+			unreachable!();
+		}
+	}
+
+	/// Returns the LLVM type of the C function with the same arguments and result as a tower function with the stack effect effect
+	unsafe fn c_fn_type(&mut self, effect: &StackEffect) -> LLVMTypeRef {
+		// C bools are bytes
+		let c_type = |this: &mut Self, ty: &Type| match ty {
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => LLVMInt8TypeInContext(this.context),
+			ty => this.llvm_type(ty)
+		};
+
+		let mut c_param_types: Vec<LLVMTypeRef> = effect.popped().iter().rev().map(|ty| c_type(self, ty)).collect();
+		let c_ret_type = match effect.pushed().front() {
+			Some(ty) => c_type(self, ty),
+			None => LLVMVoidTypeInContext(self.context)
+		};
+
+		LLVMFunctionType(c_ret_type, c_param_types.as_mut_ptr(), c_param_types.len() as u32, LLVM_FALSE)
+	}
+
+	/// Adds a C-ABI function named `tower_{name}` to the module that calls the exported tower function fnvalue, named name. Like
+	/// builtin.ll's main, it sets up a new tower stack, then pushes its arguments onto it, calls the tower function, and returns the value
+	/// left on top of the stack, if any
+	unsafe fn compile_export(&mut self, name: &str, effect: &StackEffect, fntype: LLVMTypeRef, fnvalue: LLVMValueRef) {
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+
+		let c_fntype = self.c_fn_type(effect);
+		let c_fn = LLVMAddFunction(self.module, cstrv!(format!("tower_{name}")), c_fntype);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, c_fn, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let mut malloc_params = [
+			i64_ty
+		];
+		let malloc_ty = LLVMFunctionType(ptr_ty, malloc_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let malloc = self.get_or_declare("malloc", malloc_ty);
+
+		let mut free_params = [
+			ptr_ty
+		];
+		let free_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), free_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let free = self.get_or_declare("free", free_ty);
+
+		let init_size = LLVMConstInt(i64_ty, 4096, LLVM_FALSE);
+		let bp = LLVMBuildCall2(self.builder, malloc_ty, malloc, [init_size].as_mut_ptr(), 1, cstr!("bp\0"));
+		let mut gep_indices = [
+			init_size
+		];
+		let ep = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), bp, gep_indices.as_mut_ptr(), 1, cstr!("ep\0"));
+
+		let stack_ptrs = [
+			LLVMBuildAlloca(self.builder, ptr_ty, cstr!("bp_ptr\0")),
+			LLVMBuildAlloca(self.builder, ptr_ty, cstr!("sp_ptr\0")),
+			LLVMBuildAlloca(self.builder, ptr_ty, cstr!("ep_ptr\0"))
+		];
+		LLVMBuildStore(self.builder, bp, stack_ptrs[0]);
+		LLVMBuildStore(self.builder, bp, stack_ptrs[1]);
+		LLVMBuildStore(self.builder, ep, stack_ptrs[2]);
+
+		// The first argument is pushed first, so that the last ends up on top of the stack
+		for (i, ty) in effect.popped().iter().rev().enumerate() {
+			let arg = LLVMGetParam(c_fn, i as u32);
+			let arg = match ty {
+				Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntNE, arg, LLVMConstInt(LLVMInt8TypeInContext(self.context), 0, LLVM_FALSE), cstr!("arg_bool\0")),
+				_ => arg
+			};
+			self.build_push(&stack_ptrs, ty, arg);
+		}
+
+		let mut args = stack_ptrs;
+		LLVMBuildCall2(self.builder, fntype, fnvalue, args.as_mut_ptr(), 3, cstr!("\0"));
+		self.build_catch_return();
+
+		let ret = effect.pushed().front().map(|ty| {
+			let ret = self.build_pop(&stack_ptrs, ty);
+			match ty {
+				Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => LLVMBuildZExt(self.builder, ret, LLVMInt8TypeInContext(self.context), cstr!("ret_bool\0")),
+				_ => ret
+			}
+		});
+
+		// The stack may have been reallocated while the function ran
+		let bp = LLVMBuildLoad2(self.builder, ptr_ty, stack_ptrs[0], cstr!("bp\0"));
+		LLVMBuildCall2(self.builder, free_ty, free, [bp].as_mut_ptr(), 1, cstr!("\0"));
+
+		match ret {
+			Some(ret) => LLVMBuildRet(self.builder, ret),
+			None => LLVMBuildRetVoid(self.builder)
+		};
+	}
+
+	/// Compiles and adds the passed in function to the module, returning true on success and false if other elements that are needed are not
+	/// compiled yet
+	pub fn compile_function(&mut self, func: &TypedTreeNode) -> bool {
+		if let TypedTree::Function { name, effect, body, exported, attributes, doc: _ } = &func.tree {
+			eprintln!("Compiling function: {name}");
+
+			for node in body {
+				match &node.tree {
+					TypedTree::Word(name) | TypedTree::Literal { ty: _, value: Value { ty: _, inner: ValueInner::Function { fn_name: name } } } if !self.functions.contains_key(name) => {
+						return false;
+					}
+					_ => ()
+				}
+			}
+
+			let fntype = self.llvm_type(&Type::Function { name: name.to_string(), effect: effect.clone() });
+			unsafe {
+				let fnvalue = LLVMAddFunction(self.module, cstrv!(name), fntype);
+
+				for attribute in attributes {
+					match attribute {
+						Attribute::Inline => self.add_fn_attribute(fnvalue, "inlinehint"),
+						Attribute::NoInline => self.add_fn_attribute(fnvalue, "noinline"),
+						Attribute::Cold => self.add_fn_attribute(fnvalue, "cold"),
+						// Only used by the analyser
+						Attribute::MustUse | Attribute::Deprecated(_) => ()
+					}
+				}
+
+				let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+				LLVMPositionBuilderAtEnd(self.builder, block);
+
+				let bppv = LLVMGetParam(fnvalue, 0);
+				let sppv = LLVMGetParam(fnvalue, 1);
+				let eppv = LLVMGetParam(fnvalue, 2);
+				let mut bpv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), bppv, cstr!("bp\0"));
+				let mut spv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), sppv, cstr!("sp\0"));
+				let mut epv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), eppv, cstr!("ep\0"));
+
+				for node in body {
+					match &node.tree {
+						TypedTree::Word(word) => {
+							let (wordfn_type, wordfn) = self.functions.get(word).unwrap();
+
+							let mut wordargs = [
+								bppv,
+								sppv,
+								eppv
+							];
+							LLVMBuildCall2(self.builder, *wordfn_type, *wordfn, wordargs.as_mut_ptr(), 3, cstr!("\0"));
+							// Calling a function by name catches any return within it
+							self.build_catch_return();
+							bpv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), bppv, cstr!("bp\0"));
+							spv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), sppv, cstr!("sp\0"));
+							epv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), eppv, cstr!("ep\0"));
+						},
+						TypedTree::BuiltinWord { name: word, effect } => {
+							let wordfn = match self.builtins.get(word) {
+								Some(wordfn) => *wordfn,
+								None => self.define_builtin(word, effect).unwrap_or_else(|| panic!("No implementation of builtin {word} for compilation"))
+							};
+							let wordfn_type = LLVMGlobalGetValueType(wordfn);
+
+							let mut wordargs = [
+								bppv,
+								sppv,
+								eppv
+							];
+							LLVMBuildCall2(self.builder, wordfn_type, wordfn, wordargs.as_mut_ptr(), 3, cstr!("\0"));
+							// A return within a quotation called by call or try unwinds through this function too
+							if !effect.returns().is_empty() {
+								self.build_return_if_returning(fnvalue, &mut |_| ());
+							}
+							bpv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), bppv, cstr!("bp\0"));
+							spv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), sppv, cstr!("sp\0"));
+							epv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), eppv, cstr!("ep\0"));
+						},
+						TypedTree::Literal { ty, value } => {
+							let llvm_value = self.build_literal(ty, value);
+							self.build_push(&[bppv, sppv, eppv], ty, llvm_value);
+						},
+						TypedTree::Constructor { ty: ty @ Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String | OpaqueTypeKind::Map(_, _) }, effect: _ } => {
+							// Vectors, strings and maps start empty, with no allocation
+							let empty = LLVMConstNull(self.llvm_type(ty));
+							self.build_push(&[bppv, sppv, eppv], ty, empty);
+						},
+						TypedTree::Constructor { ty, effect } => todo!(),
+						TypedTree::FieldAccess { name, of } => {
+							let stack_ptrs = [bppv, sppv, eppv];
+							let (struct_ty, fields) = match of.deref() {
+								struct_ty @ Type::Transparent { name: _, fields, sum_type: false } => (struct_ty, fields),
+								_ => unreachable!()
+							};
+							let (field_index, field_ty) = fields.iter().enumerate().find_map(|(i, (fname, ftype))| if fname == name { Some((i, ftype)) } else { None }).expect("Field access of nonexistent field");
+
+							// Fields are read in place, either from the struct on top of the stack, or through the reference on top of the stack
+							// without copying the struct it points to
+							let struct_ptr = match of {
+								Type::Reference { to: _, mutable: _ } => {
+									let ref_ptr = self.build_top_ptr(&stack_ptrs, of);
+									let struct_ptr = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), ref_ptr, cstr!("struct_ptr\0"));
+									LLVMSetAlignment(struct_ptr, 1);
+									struct_ptr
+								}
+								_ => self.build_top_ptr(&stack_ptrs, of)
+							};
+
+							let struct_llvm_ty = self.llvm_type(struct_ty);
+							let field_ptr = LLVMBuildStructGEP2(self.builder, struct_llvm_ty, struct_ptr, field_index as u32, cstr!("field_ptr\0"));
+							let field = LLVMBuildLoad2(self.builder, self.llvm_type(field_ty), field_ptr, cstr!("field\0"));
+							LLVMSetAlignment(field, 1);
+							self.build_push(&stack_ptrs, field_ty, field);
+						},
+						TypedTree::Return => {
+							// The flag is left set until the return reaches the function called by name that it ends
+							let returning = self.returning_flag();
+							LLVMBuildStore(self.builder, LLVMConstInt(LLVMInt1TypeInContext(self.context), 1, LLVM_FALSE), returning);
+							LLVMBuildRetVoid(self.builder);
+
+							// Any words after the return are unreachable, but still need a block to be built in
+							let after_return_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("after_return\0"));
+							LLVMPositionBuilderAtEnd(self.builder, after_return_block);
+						},
+						_ => unreachable!()
+					}
+				}
+
+				LLVMBuildRetVoid(self.builder);
+
+				self.functions.insert(name.to_string(), (fntype, fnvalue));
+
+				if *exported {
+					self.compile_export(name, effect, fntype, fnvalue);
+				}
+
+				true
+			}
+		} else {
+			unreachable!();
+		}
+	}
+
+	/// Builds IR that pops a value of type ty off the tower stack, returning the popped value.
+	/// stack_ptrs are the bp_ptr, sp_ptr and ep_ptr parameters of the current function
+	unsafe fn build_pop(&mut self, stack_ptrs: &[LLVMValueRef; 3], ty: &Type) -> LLVMValueRef {
+		let llvm_ty = self.llvm_type(ty);
+		let size = LLVMSizeOf(llvm_ty);
+
+		let value_ptr = self.build_top_ptr(stack_ptrs, ty);
+		let value = LLVMBuildLoad2(self.builder, llvm_ty, value_ptr, cstr!("value\0"));
+		// Values are packed on the tower stack so are not necessarily aligned
+		LLVMSetAlignment(value, 1);
+
+		self.build_stack_call("__internal_spsub", stack_ptrs, size);
+
+		value
+	}
+
+	/// Builds IR that returns a pointer to the value of type ty on top of the tower stack, without popping it
+	unsafe fn build_top_ptr(&mut self, stack_ptrs: &[LLVMValueRef; 3], ty: &Type) -> LLVMValueRef {
+		let size = LLVMSizeOf(self.llvm_type(ty));
+
+		let sp = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), stack_ptrs[1], cstr!("sp\0"));
+		let mut gep_indices = [
+			LLVMBuildNeg(self.builder, size, cstr!("neg_size\0"))
+		];
+		LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), sp, gep_indices.as_mut_ptr(), 1, cstr!("value_ptr\0"))
+	}
+
+	/// Returns the global `__tower_returning` flag, which a return sets as it unwinds through quotation calls to the function called by
+	/// name that it ends, where it is cleared
+	unsafe fn returning_flag(&mut self) -> LLVMValueRef {
+		let i1_ty = LLVMInt1TypeInContext(self.context);
+		self.get_or_add_global("__tower_returning", i1_ty)
+	}
+
+	/// Builds IR that clears the returning flag after a call by name, which ends any return unwinding from the called function
+	unsafe fn build_catch_return(&mut self) {
+		let returning = self.returning_flag();
+		LLVMBuildStore(self.builder, LLVMConstInt(LLVMInt1TypeInContext(self.context), 0, LLVM_FALSE), returning);
+	}
+
+	/// Builds IR that returns from fnvalue after building on_unwind if the returning flag is set, continuing the return's unwinding, and
+	/// otherwise leaves the builder positioned to continue the function
+	unsafe fn build_return_if_returning(&mut self, fnvalue: LLVMValueRef, on_unwind: &mut dyn FnMut(&mut Self)) {
+		let returning = self.returning_flag();
+		let is_returning = LLVMBuildLoad2(self.builder, LLVMInt1TypeInContext(self.context), returning, cstr!("is_returning\0"));
+
+		let unwind_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("unwind_return\0"));
+		let continue_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("not_returning\0"));
+		LLVMBuildCondBr(self.builder, is_returning, unwind_block, continue_block);
+
+		LLVMPositionBuilderAtEnd(self.builder, unwind_block);
+		on_unwind(self);
+		LLVMBuildRetVoid(self.builder);
+
+		LLVMPositionBuilderAtEnd(self.builder, continue_block);
+	}
+
+	/// Builds IR that calls abort if cond is true, leaving the builder positioned in the block for when cond is false
+	unsafe fn build_abort_if(&mut self, fnvalue: LLVMValueRef, cond: LLVMValueRef) {
+		let abort_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), std::ptr::null_mut(), 0, LLVM_FALSE);
+		let abort = self.get_or_declare("abort", abort_ty);
+
+		let abort_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("abort\0"));
+		let ok_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("ok\0"));
+		LLVMBuildCondBr(self.builder, cond, abort_block, ok_block);
+
+		LLVMPositionBuilderAtEnd(self.builder, abort_block);
+		LLVMBuildCall2(self.builder, abort_ty, abort, std::ptr::null_mut(), 0, cstr!("\0"));
+		LLVMBuildUnreachable(self.builder);
+
+		LLVMPositionBuilderAtEnd(self.builder, ok_block);
+	}
+
+	/// Builds IR that pushes value, of type ty, onto the tower stack.
+	/// stack_ptrs are the bp_ptr, sp_ptr and ep_ptr parameters of the current function
+	unsafe fn build_push(&mut self, stack_ptrs: &[LLVMValueRef; 3], ty: &Type, value: LLVMValueRef) {
+		let llvm_ty = self.llvm_type(ty);
+		let size = LLVMSizeOf(llvm_ty);
+
+		// __internal_spadd may reallocate the stack, so the stack pointer has to be loaded afterwards
+		self.build_stack_call("__internal_spadd", stack_ptrs, size);
+
+		let sp = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), stack_ptrs[1], cstr!("sp\0"));
+		let mut gep_indices = [
+			LLVMBuildNeg(self.builder, size, cstr!("neg_size\0"))
+		];
+		let value_ptr = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), sp, gep_indices.as_mut_ptr(), 1, cstr!("value_ptr\0"));
+		let store = LLVMBuildStore(self.builder, value, value_ptr);
+		LLVMSetAlignment(store, 1);
+	}
+
+	/// Builds a call to one of the builtin stack pointer manipulation functions (__internal_spadd, __internal_spsub) with the passed-in byte count
+	unsafe fn build_stack_call(&mut self, internal_fn: &str, stack_ptrs: &[LLVMValueRef; 3], bytes: LLVMValueRef) {
+		let internal_fnvalue = *self.builtins.get(internal_fn).unwrap_or_else(|| panic!("Builtin module does not define {internal_fn}"));
+		let internal_fntype = LLVMGlobalGetValueType(internal_fnvalue);
+
+		let mut args = [
+			stack_ptrs[0],
+			stack_ptrs[1],
+			stack_ptrs[2],
+			bytes
+		];
+		LLVMBuildCall2(self.builder, internal_fntype, internal_fnvalue, args.as_mut_ptr(), 4, cstr!("\0"));
+	}
+
+	/// Returns an LLVM value for the passed-in literal value, of type ty. Literals owning heap memory, which are produced by const fns,
+	/// are given a new heap allocation each time the built IR is run
+	unsafe fn build_literal(&mut self, ty: &Type, value: &Value) -> LLVMValueRef {
+		match (&value.inner, ty) {
+			(ValueInner::Bytes(bytes), Type::Opaque { size: _, kind: OpaqueTypeKind::UnsignedInt | OpaqueTypeKind::SignedInt | OpaqueTypeKind::Bool | OpaqueTypeKind::Char }) => {
+				// NOTE: Assumes a little-endian host, as literal values are stored in native byte order
+				let mut buf = [0u8; 16];
+				buf[..bytes.len()].copy_from_slice(bytes);
+				let num = u128::from_le_bytes(buf);
+				let words = [num as u64, (num >> 64) as u64];
+
+				LLVMConstIntOfArbitraryPrecision(self.llvm_type(ty), 2, words.as_ptr())
+			}
+			(ValueInner::Array(elems), Type::Opaque { size: _, kind: OpaqueTypeKind::Array(elem_ty) }) if elem_ty.is_copyable() => {
+				let mut llvm_elems: Vec<LLVMValueRef> = elems.iter().map(|elem| self.build_literal(elem_ty, elem)).collect();
+
+				LLVMConstArray2(self.llvm_type(elem_ty), llvm_elems.as_mut_ptr(), llvm_elems.len() as u64)
+			}
+			// Elements owning heap memory aren't constants, so are inserted one by one
+			(ValueInner::Array(elems), Type::Opaque { size: _, kind: OpaqueTypeKind::Array(elem_ty) }) => {
+				let mut array = LLVMGetUndef(self.llvm_type(ty));
+				for (i, elem) in elems.iter().enumerate() {
+					let llvm_elem = self.build_literal(elem_ty, elem);
+					array = LLVMBuildInsertValue(self.builder, array, llvm_elem, i as u32, cstr!("array\0"));
+				}
+
+				array
+			}
+			// Struct values hold their fields in reverse order, as they're popped off the stack in reverse order when constructed
+			(ValueInner::Struct(values), Type::Transparent { name: _, fields, sum_type: false }) => {
+				let mut llvm_struct = LLVMGetUndef(self.llvm_type(ty));
+				for (i, ((_, field_ty), field)) in fields.iter().zip(values.iter().rev()).enumerate() {
+					let llvm_field = self.build_literal(field_ty, field);
+					llvm_struct = LLVMBuildInsertValue(self.builder, llvm_struct, llvm_field, i as u32, cstr!("struct\0"));
+				}
+
+				llvm_struct
+			}
+			(ValueInner::String { bytes }, Type::Opaque { size: _, kind: OpaqueTypeKind::String }) => {
+				let bytes = bytes.borrow();
+				let i64_ty = LLVMInt64TypeInContext(self.context);
+				let len = LLVMConstInt(i64_ty, bytes.len() as u64, LLVM_FALSE);
+
+				// Empty strings have no allocation, like those made with the String constructor
+				let data = if bytes.is_empty() {
+					LLVMConstNull(LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC))
+				} else {
+					let contents = LLVMConstStringInContext(self.context, bytes.as_ptr() as *const i8, bytes.len() as u32, LLVM_TRUE);
+					let global = LLVMAddGlobal(self.module, LLVMTypeOf(contents), cstr!("string_lit\0"));
+					LLVMSetInitializer(global, contents);
+					LLVMSetGlobalConstant(global, LLVM_TRUE);
+					LLVMSetLinkage(global, llvm_sys::LLVMLinkage::LLVMPrivateLinkage);
+
+					self.build_heap_copy(global, len, len)
+				};
+
+				self.build_heap_struct(ty, data, len)
+			}
+			(ValueInner::Vector { elems }, Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(elem_ty) }) => {
+				let elems = elems.borrow();
+				let i64_ty = LLVMInt64TypeInContext(self.context);
+				let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+				let len = LLVMConstInt(i64_ty, elems.len() as u64, LLVM_FALSE);
+
+				let data = if elems.is_empty() {
+					LLVMConstNull(ptr_ty)
+				} else {
+					let elem_llvm_ty = self.llvm_type(elem_ty);
+					let elem_size = LLVMSizeOf(elem_llvm_ty);
+
+					let mut malloc_params = [
+						i64_ty
+					];
+					let malloc_ty = LLVMFunctionType(ptr_ty, malloc_params.as_mut_ptr(), 1, LLVM_FALSE);
+					let malloc = self.get_or_declare("malloc", malloc_ty);
+					let data = LLVMBuildCall2(self.builder, malloc_ty, malloc, [LLVMBuildMul(self.builder, len, elem_size, cstr!("data_size\0"))].as_mut_ptr(), 1, cstr!("data\0"));
+
+					for (i, elem) in elems.iter().enumerate() {
+						let llvm_elem = self.build_literal(elem_ty, elem);
+						let elem_ptr = self.build_byte_offset(data, LLVMConstInt(i64_ty, i as u64, LLVM_FALSE), elem_size);
+						let store = LLVMBuildStore(self.builder, llvm_elem, elem_ptr);
+						LLVMSetAlignment(store, 1);
+					}
+
+					data
+				};
+
+				self.build_heap_struct(ty, data, len)
+			}
+			// Function references are pointers to the compiled function
+			(ValueInner::Function { fn_name }, Type::Reference { to: _, mutable: false }) => self.functions.get(fn_name).expect("Expected referenced function to be compiled").1,
+			(ValueInner::Reference { to }, Type::Reference { to: _, mutable: _ }) => {
+				match &to.inner {
+					ValueInner::Bytes(bytes) => {
+						// Strings are stored null-terminated so they can be handed straight to libc. The array is sized from the bytes
+						// rather than read as a C string, so null bytes within the literal are kept
+						let contents = LLVMConstStringInContext(self.context, bytes.as_ptr() as *const i8, bytes.len() as u32, LLVM_FALSE);
+						let global = LLVMAddGlobal(self.module, LLVMTypeOf(contents), cstr!("str_lit\0"));
+						LLVMSetInitializer(global, contents);
+						LLVMSetGlobalConstant(global, LLVM_TRUE);
+						LLVMSetLinkage(global, llvm_sys::LLVMLinkage::LLVMPrivateLinkage);
+						LLVMSetUnnamedAddress(global, llvm_sys::LLVMUnnamedAddr::LLVMGlobalUnnamedAddr);
+
+						global
+					}
+					_ => unreachable!("References to values other than strs are rejected as literals by the analyser")
+				}
+			}
+			_ => unreachable!("Literals of type {ty} are rejected by the analyser")
+		}
+	}
+
+	/// Builds the { data, len, cap } value of the vector or string type ty, with data holding len elements and no spare capacity
+	unsafe fn build_heap_struct(&mut self, ty: &Type, data: LLVMValueRef, len: LLVMValueRef) -> LLVMValueRef {
+		let heap_value = LLVMGetUndef(self.llvm_type(ty));
+		let heap_value = LLVMBuildInsertValue(self.builder, heap_value, data, 0, cstr!("heap_value\0"));
+		let heap_value = LLVMBuildInsertValue(self.builder, heap_value, len, 1, cstr!("heap_value\0"));
+
+		LLVMBuildInsertValue(self.builder, heap_value, len, 2, cstr!("heap_value\0"))
+	}
+
+	/// Returns the function named name in the module, declaring it with fntype if it has not been declared yet
+	unsafe fn get_or_declare(&mut self, name: &str, fntype: LLVMTypeRef) -> LLVMValueRef {
+		let cname = CString::new(name).unwrap();
+		let existing = LLVMGetNamedFunction(self.module, cname.as_ptr());
+
+		if !existing.is_null() {
+			existing
+		} else {
+			LLVMAddFunction(self.module, cname.as_ptr(), fntype)
+		}
+	}
+
+	/// Returns the global variable named name in the module, adding it zero-initialised with type ty if it has not been added yet
+	unsafe fn get_or_add_global(&mut self, name: &str, ty: LLVMTypeRef) -> LLVMValueRef {
+		let cname = CString::new(name).unwrap();
+		let existing = LLVMGetNamedGlobal(self.module, cname.as_ptr());
+
+		if !existing.is_null() {
+			existing
+		} else {
+			let global = LLVMAddGlobal(self.module, ty, cname.as_ptr());
+			LLVMSetInitializer(global, LLVMConstNull(ty));
+			global
+		}
+	}
+
+	/// Adds the LLVM function attribute named attr_name (e.g. `noreturn`) to the function fnvalue
+	unsafe fn add_fn_attribute(&mut self, fnvalue: LLVMValueRef, attr_name: &str) {
+		let kind = LLVMGetEnumAttributeKindForName(attr_name.as_ptr() as *const i8, attr_name.len());
+		let attr = LLVMCreateEnumAttribute(self.context, kind, 0);
+		LLVMAddAttributeAtIndex(fnvalue, llvm_sys::LLVMAttributeFunctionIndex, attr);
+	}
+
+	/// Defines the builtin word implementation named word (e.g. `__add_u32`) in the module, for implementations that are not provided
+	/// by the builtin module. effect is the stack effect the builtin is instantiated with.
+	/// Returns None if word is not of the form `__{op}_{type}` or there is no such operation
+	fn define_builtin(&mut self, word: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		if let Some(op) = word.strip_prefix("__array_") {
+			return self.define_array_builtin(word, op, effect);
+		}
+
+		if ["__call", "__curry", "__compose"].contains(&word) {
+			return self.define_quotation_builtin(word, effect);
+		}
+
+		if ["__try", "__throw"].contains(&word) {
+			return self.define_error_builtin(word, effect);
+		}
+
+		if ["__dup", "__drop", "__clone", "__size_of"].contains(&word) {
+			return self.define_stack_builtin(word, effect);
+		}
+
+		if ["__box", "__cell", "__deref", "__deref_mut", "__store"].contains(&word) {
+			return self.define_reference_builtin(word, effect);
+		}
+
+		if let Some(op) = word.strip_prefix("__map_") {
+			return self.define_map_builtin(word, op, effect);
+		}
+
+		if word.starts_with("__vec_") || word.starts_with("__string_") || word.ends_with("_string") || word == "__read_line" {
+			return self.define_heap_builtin(word, effect);
+		}
+
+		let (op, tname) = word.strip_prefix("__")?.split_once('_')?;
+		let ty = match tname {
+			"str" => Type::new_strref(None),
+			_ => Type::from_name(tname)?
+		};
+		let (_, kind) = ty.deref().as_opaque()?;
+		let signed = *kind == OpaqueTypeKind::SignedInt;
+
+		let fntype = self.llvm_type(&Type::Function { name: word.to_string(), effect: StackEffect::none() });
+
+		unsafe {
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(word), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			match op {
+				"print" | "println" => {
+					let i32_ty = LLVMInt32TypeInContext(self.context);
+					let mut printf_params = [
+						LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC)
+					];
+					let printf_ty = LLVMFunctionType(i32_ty, printf_params.as_mut_ptr(), 1, LLVM_TRUE);
+					let printf = self.get_or_declare("printf", printf_ty);
+
+					let value = self.build_pop(&stack_ptrs, &ty);
+					let newline = if op == "println" { "\n" } else { "" };
+
+					let mut printf_args = if *ty.deref().as_opaque()?.0 == Some(16) {
+						self.build_int128_printf_args(value, signed, newline)
+					} else {
+						let (fmt, arg) = match (kind, ty.deref().as_opaque()?.0) {
+							(OpaqueTypeKind::Str, _) => ("%s", value),
+							(OpaqueTypeKind::Bool, _) => {
+								let true_str = LLVMBuildGlobalStringPtr(self.builder, cstr!("true\0"), cstr!("true_str\0"));
+								let false_str = LLVMBuildGlobalStringPtr(self.builder, cstr!("false\0"), cstr!("false_str\0"));
+								("%s", LLVMBuildSelect(self.builder, value, true_str, false_str, cstr!("bool_str\0")))
+							}
+							(OpaqueTypeKind::Char, _) => ("%s", self.build_utf8_encode(value)),
+							(OpaqueTypeKind::SignedInt, Some(8)) => ("%ld", value),
+							(OpaqueTypeKind::UnsignedInt, Some(8)) => ("%lu", value),
+							// Smaller integers are promoted to int, as they would be when passed to a C variadic function
+							(OpaqueTypeKind::SignedInt, Some(size)) if *size < 8 => ("%d", LLVMBuildSExt(self.builder, value, i32_ty, cstr!("promoted\0"))),
+							(OpaqueTypeKind::UnsignedInt, Some(size)) if *size < 8 => ("%u", LLVMBuildZExt(self.builder, value, i32_ty, cstr!("promoted\0"))),
+							_ => unreachable!("Only strs, bools, chars and integers have print builtins")
+						};
+						let fmt_str = LLVMBuildGlobalStringPtr(self.builder, cstrv!(format!("{fmt}{newline}")), cstr!("fmt\0"));
+						vec![fmt_str, arg]
+					};
+					LLVMBuildCall2(self.builder, printf_ty, printf, printf_args.as_mut_ptr(), printf_args.len() as u32, cstr!("\0"));
+				}
+				// Chars have the same representation as their u32 code points
+				"ord" => {
+					let value = self.build_pop(&stack_ptrs, &ty);
+					self.build_push(&stack_ptrs, &Type::new_uint(32), value);
+				}
+				"chr" => {
+					// Surrogates and values above the maximum code point aren't chars, so abort, like the interpreter errors
+					let value = self.build_pop(&stack_ptrs, &ty);
+					let i32_ty = LLVMInt32TypeInContext(self.context);
+					let surrogate_offset = LLVMBuildSub(self.builder, value, LLVMConstInt(i32_ty, 0xD800, LLVM_FALSE), cstr!("surrogate_offset\0"));
+					let is_surrogate = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntULT, surrogate_offset, LLVMConstInt(i32_ty, 0x800, LLVM_FALSE), cstr!("is_surrogate\0"));
+					let too_large = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntUGT, value, LLVMConstInt(i32_ty, char::MAX as u64, LLVM_FALSE), cstr!("too_large\0"));
+					let invalid = LLVMBuildOr(self.builder, is_surrogate, too_large, cstr!("invalid\0"));
+					self.build_abort_if(fnvalue, invalid);
+
+					self.build_push(&stack_ptrs, &Type::new_char(), value);
+				}
+				"add" | "sub" | "mul" | "div" | "rem" | "eq" | "ne" | "lt" | "le" | "gt" | "ge" => {
+					let rhs = self.build_pop(&stack_ptrs, &ty);
+					let lhs = self.build_pop(&stack_ptrs, &ty);
+
+					if op == "div" || op == "rem" {
+						// Division by zero is undefined behaviour in LLVM, so abort instead, like the interpreter errors
+						let is_zero = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, rhs, LLVMConstInt(LLVMTypeOf(rhs), 0, LLVM_FALSE), cstr!("is_zero\0"));
+						self.build_abort_if(fnvalue, is_zero);
+					}
+
+					let (result, result_ty) = match op {
+						"add" => (LLVMBuildAdd(self.builder, lhs, rhs, cstr!("result\0")), ty.clone()),
+						"sub" => (LLVMBuildSub(self.builder, lhs, rhs, cstr!("result\0")), ty.clone()),
+						"mul" => (LLVMBuildMul(self.builder, lhs, rhs, cstr!("result\0")), ty.clone()),
+						"div" | "rem" if signed => {
+							// Dividing the minimum value by -1 overflows, which is undefined behaviour in LLVM, so divide by 1 instead and wrap
+							// the result like the interpreter does: x / -1 is -x and x % -1 is 0
+							let int_ty = LLVMTypeOf(rhs);
+							let is_minus_one = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, rhs, LLVMConstAllOnes(int_ty), cstr!("is_minus_one\0"));
+							let safe_rhs = LLVMBuildSelect(self.builder, is_minus_one, LLVMConstInt(int_ty, 1, LLVM_FALSE), rhs, cstr!("safe_rhs\0"));
+							let result = if op == "div" {
+								let quotient = LLVMBuildSDiv(self.builder, lhs, safe_rhs, cstr!("quotient\0"));
+								let negated = LLVMBuildSub(self.builder, LLVMConstInt(int_ty, 0, LLVM_FALSE), lhs, cstr!("negated\0"));
+								LLVMBuildSelect(self.builder, is_minus_one, negated, quotient, cstr!("result\0"))
+							} else {
+								let remainder = LLVMBuildSRem(self.builder, lhs, safe_rhs, cstr!("remainder\0"));
+								LLVMBuildSelect(self.builder, is_minus_one, LLVMConstInt(int_ty, 0, LLVM_FALSE), remainder, cstr!("result\0"))
+							};
+							(result, ty.clone())
+						}
+						"div" => (LLVMBuildUDiv(self.builder, lhs, rhs, cstr!("result\0")), ty.clone()),
+						"rem" => (LLVMBuildURem(self.builder, lhs, rhs, cstr!("result\0")), ty.clone()),
+						_ => {
+							use llvm_sys::LLVMIntPredicate::*;
+
+							let predicate = match (op, signed) {
+								("eq", _) => LLVMIntEQ,
+								("ne", _) => LLVMIntNE,
+								("lt", true) => LLVMIntSLT,
+								("lt", false) => LLVMIntULT,
+								("le", true) => LLVMIntSLE,
+								("le", false) => LLVMIntULE,
+								("gt", true) => LLVMIntSGT,
+								("gt", false) => LLVMIntUGT,
+								("ge", true) => LLVMIntSGE,
+								("ge", false) => LLVMIntUGE,
+								_ => unreachable!()
+							};
+
+							(LLVMBuildICmp(self.builder, predicate, lhs, rhs, cstr!("result\0")), Type::new_bool())
+						}
+					};
+
+					self.build_push(&stack_ptrs, &result_ty, result);
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(word.to_string(), fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Builds the printf arguments for printing the 128-bit integer value, which printf has no format for. Its magnitude is split into
+	/// up to three base 10^19 digits that each fit in a u64, and the format printing just the digits needed is picked at runtime. Unused
+	/// trailing arguments are ignored by printf
+	unsafe fn build_int128_printf_args(&mut self, value: LLVMValueRef, signed: bool, newline: &str) -> Vec<LLVMValueRef> {
+		let i128_ty = LLVMTypeOf(value);
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+		let zero = LLVMConstInt(i128_ty, 0, LLVM_FALSE);
+		let zero64 = LLVMConstInt(i64_ty, 0, LLVM_FALSE);
+		let e19 = LLVMConstInt(i128_ty, 10_000_000_000_000_000_000, LLVM_FALSE);
+
+		let (sign, magnitude) = if signed {
+			let is_negative = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntSLT, value, zero, cstr!("is_negative\0"));
+			let negated = LLVMBuildSub(self.builder, zero, value, cstr!("negated\0"));
+			let minus_str = LLVMBuildGlobalStringPtr(self.builder, cstr!("-\0"), cstr!("minus_str\0"));
+			let empty_str = LLVMBuildGlobalStringPtr(self.builder, cstr!("\0"), cstr!("empty_str\0"));
+			(
+				LLVMBuildSelect(self.builder, is_negative, minus_str, empty_str, cstr!("sign\0")),
+				// The magnitude of the minimum value is still correct when treated as unsigned
+				LLVMBuildSelect(self.builder, is_negative, negated, value, cstr!("magnitude\0"))
+			)
+		} else {
+			(LLVMBuildGlobalStringPtr(self.builder, cstr!("\0"), cstr!("empty_str\0")), value)
+		};
+
+		let low = LLVMBuildTrunc(self.builder, LLVMBuildURem(self.builder, magnitude, e19, cstr!("low128\0")), i64_ty, cstr!("low\0"));
+		let rest = LLVMBuildUDiv(self.builder, magnitude, e19, cstr!("rest\0"));
+		let mid = LLVMBuildTrunc(self.builder, LLVMBuildURem(self.builder, rest, e19, cstr!("mid128\0")), i64_ty, cstr!("mid\0"));
+		let top = LLVMBuildTrunc(self.builder, LLVMBuildUDiv(self.builder, rest, e19, cstr!("top128\0")), i64_ty, cstr!("top\0"));
+
+		let has_top = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntNE, top, zero64, cstr!("has_top\0"));
+		let has_mid = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntNE, rest, zero, cstr!("has_mid\0"));
+
+		let one_digit_fmt = LLVMBuildGlobalStringPtr(self.builder, cstrv!(format!("%s%lu{newline}")), cstr!("fmt\0"));
+		let two_digit_fmt = LLVMBuildGlobalStringPtr(self.builder, cstrv!(format!("%s%lu%019lu{newline}")), cstr!("fmt\0"));
+		let three_digit_fmt = LLVMBuildGlobalStringPtr(self.builder, cstrv!(format!("%s%lu%019lu%019lu{newline}")), cstr!("fmt\0"));
+		let fmt = LLVMBuildSelect(self.builder, has_mid, two_digit_fmt, one_digit_fmt, cstr!("fmt\0"));
+		let fmt = LLVMBuildSelect(self.builder, has_top, three_digit_fmt, fmt, cstr!("fmt\0"));
+
+		let first = LLVMBuildSelect(self.builder, has_mid, mid, low, cstr!("first\0"));
+		let first = LLVMBuildSelect(self.builder, has_top, top, first, cstr!("first\0"));
+		let second = LLVMBuildSelect(self.builder, has_top, mid, low, cstr!("second\0"));
+
+		vec![fmt, sign, first, second, low]
+	}
+
+	/// Builds IR that encodes the char value as UTF-8 into a stack-allocated buffer, returning a pointer to the nul-terminated bytes
+	unsafe fn build_utf8_encode(&mut self, value: LLVMValueRef) -> LLVMValueRef {
+		let i8_ty = LLVMInt8TypeInContext(self.context);
+		let i32_ty = LLVMInt32TypeInContext(self.context);
+		let const_i32 = |n: u64| LLVMConstInt(i32_ty, n, LLVM_FALSE);
+		let buf = LLVMBuildAlloca(self.builder, LLVMArrayType2(i8_ty, 5), cstr!("utf8_buf\0"));
+
+		// The number of bytes the code point is encoded with
+		let mut len = const_i32(4);
+		for (limit, limit_len) in [(0x10000, 3), (0x800, 2), (0x80, 1)] {
+			let below = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntULT, value, const_i32(limit), cstr!("below\0"));
+			len = LLVMBuildSelect(self.builder, below, const_i32(limit_len), len, cstr!("len\0"));
+		}
+
+		// The first byte holds the length prefix and the highest bits, and each following byte the next 6 bits
+		let lead_shift = LLVMBuildMul(self.builder, LLVMBuildSub(self.builder, len, const_i32(1), cstr!("\0")), const_i32(6), cstr!("lead_shift\0"));
+		let lead_bits = LLVMBuildLShr(self.builder, value, lead_shift, cstr!("lead_bits\0"));
+		let prefix = LLVMBuildLShr(self.builder, const_i32(0xF00), len, cstr!("prefix\0"));
+		let is_ascii = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, len, const_i32(1), cstr!("is_ascii\0"));
+		let prefix = LLVMBuildSelect(self.builder, is_ascii, const_i32(0), LLVMBuildAnd(self.builder, prefix, const_i32(0xF0), cstr!("\0")), cstr!("prefix\0"));
+		let lead = LLVMBuildOr(self.builder, prefix, lead_bits, cstr!("lead\0"));
+		LLVMBuildStore(self.builder, LLVMBuildTrunc(self.builder, lead, i8_ty, cstr!("\0")), buf);
+
+		for i in 1..4 {
+			// Bytes past the end are overwritten by the nul terminator, so their shift is clamped to stay defined
+			let remaining = LLVMBuildSub(self.builder, len, const_i32(i + 1), cstr!("remaining\0"));
+			let in_range = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntSGE, remaining, const_i32(0), cstr!("in_range\0"));
+			let shift = LLVMBuildSelect(self.builder, in_range, LLVMBuildMul(self.builder, remaining, const_i32(6), cstr!("\0")), const_i32(0), cstr!("shift\0"));
+			let bits = LLVMBuildAnd(self.builder, LLVMBuildLShr(self.builder, value, shift, cstr!("\0")), const_i32(0x3F), cstr!("bits\0"));
+			let byte = LLVMBuildOr(self.builder, bits, const_i32(0x80), cstr!("byte\0"));
+			let byte_ptr = LLVMBuildInBoundsGEP2(self.builder, i8_ty, buf, [const_i32(i)].as_mut_ptr(), 1, cstr!("byte_ptr\0"));
+			LLVMBuildStore(self.builder, LLVMBuildTrunc(self.builder, byte, i8_ty, cstr!("\0")), byte_ptr);
+		}
+
+		let end_ptr = LLVMBuildInBoundsGEP2(self.builder, i8_ty, buf, [len].as_mut_ptr(), 1, cstr!("end_ptr\0"));
+		LLVMBuildStore(self.builder, LLVMConstInt(i8_ty, 0, LLVM_FALSE), end_ptr);
+
+		buf
+	}
+
+	/// Defines the array builtin word (e.g. `__array_get`) for the array type it is instantiated with in effect. As the array operations
+	/// are generic, a function is defined for each array type, named e.g. `__array_get<[u32; 4]>`
+	fn define_array_builtin(&mut self, word: &str, op: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let array_ty = effect.popped().iter().find(|ty| ty.as_array().is_some())?.clone();
+		let (elem_ty, len) = array_ty.as_array()?;
+		let (elem_ty, len) = (elem_ty.clone(), (*len)?);
+
+		let fnname = format!("{word}<{}>", array_ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			let index_ty = Type::new_uint(32);
+			let array_llvm_ty = self.llvm_type(&array_ty);
+			let elem_llvm_ty = self.llvm_type(&elem_ty);
+
+			match op {
+				"get" | "set" => {
+					let elem = if op == "set" {
+						Some(self.build_pop(&stack_ptrs, &elem_ty))
+					} else {
+						None
+					};
+					let index = self.build_pop(&stack_ptrs, &index_ty);
+
+					let out_of_bounds = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntUGE, index, LLVMConstInt(LLVMTypeOf(index), len as u64, LLVM_FALSE), cstr!("out_of_bounds\0"));
+					self.build_abort_if(fnvalue, out_of_bounds);
+
+					// The array is left on the stack and accessed in place
+					let array_ptr = self.build_top_ptr(&stack_ptrs, &array_ty);
+					let mut gep_indices = [
+						LLVMConstInt(LLVMInt32TypeInContext(self.context), 0, LLVM_FALSE),
+						index
+					];
+					let elem_ptr = LLVMBuildGEP2(self.builder, array_llvm_ty, array_ptr, gep_indices.as_mut_ptr(), 2, cstr!("elem_ptr\0"));
+
+					match elem {
+						Some(elem) => {
+							// The replaced element is dropped
+							if let Some(destructor) = self.define_destructor(&elem_ty) {
+								self.build_fn_call(destructor, &mut [elem_ptr]);
+							}
+
+							let store = LLVMBuildStore(self.builder, elem, elem_ptr);
+							LLVMSetAlignment(store, 1);
+						}
+						None => {
+							let elem = LLVMBuildLoad2(self.builder, elem_llvm_ty, elem_ptr, cstr!("elem\0"));
+							LLVMSetAlignment(elem, 1);
+							self.build_push(&stack_ptrs, &elem_ty, elem);
+						}
+					}
+				}
+				"len" => {
+					let len_value = LLVMConstInt(self.llvm_type(&index_ty), len as u64, LLVM_FALSE);
+					self.build_push(&stack_ptrs, &index_ty, len_value);
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(fnname, fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Defines the vector or string builtin word (e.g. `__vec_push`, `__string_len`, `__read_line`) in the module. Vector operations are
+	/// generic, so a function is defined for each vector type, named e.g. `__vec_push<Vec<u32>>`. The operations themselves are
+	/// implemented by the `__internal_vec_*` helpers in the builtin module, which work on any element size.
+	fn define_heap_builtin(&mut self, word: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let (heap_ty, elem_ty) = match effect.popped().iter().chain(effect.pushed().iter()).find_map(|ty| ty.as_vec().map(|elem| (ty, elem))) {
+			Some((vec_ty, elem_ty)) => (vec_ty.clone(), elem_ty.clone()),
+			None => (Type::new_string(), Type::new_uint(8))
+		};
+
+		let fnname = match heap_ty.as_vec() {
+			Some(_) => format!("{word}<{}>", heap_ty.name()),
+			None => word.to_string()
+		};
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let op = word.strip_prefix("__vec_").or(word.strip_prefix("__string_")).unwrap_or(word);
+
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			let index_ty = Type::new_uint(32);
+			let i64_ty = LLVMInt64TypeInContext(self.context);
+			let elem_llvm_ty = self.llvm_type(&elem_ty);
+			let elem_size = LLVMSizeOf(elem_llvm_ty);
+
+			match op {
+				"push" => {
+					let elem = self.build_pop(&stack_ptrs, &elem_ty);
+					if heap_ty.as_vec().is_none() {
+						// Only ASCII bytes are whole chars, so pushing any other byte could leave the string invalid UTF-8
+						let non_ascii = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntUGE, elem, LLVMConstInt(elem_llvm_ty, 0x80, LLVM_FALSE), cstr!("non_ascii\0"));
+						self.build_abort_if(fnvalue, non_ascii);
+					}
+
+					let elem_ptr = LLVMBuildAlloca(self.builder, elem_llvm_ty, cstr!("elem_ptr\0"));
+					LLVMBuildStore(self.builder, elem, elem_ptr);
+
+					let heap_ptr = self.build_top_ptr(&stack_ptrs, &heap_ty);
+					self.build_internal_call("__internal_vec_push", &mut [heap_ptr, elem_ptr, elem_size]);
+				}
+				"pop" => {
+					let elem_ptr = LLVMBuildAlloca(self.builder, elem_llvm_ty, cstr!("elem_ptr\0"));
+
+					let heap_ptr = self.build_top_ptr(&stack_ptrs, &heap_ty);
+					let popped = self.build_internal_call("__internal_vec_pop", &mut [heap_ptr, elem_ptr, elem_size]);
+					let empty = LLVMBuildNot(self.builder, popped, cstr!("empty\0"));
+					self.build_abort_if(fnvalue, empty);
+
+					let elem = LLVMBuildLoad2(self.builder, elem_llvm_ty, elem_ptr, cstr!("elem\0"));
+					if heap_ty.as_vec().is_none() {
+						// Popping a byte of a multi-byte char would leave the rest of it behind
+						let non_ascii = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntUGE, elem, LLVMConstInt(elem_llvm_ty, 0x80, LLVM_FALSE), cstr!("non_ascii\0"));
+						self.build_abort_if(fnvalue, non_ascii);
+					}
+					self.build_push(&stack_ptrs, &elem_ty, elem);
+				}
+				"get" => {
+					let index = self.build_pop(&stack_ptrs, &index_ty);
+					let index = LLVMBuildZExt(self.builder, index, i64_ty, cstr!("index\0"));
+
+					let heap_ptr = self.build_top_ptr(&stack_ptrs, &heap_ty);
+					let elem_ptr = self.build_internal_call("__internal_vec_get", &mut [heap_ptr, index, elem_size]);
+					let out_of_bounds = LLVMBuildIsNull(self.builder, elem_ptr, cstr!("out_of_bounds\0"));
+					self.build_abort_if(fnvalue, out_of_bounds);
+
+					let elem = LLVMBuildLoad2(self.builder, elem_llvm_ty, elem_ptr, cstr!("elem\0"));
+					LLVMSetAlignment(elem, 1);
+					self.build_push(&stack_ptrs, &elem_ty, elem);
+				}
+				"len" => {
+					let heap_llvm_ty = self.llvm_type(&heap_ty);
+					let heap_ptr = self.build_top_ptr(&stack_ptrs, &heap_ty);
+					let len_ptr = LLVMBuildStructGEP2(self.builder, heap_llvm_ty, heap_ptr, 1, cstr!("len_ptr\0"));
+					let len = LLVMBuildLoad2(self.builder, i64_ty, len_ptr, cstr!("len\0"));
+					LLVMSetAlignment(len, 1);
+
+					let len = LLVMBuildTrunc(self.builder, len, self.llvm_type(&index_ty), cstr!("len\0"));
+					self.build_push(&stack_ptrs, &index_ty, len);
+				}
+				"concat" => {
+					let rhs = self.build_pop(&stack_ptrs, &heap_ty);
+					let rhs_ptr = LLVMBuildAlloca(self.builder, self.llvm_type(&heap_ty), cstr!("rhs_ptr\0"));
+					LLVMBuildStore(self.builder, rhs, rhs_ptr);
+
+					let heap_ptr = self.build_top_ptr(&stack_ptrs, &heap_ty);
+					self.build_internal_call("__internal_vec_concat", &mut [heap_ptr, rhs_ptr, elem_size]);
+				}
+				"from_str" | "__read_line" => {
+					let str_value = if op == "from_str" {
+						Some(self.build_pop(&stack_ptrs, &Type::new_strref(None)))
+					} else {
+						None
+					};
+
+					let empty = LLVMConstNull(self.llvm_type(&heap_ty));
+					self.build_push(&stack_ptrs, &heap_ty, empty);
+					let heap_ptr = self.build_top_ptr(&stack_ptrs, &heap_ty);
+
+					match str_value {
+						Some(str_value) => self.build_internal_call("__internal_string_push_str", &mut [heap_ptr, str_value]),
+						None => self.build_internal_call("__internal_read_line", &mut [heap_ptr])
+					};
+				}
+				"__print_string" | "__println_string" => {
+					let mut printf_params = [
+						LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC)
+					];
+					let printf_ty = LLVMFunctionType(LLVMInt32TypeInContext(self.context), printf_params.as_mut_ptr(), 1, LLVM_TRUE);
+					let printf = self.get_or_declare("printf", printf_ty);
+
+					let string = self.build_pop(&stack_ptrs, &heap_ty);
+					let data = LLVMBuildExtractValue(self.builder, string, 0, cstr!("data\0"));
+					let len = LLVMBuildExtractValue(self.builder, string, 1, cstr!("len\0"));
+					let len = LLVMBuildTrunc(self.builder, len, LLVMInt32TypeInContext(self.context), cstr!("len\0"));
+
+					let newline = if op == "__println_string" { "\n" } else { "" };
+					let fmt_str = LLVMBuildGlobalStringPtr(self.builder, cstrv!(format!("%.*s{newline}")), cstr!("fmt\0"));
+
+					let mut printf_args = [
+						fmt_str,
+						len,
+						data
+					];
+					LLVMBuildCall2(self.builder, printf_ty, printf, printf_args.as_mut_ptr(), 3, cstr!("\0"));
+
+					let mut free_params = [
+						LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC)
+					];
+					let free_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), free_params.as_mut_ptr(), 1, LLVM_FALSE);
+					let free = self.get_or_declare("free", free_ty);
+					let mut free_args = [
+						data
+					];
+					LLVMBuildCall2(self.builder, free_ty, free, free_args.as_mut_ptr(), 1, cstr!("\0"));
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(fnname, fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Defines the quotation builtin word (`__call`, `__curry` or `__compose`) for the quotation types it is instantiated with in effect,
+	/// named e.g. `__curry<u32, &add>`. Calling a closure consumes it, so its environment is freed once it has been called
+	fn define_quotation_builtin(&mut self, word: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let fnname = format!("{word}<{}>", effect.popped().iter().rev().map(Type::name).collect::<Vec<String>>().join(", "));
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			let quotation_ty = effect.popped()[0].clone();
+
+			match word {
+				"__call" => {
+					let quotation = self.build_pop(&stack_ptrs, &quotation_ty);
+					self.build_call_quotation(&stack_ptrs, &quotation_ty, quotation);
+				}
+				"__curry" => {
+					let value_ty = effect.popped()[1].clone();
+
+					let quotation = self.build_pop(&stack_ptrs, &quotation_ty);
+					let value = self.build_pop(&stack_ptrs, &value_ty);
+
+					let closure = self.build_closure(&[(value_ty, value)], &[(quotation_ty, quotation)]);
+					self.build_push(&stack_ptrs, &effect.pushed()[0], closure);
+				}
+				"__compose" => {
+					let first_ty = effect.popped()[1].clone();
+
+					let second = self.build_pop(&stack_ptrs, &quotation_ty);
+					let first = self.build_pop(&stack_ptrs, &first_ty);
+
+					let closure = self.build_closure(&[], &[(first_ty, first), (quotation_ty, second)]);
+					self.build_push(&stack_ptrs, &effect.pushed()[0], closure);
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(fnname, fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Defines the error handling builtin word `__throw`, or `__try` for the quotation types it is instantiated with in effect, named e.g.
+	/// `__try<&parse, &recover>`. try sets up a handler with setjmp, which throw longjmps to, after storing the thrown String in the
+	/// global `__tower_error`. The innermost handler's jmp_buf is pointed to by the global `__tower_handler`, which is null outside any try.
+	/// NOTE: Values left on the stack above the body's arguments when the body throws are leaked, as their types aren't known
+	fn define_error_builtin(&mut self, word: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let fnname = match word {
+			"__try" => format!("{word}<{}>", effect.popped().iter().take(2).rev().map(Type::name).collect::<Vec<String>>().join(", ")),
+			_ => word.to_string()
+		};
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+			let i32_ty = LLVMInt32TypeInContext(self.context);
+			let i64_ty = LLVMInt64TypeInContext(self.context);
+			let string_ty = Type::new_string();
+			let string_llvm_ty = self.llvm_type(&string_ty);
+
+			let handler_global = self.get_or_add_global("__tower_handler", ptr_ty);
+			let error_global = self.get_or_add_global("__tower_error", string_llvm_ty);
+
+			let mut longjmp_params = [
+				ptr_ty,
+				i32_ty
+			];
+			let longjmp_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), longjmp_params.as_mut_ptr(), 2, LLVM_FALSE);
+
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			match word {
+				"__throw" => {
+					let thrown = self.build_pop(&stack_ptrs, &string_ty);
+					LLVMBuildStore(self.builder, thrown, error_global);
+
+					let handler = LLVMBuildLoad2(self.builder, ptr_ty, handler_global, cstr!("handler\0"));
+					let is_uncaught = LLVMBuildIsNull(self.builder, handler, cstr!("is_uncaught\0"));
+
+					// Errors thrown outside any try end the program, like they do in the interpreter
+					self.build_if(fnvalue, is_uncaught, &mut |this| {
+						let mut printf_params = [
+							ptr_ty
+						];
+						let printf_ty = LLVMFunctionType(i32_ty, printf_params.as_mut_ptr(), 1, LLVM_TRUE);
+						let printf = this.get_or_declare("printf", printf_ty);
+
+						let data = LLVMBuildExtractValue(this.builder, thrown, 0, cstr!("data\0"));
+						let len = LLVMBuildExtractValue(this.builder, thrown, 1, cstr!("len\0"));
+						let len = LLVMBuildTrunc(this.builder, len, i32_ty, cstr!("len\0"));
+						let fmt_str = LLVMBuildGlobalStringPtr(this.builder, cstr!("uncaught error - %.*s\n\0"), cstr!("fmt\0"));
+						LLVMBuildCall2(this.builder, printf_ty, printf, [fmt_str, len, data].as_mut_ptr(), 3, cstr!("\0"));
+
+						let mut exit_params = [
+							i32_ty
+						];
+						let exit_ty = LLVMFunctionType(LLVMVoidTypeInContext(this.context), exit_params.as_mut_ptr(), 1, LLVM_FALSE);
+						let exit = this.get_or_declare("exit", exit_ty);
+						LLVMBuildCall2(this.builder, exit_ty, exit, [LLVMConstInt(i32_ty, 1, LLVM_FALSE)].as_mut_ptr(), 1, cstr!("\0"));
+					});
+
+					let longjmp = self.get_or_declare("longjmp", longjmp_ty);
+					self.add_fn_attribute(longjmp, "noreturn");
+					LLVMBuildCall2(self.builder, longjmp_ty, longjmp, [handler, LLVMConstInt(i32_ty, 1, LLVM_FALSE)].as_mut_ptr(), 2, cstr!("\0"));
+				}
+				"__try" => {
+					let handler_ty = effect.popped()[0].clone();
+					let body_ty = effect.popped()[1].clone();
+					let arg_tys: Vec<Type> = effect.popped().iter().skip(2).cloned().collect();
+
+					let handler = self.build_pop(&stack_ptrs, &handler_ty);
+					let body = self.build_pop(&stack_ptrs, &body_ty);
+
+					// The body's arguments are copied off the stack, bottom first, so they can be restored if the body throws
+					let mut args_size = LLVMConstInt(i64_ty, 0, LLVM_FALSE);
+					let mut arg_offsets = Vec::new();
+					for arg_ty in arg_tys.iter().rev() {
+						arg_offsets.push((arg_ty.clone(), args_size));
+						args_size = LLVMBuildAdd(self.builder, args_size, LLVMSizeOf(self.llvm_type(arg_ty)), cstr!("args_size\0"));
+					}
+
+					let sp = LLVMBuildLoad2(self.builder, ptr_ty, stack_ptrs[1], cstr!("sp\0"));
+					let bp = LLVMBuildLoad2(self.builder, ptr_ty, stack_ptrs[0], cstr!("bp\0"));
+					let args_ptr = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), sp, [LLVMBuildNeg(self.builder, args_size, cstr!("neg_size\0"))].as_mut_ptr(), 1, cstr!("args_ptr\0"));
+					let args_copy = self.build_heap_copy(args_ptr, args_size, args_size);
+					for (arg_ty, offset) in &arg_offsets {
+						if let Some(deep_copy) = self.define_deep_copy(arg_ty) {
+							let arg_ptr = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), args_copy, [*offset].as_mut_ptr(), 1, cstr!("arg_ptr\0"));
+							self.build_fn_call(deep_copy, &mut [arg_ptr]);
+						}
+					}
+
+					// The stack may be reallocated by the body, so the depth of the stack beneath the arguments is saved rather than a pointer
+					let sp_int = LLVMBuildPtrToInt(self.builder, args_ptr, i64_ty, cstr!("sp_int\0"));
+					let bp_int = LLVMBuildPtrToInt(self.builder, bp, i64_ty, cstr!("bp_int\0"));
+					let depth = LLVMBuildSub(self.builder, sp_int, bp_int, cstr!("depth\0"));
+
+					// NOTE: Large enough for a jmp_buf on all glibc targets
+					let jmp_buf = LLVMBuildArrayAlloca(self.builder, LLVMInt8TypeInContext(self.context), LLVMConstInt(i64_ty, 512, LLVM_FALSE), cstr!("jmp_buf\0"));
+					LLVMSetAlignment(jmp_buf, 16);
+
+					let prev_handler = LLVMBuildLoad2(self.builder, ptr_ty, handler_global, cstr!("prev_handler\0"));
+					LLVMBuildStore(self.builder, jmp_buf, handler_global);
+
+					let mut setjmp_params = [
+						ptr_ty
+					];
+					let setjmp_ty = LLVMFunctionType(i32_ty, setjmp_params.as_mut_ptr(), 1, LLVM_FALSE);
+					let setjmp = self.get_or_declare("setjmp", setjmp_ty);
+					self.add_fn_attribute(setjmp, "returns_twice");
+					let jumped = LLVMBuildCall2(self.builder, setjmp_ty, setjmp, [jmp_buf].as_mut_ptr(), 1, cstr!("jumped\0"));
+					let threw = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntNE, jumped, LLVMConstInt(i32_ty, 0, LLVM_FALSE), cstr!("threw\0"));
+
+					let body_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("body\0"));
+					let handler_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("handler\0"));
+					let end_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("end_try\0"));
+					LLVMBuildCondBr(self.builder, threw, handler_block, body_block);
+
+					let mut free_params = [
+						ptr_ty
+					];
+					let free_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), free_params.as_mut_ptr(), 1, LLVM_FALSE);
+					let free = self.get_or_declare("free", free_ty);
+
+					// The body returned normally, so the copies of its arguments and the unused handler are freed
+					LLVMPositionBuilderAtEnd(self.builder, body_block);
+					self.build_call_quotation(&stack_ptrs, &body_ty, body);
+					LLVMBuildStore(self.builder, prev_handler, handler_global);
+					for (arg_ty, offset) in &arg_offsets {
+						if let Some(destructor) = self.define_destructor(arg_ty) {
+							let arg_ptr = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), args_copy, [*offset].as_mut_ptr(), 1, cstr!("arg_ptr\0"));
+							self.build_fn_call(destructor, &mut [arg_ptr]);
+						}
+					}
+					LLVMBuildCall2(self.builder, free_ty, free, [args_copy].as_mut_ptr(), 1, cstr!("\0"));
+					if let Some(destructor) = self.define_destructor(&handler_ty) {
+						let handler_ptr = LLVMBuildAlloca(self.builder, self.llvm_type(&handler_ty), cstr!("handler_ptr\0"));
+						LLVMBuildStore(self.builder, handler, handler_ptr);
+						self.build_fn_call(destructor, &mut [handler_ptr]);
+					}
+					LLVMBuildBr(self.builder, end_block);
+
+					// The body threw, so the stack is restored to how it was when the body was called, and the handler is called with the error
+					LLVMPositionBuilderAtEnd(self.builder, handler_block);
+					LLVMBuildStore(self.builder, prev_handler, handler_global);
+					let bp = LLVMBuildLoad2(self.builder, ptr_ty, stack_ptrs[0], cstr!("bp\0"));
+					let restored_sp = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), bp, [depth].as_mut_ptr(), 1, cstr!("restored_sp\0"));
+					LLVMBuildStore(self.builder, restored_sp, stack_ptrs[1]);
+					self.build_stack_call("__internal_spadd", &stack_ptrs, args_size);
+					let sp = LLVMBuildLoad2(self.builder, ptr_ty, stack_ptrs[1], cstr!("sp\0"));
+					let args_ptr = LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), sp, [LLVMBuildNeg(self.builder, args_size, cstr!("neg_size\0"))].as_mut_ptr(), 1, cstr!("args_ptr\0"));
+					LLVMBuildMemCpy(self.builder, args_ptr, 1, args_copy, 1, args_size);
+					LLVMBuildCall2(self.builder, free_ty, free, [args_copy].as_mut_ptr(), 1, cstr!("\0"));
+
+					let thrown = LLVMBuildLoad2(self.builder, string_llvm_ty, error_global, cstr!("thrown\0"));
+					self.build_push(&stack_ptrs, &string_ty, thrown);
+					self.build_call_quotation(&stack_ptrs, &handler_ty, handler);
+					LLVMBuildBr(self.builder, end_block);
+
+					LLVMPositionBuilderAtEnd(self.builder, end_block);
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(fnname, fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Builds IR that calls the quotation of type quotation_ty, which is either a function pointer or a pointer to a closure environment
+	unsafe fn build_call_quotation(&mut self, stack_ptrs: &[LLVMValueRef; 3], quotation_ty: &Type, quotation: LLVMValueRef) {
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+
+		match quotation_ty {
+			Type::Closure { effect: _ } => {
+				let env_header_ty = self.closure_env_type(&[]);
+				let invoke = self.build_load_field(env_header_ty, quotation, 0, ptr_ty);
+
+				let mut invoke_params = [
+					ptr_ty,
+					ptr_ty,
+					ptr_ty,
+					ptr_ty
+				];
+				let invoke_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), invoke_params.as_mut_ptr(), 4, LLVM_FALSE);
+				let mut args = [
+					quotation,
+					stack_ptrs[0],
+					stack_ptrs[1],
+					stack_ptrs[2]
+				];
+				LLVMBuildCall2(self.builder, invoke_ty, invoke, args.as_mut_ptr(), 4, cstr!("\0"));
+			}
+			_ => {
+				let fntype = self.llvm_type(&Type::Function { name: String::new(), effect: StackEffect::none() });
+				let mut args = [
+					stack_ptrs[0],
+					stack_ptrs[1],
+					stack_ptrs[2]
+				];
+				LLVMBuildCall2(self.builder, fntype, quotation, args.as_mut_ptr(), 3, cstr!("\0"));
+			}
+		}
+	}
+
+	/// Returns the LLVM type of a closure environment holding values of the payload types, which follow pointers to the closure's
+	/// invoke, destroy and copy functions. The invoke function takes the environment and the bp_ptr, sp_ptr and ep_ptr parameters, the
+	/// destroy function takes the environment, and the copy function takes the environment and returns a copy of it
+	unsafe fn closure_env_type(&mut self, payload: &[Type]) -> LLVMTypeRef {
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+
+		let mut elem_types = vec![ptr_ty, ptr_ty, ptr_ty];
+		elem_types.extend(payload.iter().map(|ty| self.llvm_type(ty)));
+
+		LLVMStructTypeInContext(self.context, elem_types.as_mut_ptr(), elem_types.len() as u32, LLVM_FALSE)
+	}
+
+	/// Builds IR that allocates a closure environment which, when invoked, pushes the captured values and then calls the quotations in
+	/// calls in order, returning a pointer to the environment
+	unsafe fn build_closure(&mut self, captured: &[(Type, LLVMValueRef)], calls: &[(Type, LLVMValueRef)]) -> LLVMValueRef {
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+
+		let captured_tys: Vec<Type> = captured.iter().map(|(ty, _)| ty.clone()).collect();
+		let call_tys: Vec<Type> = calls.iter().map(|(ty, _)| ty.clone()).collect();
+		let [invoke, destroy, copy] = self.define_closure_fns(&captured_tys, &call_tys);
+
+		let payload: Vec<Type> = captured_tys.into_iter().chain(call_tys).collect();
+		let env_ty = self.closure_env_type(&payload);
+
+		let mut malloc_params = [
+			LLVMInt64TypeInContext(self.context)
+		];
+		let malloc_ty = LLVMFunctionType(ptr_ty, malloc_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let malloc = self.get_or_declare("malloc", malloc_ty);
+		let env = LLVMBuildCall2(self.builder, malloc_ty, malloc, [LLVMSizeOf(env_ty)].as_mut_ptr(), 1, cstr!("env\0"));
+
+		for (i, value) in [invoke, destroy, copy].into_iter().chain(captured.iter().chain(calls).map(|(_, value)| *value)).enumerate() {
+			self.build_store_field(env_ty, env, i as u32, value);
+		}
+
+		env
+	}
+
+	/// Defines the invoke, destroy and copy functions of closure environments holding captured values of the types captured_tys and
+	/// quotations of the types call_tys (see ModuleContext::closure_env_type), named e.g. `__closure_invoke<u32; &add>`
+	unsafe fn define_closure_fns(&mut self, captured_tys: &[Type], call_tys: &[Type]) -> [LLVMValueRef; 3] {
+		let names = |tys: &[Type]| tys.iter().map(Type::name).collect::<Vec<String>>().join(", ");
+		let key = format!("{}; {}", names(captured_tys), names(call_tys));
+		let fnnames = ["invoke", "destroy", "copy"].map(|kind| format!("__closure_{kind}<{key}>"));
+
+		if let [Some(invoke), Some(destroy), Some(copy)] = fnnames.clone().map(|fnname| self.builtins.get(&fnname).copied()) {
+			return [invoke, destroy, copy];
+		}
+
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+		let void_ty = LLVMVoidTypeInContext(self.context);
+
+		let payload: Vec<Type> = captured_tys.iter().chain(call_tys).cloned().collect();
+		let env_ty = self.closure_env_type(&payload);
+		let env_size = LLVMSizeOf(env_ty);
+
+		let mut free_params = [
+			ptr_ty
+		];
+		let free_ty = LLVMFunctionType(void_ty, free_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let free = self.get_or_declare("free", free_ty);
+
+		let prev_block = LLVMGetInsertBlock(self.builder);
+
+		// Invoking a closure consumes it, moving the captured values onto the stack
+		let mut invoke_params = [
+			ptr_ty,
+			ptr_ty,
+			ptr_ty,
+			ptr_ty
+		];
+		let invoke_ty = LLVMFunctionType(void_ty, invoke_params.as_mut_ptr(), 4, LLVM_FALSE);
+		let invoke = LLVMAddFunction(self.module, cstrv!(fnnames[0]), invoke_ty);
+
+		let mut destroy_params = [
+			ptr_ty
+		];
+		let destroy_ty = LLVMFunctionType(void_ty, destroy_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let destroy = LLVMAddFunction(self.module, cstrv!(fnnames[1]), destroy_ty);
+
+		let mut copy_params = [
+			ptr_ty
+		];
+		let copy_ty = LLVMFunctionType(ptr_ty, copy_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let copy = LLVMAddFunction(self.module, cstrv!(fnnames[2]), copy_ty);
+
+		for (fnname, fnvalue) in fnnames.into_iter().zip([invoke, destroy, copy]) {
+			self.builtins.insert(fnname, fnvalue);
+		}
+
+		let block = LLVMAppendBasicBlockInContext(self.context, invoke, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let env = LLVMGetParam(invoke, 0);
+		let stack_ptrs = [
+			LLVMGetParam(invoke, 1),
+			LLVMGetParam(invoke, 2),
+			LLVMGetParam(invoke, 3)
+		];
+		let payload_values: Vec<LLVMValueRef> = payload.iter().enumerate().map(|(i, ty)| {
+			let llvm_ty = self.llvm_type(ty);
+			self.build_load_field(env_ty, env, i as u32 + 3, llvm_ty)
+		}).collect();
+		LLVMBuildCall2(self.builder, free_ty, free, [env].as_mut_ptr(), 1, cstr!("\0"));
+
+		for (ty, value) in captured_tys.iter().zip(&payload_values) {
+			self.build_push(&stack_ptrs, ty, *value);
+		}
+		let call_values = &payload_values[captured_tys.len()..];
+		for (i, (ty, value)) in call_tys.iter().zip(call_values).enumerate() {
+			self.build_call_quotation(&stack_ptrs, ty, *value);
+
+			// A return within a quotation unwinds through the closure, so the quotations after it are never called and are destroyed
+			if i + 1 < call_tys.len() {
+				let rest: Vec<(Type, LLVMValueRef)> = call_tys[i + 1..].iter().cloned().zip(call_values[i + 1..].iter().copied()).collect();
+				self.build_return_if_returning(invoke, &mut |this| {
+					for (ty, value) in &rest {
+						if let Some(destructor) = this.define_destructor(ty) {
+							let value_ptr = LLVMBuildAlloca(this.builder, this.llvm_type(ty), cstr!("value_ptr\0"));
+							LLVMBuildStore(this.builder, *value, value_ptr);
+							this.build_fn_call(destructor, &mut [value_ptr]);
+						}
+					}
+				});
+			}
+		}
+
+		LLVMBuildRetVoid(self.builder);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, destroy, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let env = LLVMGetParam(destroy, 0);
+		for (i, ty) in payload.iter().enumerate() {
+			if let Some(destructor) = self.define_destructor(ty) {
+				let field_ptr = LLVMBuildStructGEP2(self.builder, env_ty, env, i as u32 + 3, cstr!("field_ptr\0"));
+				self.build_fn_call(destructor, &mut [field_ptr]);
+			}
+		}
+		LLVMBuildCall2(self.builder, free_ty, free, [env].as_mut_ptr(), 1, cstr!("\0"));
+
+		LLVMBuildRetVoid(self.builder);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, copy, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let env = LLVMGetParam(copy, 0);
+		let new_env = self.build_heap_copy(env, env_size, env_size);
+		for (i, ty) in payload.iter().enumerate() {
+			if let Some(deep_copy) = self.define_deep_copy(ty) {
+				let field_ptr = LLVMBuildStructGEP2(self.builder, env_ty, new_env, i as u32 + 3, cstr!("field_ptr\0"));
+				self.build_fn_call(deep_copy, &mut [field_ptr]);
+			}
+		}
+
+		LLVMBuildRet(self.builder, new_env);
+
+		LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+		[invoke, destroy, copy]
+	}
+
+	/// Defines the stack manipulation builtin word (`__dup`, `__drop`, `__clone` or `__size_of`) for the type it is instantiated with in
+	/// effect, named e.g. `__drop<String>`. Dropping an owned value calls its destructor, and cloning it deep copies it
+	fn define_stack_builtin(&mut self, word: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let ty = effect.popped().front()?.clone();
+
+		let fnname = format!("{word}<{}>", ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			let llvm_ty = self.llvm_type(&ty);
+
+			match word {
+				"__dup" | "__clone" => {
+					let top_ptr = self.build_top_ptr(&stack_ptrs, &ty);
+					let value = LLVMBuildLoad2(self.builder, llvm_ty, top_ptr, cstr!("value\0"));
+					LLVMSetAlignment(value, 1);
+					self.build_push(&stack_ptrs, &ty, value);
+
+					// The pushed value is a bytewise copy, which is given its own copies of any heap memory when cloning
+					if word == "__clone" {
+						if let Some(deep_copy) = self.define_deep_copy(&ty) {
+							let copy_ptr = self.build_top_ptr(&stack_ptrs, &ty);
+							self.build_fn_call(deep_copy, &mut [copy_ptr]);
+						}
+					}
+				}
+				"__drop" | "__size_of" => {
+					let value = self.build_pop(&stack_ptrs, &ty);
+
+					if let Some(destructor) = self.define_destructor(&ty) {
+						let value_ptr = LLVMBuildAlloca(self.builder, llvm_ty, cstr!("value_ptr\0"));
+						LLVMBuildStore(self.builder, value, value_ptr);
+						self.build_fn_call(destructor, &mut [value_ptr]);
+					}
+
+					if word == "__size_of" {
+						self.build_push(&stack_ptrs, &Type::new_uint(64), LLVMSizeOf(llvm_ty));
+					}
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(fnname, fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Defines the function `__destructor<ty>`, which frees the heap memory owned by the value of type ty pointed to by its argument,
+	/// including that owned by any values it contains. Returns None if ty is copyable, so owns no heap memory
+	unsafe fn define_destructor(&mut self, ty: &Type) -> Option<LLVMValueRef> {
+		if ty.is_copyable() {
+			return None;
+		}
+
+		let fnname = format!("__destructor<{}>", ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+
+		let mut params = [
+			ptr_ty
+		];
+		let fntype = LLVMFunctionType(LLVMVoidTypeInContext(self.context), params.as_mut_ptr(), 1, LLVM_FALSE);
+		let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+		self.builtins.insert(fnname, fnvalue);
+		let prev_block = LLVMGetInsertBlock(self.builder);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let value_ptr = LLVMGetParam(fnvalue, 0);
+		let free_ty = LLVMFunctionType(LLVMVoidTypeInContext(self.context), params.as_mut_ptr(), 1, LLVM_FALSE);
+		let free = self.get_or_declare("free", free_ty);
+
+		match ty {
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String } => {
+				let elem_ty = ty.as_vec().cloned().unwrap_or(Type::new_uint(8));
+				let heap_llvm_ty = self.llvm_type(ty);
+				let data = self.build_load_field(heap_llvm_ty, value_ptr, 0, ptr_ty);
+
+				if let Some(elem_destructor) = self.define_destructor(&elem_ty) {
+					let len = self.build_load_field(heap_llvm_ty, value_ptr, 1, i64_ty);
+					let elem_size = LLVMSizeOf(self.llvm_type(&elem_ty));
+
+					self.build_loop(fnvalue, len, &mut |this, i| {
+						let elem_ptr = this.build_byte_offset(data, i, elem_size);
+						this.build_fn_call(elem_destructor, &mut [elem_ptr]);
+					});
+				}
+
+				LLVMBuildCall2(self.builder, free_ty, free, [data].as_mut_ptr(), 1, cstr!("\0"));
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key_ty, value_ty) } => {
+				let map_llvm_ty = self.llvm_type(ty);
+				let entries = self.build_load_field(map_llvm_ty, value_ptr, 0, ptr_ty);
+
+				let key_destructor = self.define_destructor(key_ty);
+				let value_destructor = self.define_destructor(value_ty);
+				if key_destructor.is_some() || value_destructor.is_some() {
+					let cap = self.build_load_field(map_llvm_ty, value_ptr, 2, i64_ty);
+					let key_size = LLVMSizeOf(self.llvm_type(key_ty));
+					let entry_size = self.build_entry_size(key_ty, value_ty);
+
+					self.build_for_each_full_entry(fnvalue, entries, cap, entry_size, &mut |this, entry_ptr| {
+						if let Some(destructor) = key_destructor {
+							let entry_key_ptr = this.build_byte_offset(entry_ptr, LLVMConstInt(LLVMInt64TypeInContext(this.context), 1, LLVM_FALSE), LLVMConstInt(LLVMInt64TypeInContext(this.context), 1, LLVM_FALSE));
+							this.build_fn_call(destructor, &mut [entry_key_ptr]);
+						}
+						if let Some(destructor) = value_destructor {
+							let entry_value_ptr = this.build_entry_value_ptr(entry_ptr, key_size);
+							this.build_fn_call(destructor, &mut [entry_value_ptr]);
+						}
+					});
+				}
+
+				LLVMBuildCall2(self.builder, free_ty, free, [entries].as_mut_ptr(), 1, cstr!("\0"));
+			}
+			Type::Reference { to, mutable: _ } => {
+				let referenced_ptr = LLVMBuildLoad2(self.builder, ptr_ty, value_ptr, cstr!("referenced_ptr\0"));
+				LLVMSetAlignment(referenced_ptr, 1);
+
+				if let Some(destructor) = self.define_destructor(to) {
+					self.build_fn_call(destructor, &mut [referenced_ptr]);
+				}
+
+				LLVMBuildCall2(self.builder, free_ty, free, [referenced_ptr].as_mut_ptr(), 1, cstr!("\0"));
+			}
+			Type::Closure { effect: _ } => {
+				// Closure environments free themselves, as only they know what they captured
+				let env = LLVMBuildLoad2(self.builder, ptr_ty, value_ptr, cstr!("env\0"));
+				LLVMSetAlignment(env, 1);
+
+				let env_header_ty = self.closure_env_type(&[]);
+				let destroy = self.build_load_field(env_header_ty, env, 1, ptr_ty);
+				LLVMBuildCall2(self.builder, free_ty, destroy, [env].as_mut_ptr(), 1, cstr!("\0"));
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Array(_) } | Type::Transparent { name: _, fields: _, sum_type: false } => {
+				let llvm_ty = self.llvm_type(ty);
+
+				for (i, elem_ty) in self.aggregate_elems(ty).iter().enumerate() {
+					if let Some(destructor) = self.define_destructor(elem_ty) {
+						let elem_ptr = self.build_elem_ptr(llvm_ty, value_ptr, i);
+						self.build_fn_call(destructor, &mut [elem_ptr]);
 					}
 				}
-				assert_ne!(to_remove.len(), 0);
-				for i in to_remove {
-					functions_left.remove(i);
+			}
+			_ => ()
+		}
+
+		LLVMBuildRetVoid(self.builder);
+
+		LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+		Some(fnvalue)
+	}
+
+	/// Defines the function `__deep_copy<ty>`, which takes a pointer to a bytewise copy of a value of type ty, and replaces the heap
+	/// memory it shares with the original with its own copies, recursively. Returns None if ty is copyable, so owns no heap memory
+	unsafe fn define_deep_copy(&mut self, ty: &Type) -> Option<LLVMValueRef> {
+		if ty.is_copyable() {
+			return None;
+		}
+
+		let fnname = format!("__deep_copy<{}>", ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+
+		let mut params = [
+			ptr_ty
+		];
+		let fntype = LLVMFunctionType(LLVMVoidTypeInContext(self.context), params.as_mut_ptr(), 1, LLVM_FALSE);
+		let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+		self.builtins.insert(fnname, fnvalue);
+		let prev_block = LLVMGetInsertBlock(self.builder);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let value_ptr = LLVMGetParam(fnvalue, 0);
+
+		match ty {
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String } => {
+				let elem_ty = ty.as_vec().cloned().unwrap_or(Type::new_uint(8));
+				let heap_llvm_ty = self.llvm_type(ty);
+				let data = self.build_load_field(heap_llvm_ty, value_ptr, 0, ptr_ty);
+				let len = self.build_load_field(heap_llvm_ty, value_ptr, 1, i64_ty);
+				let cap = self.build_load_field(heap_llvm_ty, value_ptr, 2, i64_ty);
+				let elem_size = LLVMSizeOf(self.llvm_type(&elem_ty));
+
+				let cap_bytes = LLVMBuildMul(self.builder, cap, elem_size, cstr!("cap_bytes\0"));
+				let len_bytes = LLVMBuildMul(self.builder, len, elem_size, cstr!("len_bytes\0"));
+				let new_data = self.build_heap_copy(data, cap_bytes, len_bytes);
+				self.build_store_field(heap_llvm_ty, value_ptr, 0, new_data);
+
+				if let Some(elem_deep_copy) = self.define_deep_copy(&elem_ty) {
+					self.build_loop(fnvalue, len, &mut |this, i| {
+						let elem_ptr = this.build_byte_offset(new_data, i, elem_size);
+						this.build_fn_call(elem_deep_copy, &mut [elem_ptr]);
+					});
+				}
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key_ty, value_ty) } => {
+				let map_llvm_ty = self.llvm_type(ty);
+				let entries = self.build_load_field(map_llvm_ty, value_ptr, 0, ptr_ty);
+				let cap = self.build_load_field(map_llvm_ty, value_ptr, 2, i64_ty);
+				let entry_size = self.build_entry_size(key_ty, value_ty);
+
+				let entries_bytes = LLVMBuildMul(self.builder, cap, entry_size, cstr!("entries_bytes\0"));
+				let new_entries = self.build_heap_copy(entries, entries_bytes, entries_bytes);
+				self.build_store_field(map_llvm_ty, value_ptr, 0, new_entries);
+
+				let key_deep_copy = self.define_deep_copy(key_ty);
+				let value_deep_copy = self.define_deep_copy(value_ty);
+				if key_deep_copy.is_some() || value_deep_copy.is_some() {
+					let key_size = LLVMSizeOf(self.llvm_type(key_ty));
+
+					self.build_for_each_full_entry(fnvalue, new_entries, cap, entry_size, &mut |this, entry_ptr| {
+						if let Some(deep_copy) = key_deep_copy {
+							let entry_key_ptr = this.build_byte_offset(entry_ptr, LLVMConstInt(LLVMInt64TypeInContext(this.context), 1, LLVM_FALSE), LLVMConstInt(LLVMInt64TypeInContext(this.context), 1, LLVM_FALSE));
+							this.build_fn_call(deep_copy, &mut [entry_key_ptr]);
+						}
+						if let Some(deep_copy) = value_deep_copy {
+							let entry_value_ptr = this.build_entry_value_ptr(entry_ptr, key_size);
+							this.build_fn_call(deep_copy, &mut [entry_value_ptr]);
+						}
+					});
+				}
+			}
+			Type::Reference { to, mutable: _ } => {
+				let referenced_ptr = LLVMBuildLoad2(self.builder, ptr_ty, value_ptr, cstr!("referenced_ptr\0"));
+				LLVMSetAlignment(referenced_ptr, 1);
+
+				let size = LLVMSizeOf(self.llvm_type(to));
+				let new_referenced_ptr = self.build_heap_copy(referenced_ptr, size, size);
+				let store = LLVMBuildStore(self.builder, new_referenced_ptr, value_ptr);
+				LLVMSetAlignment(store, 1);
+
+				if let Some(deep_copy) = self.define_deep_copy(to) {
+					self.build_fn_call(deep_copy, &mut [new_referenced_ptr]);
+				}
+			}
+			Type::Closure { effect: _ } => {
+				let env = LLVMBuildLoad2(self.builder, ptr_ty, value_ptr, cstr!("env\0"));
+				LLVMSetAlignment(env, 1);
+
+				let env_header_ty = self.closure_env_type(&[]);
+				let copy = self.build_load_field(env_header_ty, env, 2, ptr_ty);
+				let mut copy_params = [
+					ptr_ty
+				];
+				let copy_ty = LLVMFunctionType(ptr_ty, copy_params.as_mut_ptr(), 1, LLVM_FALSE);
+				let new_env = LLVMBuildCall2(self.builder, copy_ty, copy, [env].as_mut_ptr(), 1, cstr!("new_env\0"));
+
+				let store = LLVMBuildStore(self.builder, new_env, value_ptr);
+				LLVMSetAlignment(store, 1);
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Array(_) } | Type::Transparent { name: _, fields: _, sum_type: false } => {
+				let llvm_ty = self.llvm_type(ty);
+
+				for (i, elem_ty) in self.aggregate_elems(ty).iter().enumerate() {
+					if let Some(deep_copy) = self.define_deep_copy(elem_ty) {
+						let elem_ptr = self.build_elem_ptr(llvm_ty, value_ptr, i);
+						self.build_fn_call(deep_copy, &mut [elem_ptr]);
+					}
 				}
 			}
+			_ => ()
 		}
+
+		LLVMBuildRetVoid(self.builder);
+
+		LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+		Some(fnvalue)
 	}
 
-	/// Compiles and adds the passed in function to the module, returning true on success and false if other elements that are needed are not
-	/// compiled yet
-	pub fn compile_function(&mut self, func: &TypedTreeNode) -> bool {
-		if let TypedTree::Function { name, effect, body } = &func.tree {
-			eprintln!("Compiling function: {name}");
+	/// Builds IR that allocates alloc_bytes bytes on the heap and copies copy_bytes bytes from src into it, returning the new allocation
+	unsafe fn build_heap_copy(&mut self, src: LLVMValueRef, alloc_bytes: LLVMValueRef, copy_bytes: LLVMValueRef) -> LLVMValueRef {
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+		let i64_ty = LLVMInt64TypeInContext(self.context);
 
-			for node in body {
-				if let TypedTree::Word(name) = &node.tree {
-					if !self.functions.contains_key(name) {
-						return false;
+		let mut malloc_params = [
+			i64_ty
+		];
+		let malloc_ty = LLVMFunctionType(ptr_ty, malloc_params.as_mut_ptr(), 1, LLVM_FALSE);
+		let malloc = self.get_or_declare("malloc", malloc_ty);
+
+		let mut memcpy_params = [
+			ptr_ty,
+			ptr_ty,
+			i64_ty
+		];
+		let memcpy_ty = LLVMFunctionType(ptr_ty, memcpy_params.as_mut_ptr(), 3, LLVM_FALSE);
+		let memcpy = self.get_or_declare("memcpy", memcpy_ty);
+
+		let dst = LLVMBuildCall2(self.builder, malloc_ty, malloc, [alloc_bytes].as_mut_ptr(), 1, cstr!("heap_copy\0"));
+		LLVMBuildCall2(self.builder, memcpy_ty, memcpy, [dst, src, copy_bytes].as_mut_ptr(), 3, cstr!("\0"));
+
+		dst
+	}
+
+	/// Builds IR that returns the size of a map entry with the key and value types, which is a state byte followed by the key and value
+	unsafe fn build_entry_size(&mut self, key_ty: &Type, value_ty: &Type) -> LLVMValueRef {
+		let key_size = LLVMSizeOf(self.llvm_type(key_ty));
+		let value_size = LLVMSizeOf(self.llvm_type(value_ty));
+		let kv_size = LLVMBuildAdd(self.builder, key_size, value_size, cstr!("kv_size\0"));
+
+		LLVMBuildAdd(self.builder, kv_size, LLVMConstInt(LLVMInt64TypeInContext(self.context), 1, LLVM_FALSE), cstr!("entry_size\0"))
+	}
+
+	/// Builds a loop over the cap map entries starting at entries, running body with a pointer to each full entry
+	unsafe fn build_for_each_full_entry(&mut self, fnvalue: LLVMValueRef, entries: LLVMValueRef, cap: LLVMValueRef, entry_size: LLVMValueRef, body: &mut dyn FnMut(&mut Self, LLVMValueRef)) {
+		self.build_loop(fnvalue, cap, &mut |this, i| {
+			let entry_ptr = this.build_byte_offset(entries, i, entry_size);
+			let state = LLVMBuildLoad2(this.builder, LLVMInt8TypeInContext(this.context), entry_ptr, cstr!("state\0"));
+			let is_full = LLVMBuildICmp(this.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, state, LLVMConstInt(LLVMInt8TypeInContext(this.context), 1, LLVM_FALSE), cstr!("is_full\0"));
+
+			this.build_if(fnvalue, is_full, &mut |this| body(this, entry_ptr));
+		});
+	}
+
+	/// Builds a loop that runs body for each i64 index from 0 up to count, passing it the index. body may add blocks of its own
+	unsafe fn build_loop(&mut self, fnvalue: LLVMValueRef, count: LLVMValueRef, body: &mut dyn FnMut(&mut Self, LLVMValueRef)) {
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+
+		let entry_block = LLVMGetInsertBlock(self.builder);
+		let cond_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("loop\0"));
+		let body_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("loop_body\0"));
+		let end_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("loop_end\0"));
+		LLVMBuildBr(self.builder, cond_block);
+
+		LLVMPositionBuilderAtEnd(self.builder, cond_block);
+		let i = LLVMBuildPhi(self.builder, i64_ty, cstr!("i\0"));
+		let done = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntUGE, i, count, cstr!("done\0"));
+		LLVMBuildCondBr(self.builder, done, end_block, body_block);
+
+		LLVMPositionBuilderAtEnd(self.builder, body_block);
+		body(self, i);
+		let i_next = LLVMBuildAdd(self.builder, i, LLVMConstInt(i64_ty, 1, LLVM_FALSE), cstr!("i_next\0"));
+		let latch_block = LLVMGetInsertBlock(self.builder);
+		LLVMBuildBr(self.builder, cond_block);
+
+		let mut incoming_values = [
+			LLVMConstInt(i64_ty, 0, LLVM_FALSE),
+			i_next
+		];
+		let mut incoming_blocks = [
+			entry_block,
+			latch_block
+		];
+		LLVMAddIncoming(i, incoming_values.as_mut_ptr(), incoming_blocks.as_mut_ptr(), 2);
+
+		LLVMPositionBuilderAtEnd(self.builder, end_block);
+	}
+
+	/// Builds IR that runs body only if cond is true, leaving the builder positioned after it
+	unsafe fn build_if(&mut self, fnvalue: LLVMValueRef, cond: LLVMValueRef, body: &mut dyn FnMut(&mut Self)) {
+		let then_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("then\0"));
+		let end_block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("end_if\0"));
+		LLVMBuildCondBr(self.builder, cond, then_block, end_block);
+
+		LLVMPositionBuilderAtEnd(self.builder, then_block);
+		body(self);
+		LLVMBuildBr(self.builder, end_block);
+
+		LLVMPositionBuilderAtEnd(self.builder, end_block);
+	}
+
+	/// Builds IR that returns a pointer index * size bytes after ptr
+	unsafe fn build_byte_offset(&mut self, ptr: LLVMValueRef, index: LLVMValueRef, size: LLVMValueRef) -> LLVMValueRef {
+		let mut gep_indices = [
+			LLVMBuildMul(self.builder, index, size, cstr!("offset\0"))
+		];
+		LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), ptr, gep_indices.as_mut_ptr(), 1, cstr!("offset_ptr\0"))
+	}
+
+	/// Builds IR that loads the field at index, of LLVM type field_ty, from the possibly unaligned struct of LLVM type struct_ty pointed to by ptr
+	unsafe fn build_load_field(&mut self, struct_ty: LLVMTypeRef, ptr: LLVMValueRef, index: u32, field_ty: LLVMTypeRef) -> LLVMValueRef {
+		let field_ptr = LLVMBuildStructGEP2(self.builder, struct_ty, ptr, index, cstr!("field_ptr\0"));
+		let field = LLVMBuildLoad2(self.builder, field_ty, field_ptr, cstr!("field\0"));
+		LLVMSetAlignment(field, 1);
+
+		field
+	}
+
+	/// Builds IR that stores value to the field at index of the possibly unaligned struct of LLVM type struct_ty pointed to by ptr
+	unsafe fn build_store_field(&mut self, struct_ty: LLVMTypeRef, ptr: LLVMValueRef, index: u32, value: LLVMValueRef) {
+		let field_ptr = LLVMBuildStructGEP2(self.builder, struct_ty, ptr, index, cstr!("field_ptr\0"));
+		let store = LLVMBuildStore(self.builder, value, field_ptr);
+		LLVMSetAlignment(store, 1);
+	}
+
+	/// Builds a call to the function fnvalue defined in the module with args, returning the call's result
+	unsafe fn build_fn_call(&mut self, fnvalue: LLVMValueRef, args: &mut [LLVMValueRef]) -> LLVMValueRef {
+		LLVMBuildCall2(self.builder, LLVMGlobalGetValueType(fnvalue), fnvalue, args.as_mut_ptr(), args.len() as u32, cstr!("\0"))
+	}
+
+	/// Defines the reference builtin word (e.g. `__box`) for the referenced type it is instantiated with in effect, named e.g.
+	/// `__box<Point>`. References are pointers to heap-allocated values, so only the pointer is moved around the stack.
+	fn define_reference_builtin(&mut self, word: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let ref_ty = effect.popped().iter().chain(effect.pushed().iter()).find(|ty| matches!(ty, Type::Reference { to: _, mutable: _ }))?.clone();
+		let value_ty = ref_ty.deref().clone();
+
+		let fnname = format!("{word}<{}>", value_ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
+
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+			let value_llvm_ty = self.llvm_type(&value_ty);
+
+			match word {
+				"__box" | "__cell" => {
+					let mut malloc_params = [
+						LLVMInt64TypeInContext(self.context)
+					];
+					let malloc_ty = LLVMFunctionType(ptr_ty, malloc_params.as_mut_ptr(), 1, LLVM_FALSE);
+					let malloc = self.get_or_declare("malloc", malloc_ty);
+
+					let value = self.build_pop(&stack_ptrs, &value_ty);
+
+					let mut malloc_args = [
+						LLVMSizeOf(value_llvm_ty)
+					];
+					let value_ptr = LLVMBuildCall2(self.builder, malloc_ty, malloc, malloc_args.as_mut_ptr(), 1, cstr!("value_ptr\0"));
+					LLVMBuildStore(self.builder, value, value_ptr);
+
+					self.build_push(&stack_ptrs, &ref_ty, value_ptr);
+				}
+				"__deref" | "__deref_mut" | "__store" => {
+					let stored = if word == "__store" {
+						Some(self.build_pop(&stack_ptrs, &value_ty))
+					} else {
+						None
+					};
+
+					// The reference is left on the stack
+					let ref_ptr = self.build_top_ptr(&stack_ptrs, &ref_ty);
+					let value_ptr = LLVMBuildLoad2(self.builder, ptr_ty, ref_ptr, cstr!("value_ptr\0"));
+					LLVMSetAlignment(value_ptr, 1);
+
+					match stored {
+						Some(value) => {
+							// The replaced value is dropped
+							if let Some(destructor) = self.define_destructor(&value_ty) {
+								self.build_fn_call(destructor, &mut [value_ptr]);
+							}
+
+							LLVMBuildStore(self.builder, value, value_ptr);
+						}
+						None => {
+							let value = LLVMBuildLoad2(self.builder, value_llvm_ty, value_ptr, cstr!("value\0"));
+							self.build_push(&stack_ptrs, &value_ty, value);
+						}
 					}
 				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
 			}
 
-			let fntype = self.llvm_type(&Type::Function { name: name.to_string(), effect: effect.clone() });
-			unsafe {
-				let fnvalue = LLVMAddFunction(self.module, cstrv!(name), fntype);
+			LLVMBuildRetVoid(self.builder);
 
-				let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
-				LLVMPositionBuilderAtEnd(self.builder, block);
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
 
-				let bppv = LLVMGetParam(fnvalue, 0);
-				let sppv = LLVMGetParam(fnvalue, 1);
-				let eppv = LLVMGetParam(fnvalue, 2);
-				let mut bpv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), bppv, cstr!("bp\0"));
-				let mut spv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), sppv, cstr!("sp\0"));
-				let mut epv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), eppv, cstr!("ep\0"));
+			self.builtins.insert(fnname, fnvalue);
 
-				for node in body {
-					match &node.tree {
-						TypedTree::Word(word) => {
-							let (wordfn_type, wordfn) = self.functions.get(word).unwrap();
+			Some(fnvalue)
+		}
+	}
 
-							let mut wordargs = [
-								bppv,
-								sppv,
-								eppv
-							];
-							LLVMBuildCall2(self.builder, *wordfn_type, *wordfn, wordargs.as_mut_ptr(), 3, cstr!("\0"));
-							bpv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), bppv, cstr!("bp\0"));
-							spv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), sppv, cstr!("sp\0"));
-							epv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), eppv, cstr!("ep\0"));
-						},
-						TypedTree::BuiltinWord(word) => {
-							let wordfn = self.builtins.get(word).unwrap();
-							let wordfn_type = LLVMGlobalGetValueType(*wordfn);
+	/// Defines the map builtin word (e.g. `__map_insert`) for the map type it is instantiated with in effect, named e.g.
+	/// `__map_insert<Map<u32, bool>>`. The hash table itself is implemented by the `__internal_map_*` helpers in the builtin module,
+	/// which are passed the hash and equality functions for the key type.
+	fn define_map_builtin(&mut self, word: &str, op: &str, effect: &StackEffect) -> Option<LLVMValueRef> {
+		let map_ty = effect.popped().iter().find(|ty| ty.as_map().is_some())?.clone();
+		let (key_ty, value_ty) = map_ty.as_map()?;
+		let (key_ty, value_ty) = (key_ty.clone(), value_ty.clone());
+
+		let fnname = format!("{word}<{}>", map_ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return Some(*fnvalue);
+		}
 
-							let mut wordargs = [
-								bppv,
-								sppv,
-								eppv
-							];
-							LLVMBuildCall2(self.builder, wordfn_type, *wordfn, wordargs.as_mut_ptr(), 3, cstr!("\0"));
-							bpv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), bppv, cstr!("bp\0"));
-							spv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), sppv, cstr!("sp\0"));
-							epv = LLVMBuildLoad2(self.builder, LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC), eppv, cstr!("ep\0"));
-						},
-						TypedTree::Literal { ty, value } => todo!(),
-						TypedTree::Constructor { ty, effect } => todo!(),
-						TypedTree::FieldAccess { name } => todo!(),
-						_ => unreachable!()
+		let fntype = self.llvm_type(&Type::Function { name: fnname.clone(), effect: effect.clone() });
+
+		unsafe {
+			let hash_fn = self.define_hash_fn(&key_ty);
+			let eq_fn = self.define_eq_fn(&key_ty);
+
+			let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+			let prev_block = LLVMGetInsertBlock(self.builder);
+
+			let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+			LLVMPositionBuilderAtEnd(self.builder, block);
+
+			let stack_ptrs = [
+				LLVMGetParam(fnvalue, 0),
+				LLVMGetParam(fnvalue, 1),
+				LLVMGetParam(fnvalue, 2)
+			];
+
+			let key_llvm_ty = self.llvm_type(&key_ty);
+			let value_llvm_ty = self.llvm_type(&value_ty);
+			let key_size = LLVMSizeOf(key_llvm_ty);
+			let value_size = LLVMSizeOf(value_llvm_ty);
+
+			match op {
+				"insert" => {
+					let value = self.build_pop(&stack_ptrs, &value_ty);
+					let value_ptr = LLVMBuildAlloca(self.builder, value_llvm_ty, cstr!("value_ptr\0"));
+					LLVMBuildStore(self.builder, value, value_ptr);
+
+					let key = self.build_pop(&stack_ptrs, &key_ty);
+					let key_ptr = LLVMBuildAlloca(self.builder, key_llvm_ty, cstr!("key_ptr\0"));
+					LLVMBuildStore(self.builder, key, key_ptr);
+
+					let map_ptr = self.build_top_ptr(&stack_ptrs, &map_ty);
+
+					let key_destructor = self.define_destructor(&key_ty);
+					let value_destructor = self.define_destructor(&value_ty);
+					if key_destructor.is_some() || value_destructor.is_some() {
+						let entry_ptr = self.build_internal_call("__internal_map_find", &mut [map_ptr, key_ptr, key_size, value_size, hash_fn, eq_fn]);
+						let found = LLVMBuildIsNotNull(self.builder, entry_ptr, cstr!("found\0"));
+
+						// When the key is already present, the existing key is kept and its value replaced, so the new key and old value are dropped
+						self.build_if(fnvalue, found, &mut |this| {
+							if let Some(destructor) = value_destructor {
+								let entry_value_ptr = this.build_entry_value_ptr(entry_ptr, key_size);
+								this.build_fn_call(destructor, &mut [entry_value_ptr]);
+							}
+							if let Some(destructor) = key_destructor {
+								this.build_fn_call(destructor, &mut [key_ptr]);
+							}
+						});
 					}
+
+					self.build_internal_call("__internal_map_insert", &mut [map_ptr, key_ptr, value_ptr, key_size, value_size, hash_fn, eq_fn]);
 				}
+				"get" | "contains" | "remove" => {
+					let key = self.build_pop(&stack_ptrs, &key_ty);
+					let key_ptr = LLVMBuildAlloca(self.builder, key_llvm_ty, cstr!("key_ptr\0"));
+					LLVMBuildStore(self.builder, key, key_ptr);
 
-				self.functions.insert(name.to_string(), (fntype, fnvalue));
+					let map_ptr = self.build_top_ptr(&stack_ptrs, &map_ty);
 
-				true
+					let key_destructor = self.define_destructor(&key_ty);
+
+					let entry_ptr = self.build_internal_call("__internal_map_find", &mut [map_ptr, key_ptr, key_size, value_size, hash_fn, eq_fn]);
+					let found = LLVMBuildIsNotNull(self.builder, entry_ptr, cstr!("found\0"));
+
+					if op == "get" || op == "remove" {
+						// The value follows the state byte and key of the entry. If the key isn't found, the zero value is pushed instead
+						let zero_ptr = LLVMBuildAlloca(self.builder, value_llvm_ty, cstr!("zero_ptr\0"));
+						LLVMBuildStore(self.builder, LLVMConstNull(value_llvm_ty), zero_ptr);
+
+						let entry_value_ptr = self.build_entry_value_ptr(entry_ptr, key_size);
+						let value_ptr = LLVMBuildSelect(self.builder, found, entry_value_ptr, zero_ptr, cstr!("value_ptr\0"));
+
+						let value = LLVMBuildLoad2(self.builder, value_llvm_ty, value_ptr, cstr!("value\0"));
+						LLVMSetAlignment(value, 1);
+						self.build_push(&stack_ptrs, &value_ty, value);
+					}
+
+					if op == "remove" {
+						// The removed entry's key is dropped, while its value is moved onto the stack
+						if let Some(destructor) = key_destructor {
+							self.build_if(fnvalue, found, &mut |this| {
+								let mut gep_indices = [
+									LLVMConstInt(LLVMInt64TypeInContext(this.context), 1, LLVM_FALSE)
+								];
+								let entry_key_ptr = LLVMBuildGEP2(this.builder, LLVMInt8TypeInContext(this.context), entry_ptr, gep_indices.as_mut_ptr(), 1, cstr!("entry_key_ptr\0"));
+								this.build_fn_call(destructor, &mut [entry_key_ptr]);
+							});
+						}
+
+						self.build_internal_call("__internal_map_remove", &mut [map_ptr, key_ptr, key_size, value_size, hash_fn, eq_fn]);
+					}
+
+					self.build_push(&stack_ptrs, &Type::new_bool(), found);
+
+					// The popped key is dropped once it has been looked up
+					if let Some(destructor) = key_destructor {
+						self.build_fn_call(destructor, &mut [key_ptr]);
+					}
+				}
+				"len" => {
+					let map_llvm_ty = self.llvm_type(&map_ty);
+					let map_ptr = self.build_top_ptr(&stack_ptrs, &map_ty);
+					let len_ptr = LLVMBuildStructGEP2(self.builder, map_llvm_ty, map_ptr, 1, cstr!("len_ptr\0"));
+					let len = LLVMBuildLoad2(self.builder, LLVMInt64TypeInContext(self.context), len_ptr, cstr!("len\0"));
+					LLVMSetAlignment(len, 1);
+
+					let index_ty = Type::new_uint(32);
+					let len = LLVMBuildTrunc(self.builder, len, self.llvm_type(&index_ty), cstr!("len\0"));
+					self.build_push(&stack_ptrs, &index_ty, len);
+				}
+				_ => {
+					LLVMDeleteFunction(fnvalue);
+					LLVMPositionBuilderAtEnd(self.builder, prev_block);
+					return None;
+				}
+			}
+
+			LLVMBuildRetVoid(self.builder);
+
+			LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+			self.builtins.insert(fnname, fnvalue);
+
+			Some(fnvalue)
+		}
+	}
+
+	/// Builds IR that returns a pointer to the value of the map entry pointed to by entry_ptr, which follows the entry's state byte and key
+	unsafe fn build_entry_value_ptr(&mut self, entry_ptr: LLVMValueRef, key_size: LLVMValueRef) -> LLVMValueRef {
+		let mut gep_indices = [
+			LLVMBuildAdd(self.builder, key_size, LLVMConstInt(LLVMInt64TypeInContext(self.context), 1, LLVM_FALSE), cstr!("value_offset\0"))
+		];
+		LLVMBuildGEP2(self.builder, LLVMInt8TypeInContext(self.context), entry_ptr, gep_indices.as_mut_ptr(), 1, cstr!("entry_value_ptr\0"))
+	}
+
+	/// Defines the function `__hash<ty>`, which hashes the value of type ty pointed to by its argument, for use as a map key.
+	/// Values are hashed structurally, so e.g. strings are hashed by their contents and structs field by field, skipping padding
+	unsafe fn define_hash_fn(&mut self, ty: &Type) -> LLVMValueRef {
+		let fnname = format!("__hash<{}>", ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return *fnvalue;
+		}
+
+		let mut params = [
+			LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC)
+		];
+		let fntype = LLVMFunctionType(LLVMInt64TypeInContext(self.context), params.as_mut_ptr(), 1, LLVM_FALSE);
+		let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+		let prev_block = LLVMGetInsertBlock(self.builder);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let hash = self.build_hash(LLVMGetParam(fnvalue, 0), ty);
+		LLVMBuildRet(self.builder, hash);
+
+		LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+		self.builtins.insert(fnname, fnvalue);
+
+		fnvalue
+	}
+
+	/// Builds IR that hashes the value of type ty pointed to by value_ptr, returning the i64 hash
+	unsafe fn build_hash(&mut self, value_ptr: LLVMValueRef, ty: &Type) -> LLVMValueRef {
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+
+		match ty {
+			Type::Opaque { size: _, kind: OpaqueTypeKind::String } => {
+				let string_llvm_ty = self.llvm_type(ty);
+				let data_ptr = LLVMBuildStructGEP2(self.builder, string_llvm_ty, value_ptr, 0, cstr!("data_ptr\0"));
+				let data = LLVMBuildLoad2(self.builder, ptr_ty, data_ptr, cstr!("data\0"));
+				LLVMSetAlignment(data, 1);
+				let len_ptr = LLVMBuildStructGEP2(self.builder, string_llvm_ty, value_ptr, 1, cstr!("len_ptr\0"));
+				let len = LLVMBuildLoad2(self.builder, i64_ty, len_ptr, cstr!("len\0"));
+				LLVMSetAlignment(len, 1);
+
+				self.build_internal_call("__internal_hash_bytes", &mut [data, len])
+			}
+			Type::Reference { to: _, mutable: _ } => {
+				let mut strlen_params = [ptr_ty];
+				let strlen_ty = LLVMFunctionType(i64_ty, strlen_params.as_mut_ptr(), 1, LLVM_FALSE);
+				let strlen = self.get_or_declare("strlen", strlen_ty);
+
+				let str_value = LLVMBuildLoad2(self.builder, ptr_ty, value_ptr, cstr!("str\0"));
+				LLVMSetAlignment(str_value, 1);
+				let mut strlen_args = [str_value];
+				let len = LLVMBuildCall2(self.builder, strlen_ty, strlen, strlen_args.as_mut_ptr(), 1, cstr!("len\0"));
+
+				self.build_internal_call("__internal_hash_bytes", &mut [str_value, len])
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Array(_) } | Type::Transparent { name: _, fields: _, sum_type: false } => {
+				let llvm_ty = self.llvm_type(ty);
+				let elems = self.aggregate_elems(ty);
+
+				// Combines the hashes of the elements the same way FNV-1a combines bytes
+				let mut hash = LLVMConstInt(i64_ty, 0xcbf29ce484222325, LLVM_FALSE);
+				for (i, elem_ty) in elems.iter().enumerate() {
+					let elem_ptr = self.build_elem_ptr(llvm_ty, value_ptr, i);
+					let elem_hash = self.build_hash(elem_ptr, elem_ty);
+					let mixed = LLVMBuildXor(self.builder, hash, elem_hash, cstr!("mixed\0"));
+					hash = LLVMBuildMul(self.builder, mixed, LLVMConstInt(i64_ty, 0x100000001b3, LLVM_FALSE), cstr!("hash\0"));
+				}
+
+				hash
+			}
+			_ => {
+				let size = LLVMSizeOf(self.llvm_type(ty));
+
+				self.build_internal_call("__internal_hash_bytes", &mut [value_ptr, size])
 			}
-		} else {
-			unreachable!();
 		}
 	}
 
+	/// Defines the function `__eq<ty>`, which compares the two values of type ty pointed to by its arguments structurally, for use
+	/// as a map key
+	unsafe fn define_eq_fn(&mut self, ty: &Type) -> LLVMValueRef {
+		let fnname = format!("__eq<{}>", ty.name());
+		if let Some(fnvalue) = self.builtins.get(&fnname) {
+			return *fnvalue;
+		}
+
+		let mut params = [
+			LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC),
+			LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC)
+		];
+		let fntype = LLVMFunctionType(LLVMInt1TypeInContext(self.context), params.as_mut_ptr(), 2, LLVM_FALSE);
+		let fnvalue = LLVMAddFunction(self.module, cstrv!(fnname), fntype);
+		let prev_block = LLVMGetInsertBlock(self.builder);
+
+		let block = LLVMAppendBasicBlockInContext(self.context, fnvalue, cstr!("entry\0"));
+		LLVMPositionBuilderAtEnd(self.builder, block);
+
+		let eq = self.build_eq(LLVMGetParam(fnvalue, 0), LLVMGetParam(fnvalue, 1), ty);
+		LLVMBuildRet(self.builder, eq);
+
+		LLVMPositionBuilderAtEnd(self.builder, prev_block);
+
+		self.builtins.insert(fnname, fnvalue);
+
+		fnvalue
+	}
+
+	/// Builds IR that compares the values of type ty pointed to by lhs_ptr and rhs_ptr, returning an i1 that is true if they are equal
+	unsafe fn build_eq(&mut self, lhs_ptr: LLVMValueRef, rhs_ptr: LLVMValueRef, ty: &Type) -> LLVMValueRef {
+		let i64_ty = LLVMInt64TypeInContext(self.context);
+		let i32_ty = LLVMInt32TypeInContext(self.context);
+		let ptr_ty = LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC);
+		let zero = LLVMConstInt(i32_ty, 0, LLVM_FALSE);
+
+		let mut memcmp_params = [ptr_ty, ptr_ty, i64_ty];
+		let memcmp_ty = LLVMFunctionType(i32_ty, memcmp_params.as_mut_ptr(), 3, LLVM_FALSE);
+
+		match ty {
+			Type::Opaque { size: _, kind: OpaqueTypeKind::String } => {
+				let string_llvm_ty = self.llvm_type(ty);
+				let load_field = |ptr: LLVMValueRef, field: u32, field_ty: LLVMTypeRef| {
+					let field_ptr = LLVMBuildStructGEP2(self.builder, string_llvm_ty, ptr, field, cstr!("field_ptr\0"));
+					let value = LLVMBuildLoad2(self.builder, field_ty, field_ptr, cstr!("field\0"));
+					LLVMSetAlignment(value, 1);
+					value
+				};
+				let lhs_data = load_field(lhs_ptr, 0, ptr_ty);
+				let lhs_len = load_field(lhs_ptr, 1, i64_ty);
+				let rhs_data = load_field(rhs_ptr, 0, ptr_ty);
+				let rhs_len = load_field(rhs_ptr, 1, i64_ty);
+
+				// Only compare the contents up to the length of the strings if they are the same length
+				let len_eq = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, lhs_len, rhs_len, cstr!("len_eq\0"));
+				let cmp_len = LLVMBuildSelect(self.builder, len_eq, lhs_len, LLVMConstInt(i64_ty, 0, LLVM_FALSE), cstr!("cmp_len\0"));
+
+				let memcmp = self.get_or_declare("memcmp", memcmp_ty);
+				let mut memcmp_args = [lhs_data, rhs_data, cmp_len];
+				let cmp = LLVMBuildCall2(self.builder, memcmp_ty, memcmp, memcmp_args.as_mut_ptr(), 3, cstr!("cmp\0"));
+				let data_eq = LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, cmp, zero, cstr!("data_eq\0"));
+
+				LLVMBuildAnd(self.builder, len_eq, data_eq, cstr!("eq\0"))
+			}
+			Type::Reference { to: _, mutable: _ } => {
+				let mut strcmp_params = [ptr_ty, ptr_ty];
+				let strcmp_ty = LLVMFunctionType(i32_ty, strcmp_params.as_mut_ptr(), 2, LLVM_FALSE);
+				let strcmp = self.get_or_declare("strcmp", strcmp_ty);
+
+				let lhs = LLVMBuildLoad2(self.builder, ptr_ty, lhs_ptr, cstr!("lhs\0"));
+				LLVMSetAlignment(lhs, 1);
+				let rhs = LLVMBuildLoad2(self.builder, ptr_ty, rhs_ptr, cstr!("rhs\0"));
+				LLVMSetAlignment(rhs, 1);
+
+				let mut strcmp_args = [lhs, rhs];
+				let cmp = LLVMBuildCall2(self.builder, strcmp_ty, strcmp, strcmp_args.as_mut_ptr(), 2, cstr!("cmp\0"));
+
+				LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, cmp, zero, cstr!("eq\0"))
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Array(_) } | Type::Transparent { name: _, fields: _, sum_type: false } => {
+				let llvm_ty = self.llvm_type(ty);
+				let elems = self.aggregate_elems(ty);
+
+				let mut eq = LLVMConstInt(LLVMInt1TypeInContext(self.context), 1, LLVM_FALSE);
+				for (i, elem_ty) in elems.iter().enumerate() {
+					let lhs_elem_ptr = self.build_elem_ptr(llvm_ty, lhs_ptr, i);
+					let rhs_elem_ptr = self.build_elem_ptr(llvm_ty, rhs_ptr, i);
+					let elem_eq = self.build_eq(lhs_elem_ptr, rhs_elem_ptr, elem_ty);
+					eq = LLVMBuildAnd(self.builder, eq, elem_eq, cstr!("eq\0"));
+				}
+
+				eq
+			}
+			_ => {
+				let size = LLVMSizeOf(self.llvm_type(ty));
+
+				let memcmp = self.get_or_declare("memcmp", memcmp_ty);
+				let mut memcmp_args = [lhs_ptr, rhs_ptr, size];
+				let cmp = LLVMBuildCall2(self.builder, memcmp_ty, memcmp, memcmp_args.as_mut_ptr(), 3, cstr!("cmp\0"));
+
+				LLVMBuildICmp(self.builder, llvm_sys::LLVMIntPredicate::LLVMIntEQ, cmp, zero, cstr!("eq\0"))
+			}
+		}
+	}
+
+	/// Builds IR that returns a pointer to the i-th element of the array or struct of LLVM type llvm_ty pointed to by ptr
+	unsafe fn build_elem_ptr(&mut self, llvm_ty: LLVMTypeRef, ptr: LLVMValueRef, i: usize) -> LLVMValueRef {
+		let mut gep_indices = [
+			LLVMConstInt(LLVMInt32TypeInContext(self.context), 0, LLVM_FALSE),
+			LLVMConstInt(LLVMInt32TypeInContext(self.context), i as u64, LLVM_FALSE)
+		];
+		LLVMBuildGEP2(self.builder, llvm_ty, ptr, gep_indices.as_mut_ptr(), 2, cstr!("elem_ptr\0"))
+	}
+
+	/// Returns the types of the elements of an array or fields of a struct, in the order they are laid out in its LLVM type
+	fn aggregate_elems(&self, ty: &Type) -> Vec<Type> {
+		match ty {
+			Type::Opaque { size: Some(len), kind: OpaqueTypeKind::Array(elem) } => vec![elem.as_ref().clone(); *len],
+			Type::Transparent { name: _, fields, sum_type: _ } => fields.iter().map(|(_, ftype)| ftype.clone()).collect(),
+			_ => Vec::new()
+		}
+	}
+
+	/// Builds a call to one of the `__internal_` helper functions of the builtin module, returning the call's result
+	unsafe fn build_internal_call(&mut self, internal_fn: &str, args: &mut [LLVMValueRef]) -> LLVMValueRef {
+		let internal_fnvalue = *self.builtins.get(internal_fn).unwrap_or_else(|| panic!("Builtin module does not define {internal_fn}"));
+		let internal_fntype = LLVMGlobalGetValueType(internal_fnvalue);
+
+		LLVMBuildCall2(self.builder, internal_fntype, internal_fnvalue, args.as_mut_ptr(), args.len() as u32, cstr!("\0"))
+	}
+
 	/// Returns the LLVM LLVMTypeRef for the passed-in tower type
 	pub fn llvm_type(&mut self, ty: &Type) -> LLVMTypeRef {
 		let res: LLVMTypeRef = unsafe { match ty {
 			Type::Opaque { size, kind } => {
 				match kind {
 					OpaqueTypeKind::Bool => LLVMInt1TypeInContext(self.context),
+					OpaqueTypeKind::Char => LLVMInt32TypeInContext(self.context),
 					OpaqueTypeKind::UnsignedInt | OpaqueTypeKind::SignedInt => {
 						match size.unwrap() * 8 {
 							8 => LLVMInt8TypeInContext(self.context),
@@ -283,7 +2627,31 @@ impl<'a> ModuleContext<'a> {
 							size.unwrap() as u64
 						)
 					},
-					OpaqueTypeKind::Array => todo!(),
+					OpaqueTypeKind::Array(elem) => {
+						LLVMArrayType2(
+							self.llvm_type(elem),
+							size.unwrap() as u64
+						)
+					},
+					// { data, len, cap }, matching the representation used by the __internal_vec_* helpers
+					OpaqueTypeKind::Vector(_) | OpaqueTypeKind::String => {
+						let mut elem_types = [
+							LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC),
+							LLVMInt64TypeInContext(self.context),
+							LLVMInt64TypeInContext(self.context)
+						];
+						LLVMStructTypeInContext(self.context, elem_types.as_mut_ptr(), 3, LLVM_FALSE)
+					},
+					// { entries, len, cap, used }, matching the representation used by the __internal_map_* helpers
+					OpaqueTypeKind::Map(_, _) => {
+						let mut elem_types = [
+							LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC),
+							LLVMInt64TypeInContext(self.context),
+							LLVMInt64TypeInContext(self.context),
+							LLVMInt64TypeInContext(self.context)
+						];
+						LLVMStructTypeInContext(self.context, elem_types.as_mut_ptr(), 4, LLVM_FALSE)
+					},
 				}
 			},
 			Type::Transparent { name, fields, sum_type } => {
@@ -303,7 +2671,9 @@ impl<'a> ModuleContext<'a> {
 					todo!()
 				}
 			},
-			Type::Reference { to: _ } => LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC),
+			Type::Reference { to: _, mutable: _ } => LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC),
+			// Closures are pointers to their heap-allocated environment
+			Type::Closure { effect: _ } => LLVMPointerTypeInContext(self.context, LLVM_ADDRESS_SPACE_GENERIC),
 			Type::Generic { name: _ } => unreachable!(),
 			Type::Function { name: _, effect: _ } => {
 				// NOTE: I don't think instructions will be processed here
@@ -328,6 +2698,47 @@ impl<'a> ModuleContext<'a> {
 	}
 }
 
+/// Returns the name of the C type values of the type ty are passed to and returned from C functions as
+fn c_type_name(ty: &Type) -> String {
+	match ty {
+		Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => "bool".to_string(),
+		Type::Opaque { size: Some(size), kind: OpaqueTypeKind::UnsignedInt } => format!("uint{}_t", size * 8),
+		Type::Opaque { size: Some(size), kind: OpaqueTypeKind::SignedInt } => format!("int{}_t", size * 8),
+		Type::Reference { to: _, mutable: false } => "const char*".to_string(),
+		_ => unreachable!()
+	}
+}
+
+/// Generates a C header declaring the C-ABI wrappers of the functions exported from the module, or None if it exports none
+pub fn generate_header(module: &TypedTreeNode) -> Option<String> {
+	let (name, elems) = match &module.tree {
+		TypedTree::Module { name, elems } => (name, elems),
+		_ => unreachable!()
+	};
+
+	let mut decls = Vec::new();
+	for (_, elem) in elems {
+		if let TypedTree::Function { name: fn_name, effect, body: _, exported: true, attributes: _, doc: _ } = &elem.tree {
+			let ret = effect.pushed().front().map(c_type_name).unwrap_or("void".to_string());
+			let params: Vec<String> = effect.popped().iter().rev().map(c_type_name).collect();
+			let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+
+			decls.push(format!("/* {fn_name} {effect} */\n{ret} tower_{fn_name}({params});\n"));
+		}
+	}
+
+	if decls.is_empty() {
+		return None;
+	}
+
+	let guard = format!("TOWER_{}_H", name.to_uppercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+
+	Some(format!(
+		"/* Generated from tower module {name} - do not edit */\n\n#ifndef {guard}\n#define {guard}\n\n#include <stdbool.h>\n#include <stdint.h>\n\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n\n{}\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {guard} */\n",
+		decls.join("\n")
+	))
+}
+
 pub fn compile(typed_tree: TypedTreeNode) -> CompiledProgram {
 	let mut context = CompileContext::new();
 
@@ -482,4 +2893,44 @@ pub fn compile_test_program() {
 		LLVMContextDispose(ctx);
 		LLVMShutdown();
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use crate::{analyser::{self, tree::TypedTreeNode}, interpreter::builtin::builtin_functions, parser::{self, result::ScanResult::{Valid, WithErr}, source_map::SourceMap}};
+
+	use super::generate_header;
+
+	/// Analyses the content of the file test.tower, returning the typed tree or the message of the error it fails with
+	fn analyse(content: &str) -> Result<TypedTreeNode, String> {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tree, errors) = parser::parse(&sources, file);
+		assert!(errors.is_empty(), "Expected test content to parse");
+
+		match analyser::analyse(&tree, &builtin_functions()) {
+			Valid(typed_tree) => Ok(typed_tree),
+			WithErr(e) => Err(e.to_string()),
+			_ => panic!("Expected test content to be recognised")
+		}
+	}
+
+	#[test]
+	fn header_declares_exports() {
+		let typed_tree = analyse("export fn add2 { __add_u32 }\nexport fn neg { -1i64 __mul_i64 }\nexport fn yes { 1u 1u __eq_u32 }\nfn main { }\n").expect("Expected exports to analyse");
+		let header = generate_header(&typed_tree).expect("Expected a header");
+
+		assert!(header.contains("#ifndef TOWER_TEST_H\n#define TOWER_TEST_H\n"));
+		assert!(header.contains("uint32_t tower_add2(uint32_t, uint32_t);\n"));
+		assert!(header.contains("int64_t tower_neg(int64_t);\n"));
+		assert!(header.contains("bool tower_yes(void);\n"));
+
+		assert_eq!(generate_header(&analyse("fn main { }\n").expect("Expected main to analyse")), None);
+	}
+
+	#[test]
+	fn exports_need_c_types() {
+		assert_eq!(analyse("export fn s { \"a\" to_string }\nfn main { }\n").err(), Some("type String cannot be passed to or returned from C functions (only integers up to 64 bits, bools and &str can be)".to_string()));
+		assert_eq!(analyse("export fn main { }\n").err(), Some("main cannot be exported, as it is the entry point of the compiled program".to_string()));
+		assert_eq!(analyse("export fn + { __add_u32 }\nfn main { }\n").err(), Some("function + cannot be exported, as its name is not a valid C identifier".to_string()));
+	}
+}
@@ -1,62 +1,90 @@
 pub mod error;
 pub mod builtin;
+pub mod ffi;
 
 use builtin::BuiltinWord;
 use error::{RuntimeError, RuntimeErrorKind};
 
-use crate::analyser::{tree::{TypedTree, TypedTreeNode}, ttype::Type, value::{Value, ValueInner}};
+use crate::analyser::{tree::{TypedTree, TypedTreeNode}, ttype::{OpaqueTypeKind, Type}, value::{Value, ValueInner}};
 
 pub fn interp(typed_tree: &TypedTreeNode, builtins: &im::OrdMap<String, BuiltinWord>) -> Result<Vec<Value>, RuntimeError> {
 	match &typed_tree.tree {
 		TypedTree::Module { name: _, elems } => {
-			let fns: im::OrdMap<String, TypedTreeNode> = elems.iter().filter_map(|(name, e)| if let TypedTree::Function { name: _, effect: _, body: _ } = e.tree { Some((name.clone(), e.clone())) } else { None }).collect();
-			let types: im::OrdMap<String, Type> = elems.iter().filter_map(|(name, e)| if let TypedTree::Type(t) = &e.tree { Some((name.clone(), t.clone())) } else { None }).collect();
-
-			if let Some(f) = fns.get("main") {
-				let mut stack: Vec<Value> = Vec::new();
-
-				interp_node(f, &fns, &types, builtins, &mut stack)?;
-
-				Ok(stack)
+			if let Some(f @ TypedTreeNode { span: _, tree: TypedTree::Function { .. } }) = elems.get("main") {
+				interp_fn(f, elems, builtins)
 			} else {
-				return Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError("main".to_string()), typed_tree.cursor));
+				return Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError("main".to_string()), typed_tree.span));
 			}
 		},
 		_ => {
-			return Err(RuntimeError::new(RuntimeErrorKind::ModuleNotFoundError, typed_tree.cursor))
+			return Err(RuntimeError::new(RuntimeErrorKind::ModuleNotFoundError, typed_tree.span))
 		}
 	}
 }
 
+/// Runs the function func on an empty stack, returning the stack it leaves. elems are the top-level elements func can use, e.g. the
+/// elements of its module
+pub fn interp_fn(func: &TypedTreeNode, elems: &im::OrdMap<String, TypedTreeNode>, builtins: &im::OrdMap<String, BuiltinWord>) -> Result<Vec<Value>, RuntimeError> {
+	let fns: im::OrdMap<String, TypedTreeNode> = elems.iter().filter_map(|(name, e)| if let TypedTree::Function { .. } | TypedTree::Extern { .. } = e.tree { Some((name.clone(), e.clone())) } else { None }).collect();
+	let types: im::OrdMap<String, Type> = elems.iter().filter_map(|(name, e)| if let TypedTree::Type { ty, .. } = &e.tree { Some((name.clone(), ty.clone())) } else { None }).collect();
+
+	let mut stack: Vec<Value> = Vec::new();
+
+	catch_return(interp_node(func, &fns, &types, builtins, &mut stack))?;
+
+	Ok(stack)
+}
+
+/// Ends a return unwinding from the result of running a function called by name, as the return ends that function
+fn catch_return(result: Result<(), RuntimeError>) -> Result<(), RuntimeError> {
+	match result {
+		Err(err) if matches!(err.kind(), RuntimeErrorKind::Returning) => Ok(()),
+		result => result
+	}
+}
+
 fn interp_node(typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNode>, types: &im::OrdMap<String, Type>, builtins: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>) -> Result<(), RuntimeError> {
 	match &typed_tree.tree {
 		TypedTree::Module { name: _, elems: _ } => unreachable!(),
-		TypedTree::Function { name, effect: _, body } => {
+		TypedTree::Function { name, effect: _, body, exported: _, attributes: _, doc: _ } => {
 			eprintln!("Debug: Executing function {name}");
 
 			for node in body {
+				if let TypedTree::Return = node.tree {
+					return Err(RuntimeError::new(RuntimeErrorKind::Returning, node.span));
+				}
+
 				interp_node(node, fns, types, builtins, stack)?;
 			}
 
 			Ok(())
 		},
-		TypedTree::Type(_) => unreachable!(),
+		TypedTree::Type { .. } => unreachable!(),
+		// Uses of constants are replaced with literals by the analyser, and static assertions are only evaluated during analysis
+		TypedTree::Constant { .. } | TypedTree::StaticAssert { .. } => unreachable!(),
+		TypedTree::Extern { name, library, effect } => ffi::call_extern(typed_tree, library, name, effect, stack),
 		TypedTree::Word(wd) => {
 			if let Some(node) = fns.get(wd) {
-				interp_node(node, fns, types, builtins, stack)
+				catch_return(interp_node(node, fns, types, builtins, stack))
 			} else {
-				return Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError(wd.clone()), typed_tree.cursor))
+				return Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError(wd.clone()), typed_tree.span))
 			}
 		},
-		TypedTree::BuiltinWord(wd) => {
+		TypedTree::BuiltinWord { name: wd, effect: _ } => {
 			if let Some(builtin) = builtins.get(wd) {
 				(builtin.f)(typed_tree, fns, types, builtins, stack)
 			} else {
-				return Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError(wd.clone()), typed_tree.cursor))
+				return Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError(wd.clone()), typed_tree.span))
 			}
 		}
-		TypedTree::Literal { ty: _, value } => {
-			stack.push(value.clone());
+		TypedTree::Literal { ty, value } => {
+			// Each use of a literal owning heap memory, e.g. a String produced by a const fn, gets its own copy of it
+			if ty.is_copyable() {
+				stack.push(value.clone());
+			} else {
+				stack.push(value.deep_clone());
+			}
+
 			Ok(())
 		},
 		TypedTree::Constructor { ty, effect: _ } => {
@@ -76,11 +104,41 @@ fn interp_node(typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNod
 
 					Ok(())
 				},
+				Type::Opaque { size: _, kind: OpaqueTypeKind::Vector(elem_ty) } => {
+					stack.push(Value::new_vec(elem_ty.as_ref().clone(), Vec::new()));
+
+					Ok(())
+				},
+				Type::Opaque { size: _, kind: OpaqueTypeKind::String } => {
+					stack.push(Value::new_string(""));
+
+					Ok(())
+				},
+				Type::Opaque { size: _, kind: OpaqueTypeKind::Map(key_ty, value_ty) } => {
+					stack.push(Value::new_map(key_ty.as_ref().clone(), value_ty.as_ref().clone()));
+
+					Ok(())
+				},
 				_ => unreachable!()
 			}
 		},
-		TypedTree::FieldAccess { name } => {
+		// Returns are handled by the function they're in, and unwind to the function called by name that they end
+		TypedTree::Return => unreachable!(),
+		// Programs with syntax errors aren't run
+		TypedTree::Invalid => unreachable!(),
+		TypedTree::FieldAccess { name, of: _ } => {
 			if let Some(val) = stack.last() {
+				// Fields can be read through references. The value behind a mutable reference is copied, so the cell isn't left borrowed
+				let mut_referenced;
+				let val = match &val.inner {
+					ValueInner::Reference { to } => to.as_ref(),
+					ValueInner::MutReference { to } => {
+						mut_referenced = to.borrow().clone();
+						&mut_referenced
+					}
+					_ => val
+				};
+
 				let field_value = if let ValueInner::Struct(vals) = &val.inner {
 					if let Type::Transparent { name: _, fields, sum_type: false } = &val.ty {
 						vals.iter().rev().zip(fields.iter()).find_map(|(val, (fname, _))| if fname == name { Some(val) } else { None })
@@ -91,16 +149,164 @@ fn interp_node(typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNod
 					unreachable!()
 				};
 
-				if let Some(field_value) = field_value {
-					stack.push(field_value.clone());
+				if let Some(field_value) = field_value.cloned() {
+					stack.push(field_value);
 
 					Ok(())
 				} else {
 					unreachable!()
 				}
 			} else {
-				return Err(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.cursor));
+				return Err(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span));
 			}
 		},
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+	use crate::{analyser::{self, value::Value}, parser::{self, result::ScanResult, source_map::SourceMap}};
+
+	use super::{builtin::builtin_functions, interp};
+
+	/// Runs the main function of the content, returning the values it leaves on the stack, or the message of the runtime error it fails with
+	fn run(content: &str) -> Result<Vec<Value>, String> {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tree, errors) = parser::parse(&sources, file);
+		assert!(errors.is_empty(), "Expected test content to parse");
+
+		let builtins = builtin_functions();
+		let typed_tree = match analyser::analyse(&tree, &builtins) {
+			ScanResult::Valid(typed_tree) => typed_tree,
+			ScanResult::WithErr(e) => panic!("Expected test content to analyse, but got: {e}"),
+			ScanResult::Unrecognised => panic!("Expected test content to be recognised")
+		};
+
+		interp(&typed_tree, &builtins).map_err(|e| e.to_string())
+	}
+
+	/// Runs the main function of the content, returning the u32s it leaves on the stack
+	fn run_u32s(content: &str) -> Vec<u32> {
+		let stack = run(content).unwrap_or_else(|e| panic!("Expected test content to run, but got: {e}"));
+		stack.iter().map(|value| u32::from_ne_bytes(value.as_bytes().expect("Expected bytes").try_into().expect("Expected u32"))).collect()
+	}
+
+	/// Runs the main function of the content, returning the u32 it leaves on top of the stack
+	fn run_top_u32(content: &str) -> u32 {
+		let stack = run(content).unwrap_or_else(|e| panic!("Expected test content to run, but got: {e}"));
+		u32::from_ne_bytes(stack.last().expect("Expected a value").as_bytes().expect("Expected bytes").try_into().expect("Expected u32"))
+	}
+
+	#[test]
+	fn overloaded_words_resolve_by_type() {
+		assert_eq!(run_u32s("fn main { 3u 4u mul 2u sub 5u 2u div }\n"), vec![10, 2]);
+		assert_eq!(run_top_u32("fn main { 'a' ord 1u add }\n"), 98);
+	}
+
+	#[test]
+	fn array_words() {
+		assert_eq!(run_top_u32("fn main { [1u 2u 3u] 1u 9u set 1u get }\n"), 9);
+		assert_eq!(run_top_u32("fn main { [1u 2u 3u] len }\n"), 3);
+		assert_eq!(run("fn main { [1u 2u 3u] 3u get }\n").err(), Some("index 3 is out of bounds for length 3".to_string()));
+	}
+
+	#[test]
+	fn vectors_and_strings() {
+		assert_eq!(run_top_u32("fn main { -> Vec<u32> 1u push 2u push pop }\n"), 2);
+		assert_eq!(run_top_u32("fn main { -> Vec<u32> 1u push -> Vec<u32> 2u push 3u push concat len }\n"), 3);
+		assert_eq!(run("fn main { -> Vec<u32> pop }\n").err(), Some("attempted to pop from an empty collection".to_string()));
+
+		// The elements of a String are its UTF-8 bytes
+		assert_eq!(run_top_u32("fn main { \"h\\u{e9}llo\" to_string len }\n"), 6);
+		assert_eq!(run_top_u32("fn main { \"ab\" to_string \"cd\" to_string concat len }\n"), 4);
+		// Only ASCII bytes can be pushed to or popped from a String, so it stays valid UTF-8
+		assert_eq!(run_top_u32("fn main { \"a\" to_string 98u8 push len }\n"), 2);
+		assert_eq!(run("fn main { \"a\" to_string 233u8 push }\n").err(), Some("error decoding UTF-8 string from bytes [233]".to_string()));
+		assert_eq!(run("fn main { \"hé\" to_string pop }\n").err(), Some("error decoding UTF-8 string from bytes [169]".to_string()));
+	}
+
+	#[test]
+	fn map_words() {
+		let map = "-> Map<String, u32> \"a\" to_string 1u insert";
+
+		assert_eq!(run_top_u32(&format!("fn main {{ {map} \"a\" to_string get drop }}\n")), 1);
+		// A missing key gets the zero value
+		assert_eq!(run_top_u32(&format!("fn main {{ {map} \"b\" to_string get drop }}\n")), 0);
+		assert_eq!(run_top_u32(&format!("fn main {{ {map} \"a\" to_string 2u insert len }}\n")), 1);
+		assert_eq!(run_top_u32(&format!("fn main {{ {map} \"a\" to_string remove drop drop \"a\" to_string contains drop len }}\n")), 0);
+		// remove pushes the value it removed, or the zero value
+		assert_eq!(run_top_u32(&format!("fn main {{ {map} \"a\" to_string remove drop }}\n")), 1);
+		assert_eq!(run_top_u32(&format!("fn main {{ {map} \"b\" to_string remove drop }}\n")), 0);
+		// Owned values can't be copied out with get, but can be taken out with remove
+		assert_eq!(run_top_u32("fn main { -> Map<u32, String> 1u \"ab\" to_string insert 1u remove drop len }\n"), 2);
+	}
+
+	#[test]
+	fn references() {
+		assert_eq!(run_top_u32("fn main { 5u cell 7u store deref }\n"), 7);
+		assert_eq!(run_top_u32("struct P { x: u32 y: u32 }\nfn main { 1u 2u -> P box .y }\n"), 2);
+	}
+
+	#[test]
+	fn closures() {
+		let fns = "fn plus { __add_u32 }\nfn double { 2u __mul_u32 }\n";
+
+		assert_eq!(run_top_u32(&format!("{fns}fn main {{ 5u 1u &plus curry call }}\n")), 6);
+		assert_eq!(run_top_u32(&format!("{fns}fn main {{ 5u 1u &plus curry &double compose call }}\n")), 12);
+	}
+
+	#[test]
+	fn extern_calls_match_signature() {
+		let externs = "extern \"libc.so.6\" fn abs (i32 -> i32)\nextern \"libc.so.6\" fn labs (i64 -> i64)\nextern \"libc.so.6\" fn strlen (&str -> u64)\n";
+		let stack = run(&format!("{externs}fn main {{ -5i32 abs -7000000000i64 labs \"hello\" strlen \"hi\" strlen }}\n")).expect("Expected externs to be called");
+		let bytes: Vec<&[u8]> = stack.iter().map(|value| value.as_bytes().expect("Expected bytes")).collect();
+		assert_eq!(bytes, vec![&5i32.to_ne_bytes()[..], &7000000000i64.to_ne_bytes()[..], &5u64.to_ne_bytes()[..], &2u64.to_ne_bytes()[..]]);
+	}
+
+	#[test]
+	fn extern_null_string_errors() {
+		let error = run("extern \"libc.so.6\" fn getenv (&str -> &str)\nfn main { \"TOWER_TEST_NOT_SET\" getenv }\n").expect_err("Expected null string to be an error");
+		assert!(error.ends_with("getenv in libc.so.6: returned a null string"), "{error}");
+	}
+
+	#[test]
+	fn extern_strings_must_be_utf8() {
+		std::env::set_var("TOWER_TEST_INVALID_UTF8", OsStr::from_bytes(b"a\xff"));
+
+		let error = run("extern \"libc.so.6\" fn getenv (&str -> &str)\nfn main { \"TOWER_TEST_INVALID_UTF8\" getenv }\n").expect_err("Expected invalid string to be an error");
+		assert_eq!(error, "error decoding UTF-8 string from bytes [97, 255]");
+	}
+
+	#[test]
+	fn try_restores_stack() {
+		// The body's argument is restored for the handler, rather than the value the body left before throwing
+		let fns = "fn body { 1u __add_u32 \"e\" to_string throw }\nfn handler { __string_len drop drop 10u __add_u32 }\n";
+
+		assert_eq!(run_top_u32(&format!("{fns}fn main {{ 5u &body &handler try }}\n")), 15);
+		assert_eq!(run("fn main { \"oops\" to_string throw }\n").err(), Some("uncaught error - oops".to_string()));
+	}
+
+	#[test]
+	fn return_ends_function() {
+		assert_eq!(run_u32s("fn one { 1u return 2u __drop }\nfn main { one 3u }\n"), vec![1, 3]);
+	}
+
+	#[test]
+	fn return_unwinds_through_quotations() {
+		let fns = "fn parse { \"bad\" to_string throw 5u }\nfn parse_ok { 5u }\nfn fallback { __string_len drop drop 7u return }\n";
+
+		// The return in the handler skips the rest of the function that called try, but not that function's caller
+		assert_eq!(run_u32s(&format!("{fns}fn get {{ &parse &fallback try 1u add }}\nfn main {{ get 2u }}\n")), vec![7, 2]);
+		assert_eq!(run_u32s(&format!("{fns}fn get {{ &parse_ok &fallback try 1u add }}\nfn main {{ get 2u }}\n")), vec![6, 2]);
+		// Closures unwind without calling the quotations composed after the one that returned
+		assert_eq!(run_u32s(&format!("{fns}fn add3 {{ 3u __add_u32 }}\nfn early {{ \"e\" to_string &fallback curry &add3 compose call 3u add }}\nfn main {{ early }}\n")), vec![7]);
+	}
+
+	#[test]
+	fn char_code_points() {
+		assert_eq!(run_u32s("fn main { 'a' ord 66u chr ord }\n"), vec![97, 66]);
+		assert_eq!(run("fn main { 55296u chr ord }\n").err(), Some("0xd800 is not a valid char (unicode scalar value)".to_string()));
+	}
+}
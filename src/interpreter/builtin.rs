@@ -1,8 +1,8 @@
 use std::{collections::HashMap, rc::Rc};
 
-use crate::analyser::{stack_effect::StackEffect, tree::TypedTreeNode, ttype::Type, value::Value};
+use crate::analyser::{stack_effect::StackEffect, tree::{TypedTree, TypedTreeNode}, ttype::Type, value::{Value, ValueInner}};
 
-use super::error::{RuntimeError, RuntimeErrorKind};
+use super::{error::{RuntimeError, RuntimeErrorKind}, interp_node};
 
 pub type BuiltinWordFn = Rc<dyn Fn(&TypedTreeNode, &im::OrdMap<String, TypedTreeNode>, &im::OrdMap<String, Type>, &im::OrdMap<String, BuiltinWord>, &mut Vec<Value>) -> Result<(), RuntimeError>>;
 
@@ -12,55 +12,912 @@ pub type BuiltinWordFn = Rc<dyn Fn(&TypedTreeNode, &im::OrdMap<String, TypedTree
 #[derive(Clone)]
 pub struct BuiltinWord {
 	pub f: BuiltinWordFn,
-	pub effect: StackEffect
+	pub effect: StackEffect,
+	/// The name of the overloaded word this builtin is an implementation of, if any - E.g. `__println_u32` implements `println`
+	pub overloads: Option<String>,
+	/// Whether this builtin pushes copies of values it leaves on the stack, e.g. `dup` or `get`. The analyser rejects these when the
+	/// copied values are owned, as the copy would alias heap memory
+	pub copies: bool,
+	/// Whether this builtin does I/O, e.g. `println` or `read_line`, so can't be run by const fns and static assertions during analysis
+	pub impure: bool,
+	/// Whether this builtin pushes the zero value of a map's value type when the key it looks up is missing, e.g. `get`. The analyser
+	/// rejects these for maps whose value type has no zero value, such as references
+	pub zeroes: bool
+}
+
+impl BuiltinWord {
+	/// Marks the builtin as pushing copies of values it leaves on the stack
+	fn copying(mut self) -> Self {
+		self.copies = true;
+		self
+	}
+
+	/// Marks the builtin as doing I/O
+	fn impure(mut self) -> Self {
+		self.impure = true;
+		self
+	}
+
+	/// Marks the builtin as pushing the zero value of a map's value type for a missing key
+	fn zeroing(mut self) -> Self {
+		self.zeroes = true;
+		self
+	}
 }
 
 impl From<(BuiltinWordFn, StackEffect)> for BuiltinWord {
 	fn from(value: (BuiltinWordFn, StackEffect)) -> Self {
 		BuiltinWord {
 			f: value.0,
-			effect: value.1
+			effect: value.1,
+			overloads: None,
+			copies: false,
+			impure: false,
+			zeroes: false
+		}
+	}
+}
+
+impl From<(BuiltinWordFn, StackEffect, &str)> for BuiltinWord {
+	fn from(value: (BuiltinWordFn, StackEffect, &str)) -> Self {
+		BuiltinWord {
+			f: value.0,
+			effect: value.1,
+			overloads: Some(value.2.to_string()),
+			copies: false,
+			impure: false,
+			zeroes: false
+		}
+	}
+}
+
+/// Pops a value off the stack, returning a StackUnderflowError located at typed_tree if there is none
+pub fn pop_value(typed_tree: &TypedTreeNode, stack: &mut Vec<Value>) -> Result<Value, RuntimeError> {
+	stack.pop().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))
+}
+
+/// Pops a u32 array index off the stack
+fn pop_index(typed_tree: &TypedTreeNode, stack: &mut Vec<Value>) -> Result<usize, RuntimeError> {
+	let index = pop_value(typed_tree, stack)?;
+
+	Ok(u32::from_ne_bytes(index.as_bytes().expect("Expected bytes").try_into().expect("Expected 4 bytes")) as usize)
+}
+
+/// Returns the zero value of the type ty, pushed in place of a missing map value, returning a NoZeroValueError if it has none
+fn zero_value(typed_tree: &TypedTreeNode, ty: &Type) -> Result<Value, RuntimeError> {
+	Value::zeroed(ty).ok_or_else(|| RuntimeError::new(RuntimeErrorKind::NoZeroValueError(ty.name()), typed_tree.span))
+}
+
+/// Returns the contents of the String value, returning a Utf8Error if it is not valid UTF-8
+fn string_contents(typed_tree: &TypedTreeNode, value: Value) -> Result<String, RuntimeError> {
+	match &value.inner {
+		ValueInner::String { bytes } => String::from_utf8(bytes.borrow().clone()).map_err(|err| RuntimeError::new(RuntimeErrorKind::Utf8Error(err.into_bytes()), typed_tree.span)),
+		_ => unreachable!()
+	}
+}
+
+/// Calls the quotation, which is either a reference to the function to call, or a closure
+fn call_quotation(typed_tree: &TypedTreeNode, quotation: Value, fns: &im::OrdMap<String, TypedTreeNode>, types: &im::OrdMap<String, Type>, builtins: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>) -> Result<(), RuntimeError> {
+	match quotation.inner {
+		ValueInner::Function { fn_name } => match fns.get(&fn_name) {
+			Some(node) => interp_node(node, fns, types, builtins, stack),
+			None => Err(RuntimeError::new(RuntimeErrorKind::FunctionMissingError(fn_name), typed_tree.span))
+		},
+		ValueInner::Closure { captured, calls } => {
+			stack.extend(captured);
+
+			for quotation in calls {
+				call_quotation(typed_tree, quotation, fns, types, builtins, stack)?;
+			}
+
+			Ok(())
 		}
+		_ => unreachable!()
+	}
+}
+
+/// Returns the stack effect of the closure pushed by the builtin call typed_tree, as worked out by the analyser
+fn pushed_closure_effect(typed_tree: &TypedTreeNode) -> StackEffect {
+	match &typed_tree.tree {
+		TypedTree::BuiltinWord { name: _, effect } => effect.last_pushed().and_then(Type::as_quotation).expect("Expected builtin to push a closure").clone(),
+		_ => unreachable!()
 	}
 }
 
+/// Inserts print, println, arithmetic and comparison builtins for the integer type $t into $words, each registered as an overload
+/// of the word they implement
+macro_rules! int_builtins {
+	($words: ident, $($t: ident => $ty: expr),*) => {
+		$(
+			$words.insert(concat!("__print_", stringify!($t)).into(), BuiltinWord::from((
+				Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+					let value = pop_value(typed_tree, stack)?;
+					print!("{}", $t::from_ne_bytes(value.as_bytes().expect("Expected bytes").try_into().expect("Expected correctly sized integer")));
+
+					Ok(())
+				}) as BuiltinWordFn,
+				StackEffect::new_popped(im::vector![$ty]),
+				"print"
+			)).impure());
+			$words.insert(concat!("__println_", stringify!($t)).into(), BuiltinWord::from((
+				Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+					let value = pop_value(typed_tree, stack)?;
+					println!("{}", $t::from_ne_bytes(value.as_bytes().expect("Expected bytes").try_into().expect("Expected correctly sized integer")));
+
+					Ok(())
+				}) as BuiltinWordFn,
+				StackEffect::new_popped(im::vector![$ty]),
+				"println"
+			)).impure());
+			int_builtins!(@binop $words, $t, $ty, add, |lhs: $t, rhs: $t| Some(lhs.wrapping_add(rhs)));
+			int_builtins!(@binop $words, $t, $ty, sub, |lhs: $t, rhs: $t| Some(lhs.wrapping_sub(rhs)));
+			int_builtins!(@binop $words, $t, $ty, mul, |lhs: $t, rhs: $t| Some(lhs.wrapping_mul(rhs)));
+			int_builtins!(@binop $words, $t, $ty, div, |lhs: $t, rhs: $t| (rhs != 0).then(|| lhs.wrapping_div(rhs)));
+			int_builtins!(@binop $words, $t, $ty, rem, |lhs: $t, rhs: $t| (rhs != 0).then(|| lhs.wrapping_rem(rhs)));
+			int_builtins!(@cmp $words, $t, $ty, eq, |lhs: $t, rhs: $t| lhs == rhs);
+			int_builtins!(@cmp $words, $t, $ty, ne, |lhs: $t, rhs: $t| lhs != rhs);
+			int_builtins!(@cmp $words, $t, $ty, lt, |lhs: $t, rhs: $t| lhs < rhs);
+			int_builtins!(@cmp $words, $t, $ty, le, |lhs: $t, rhs: $t| lhs <= rhs);
+			int_builtins!(@cmp $words, $t, $ty, gt, |lhs: $t, rhs: $t| lhs > rhs);
+			int_builtins!(@cmp $words, $t, $ty, ge, |lhs: $t, rhs: $t| lhs >= rhs);
+		)*
+	};
+	// Binary operators that produce a value of the same type, or None on division by zero
+	(@binop $words: ident, $t: ident, $ty: expr, $op: ident, $f: expr) => {
+		$words.insert(concat!("__", stringify!($op), "_", stringify!($t)).into(), (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = pop_value(typed_tree, stack)?;
+				let rhs = $t::from_ne_bytes(rhs.as_bytes().expect("Expected bytes").try_into().expect("Expected correctly sized integer"));
+				let lhs = $t::from_ne_bytes(lhs.as_bytes().expect("Expected bytes").try_into().expect("Expected correctly sized integer"));
+
+				match ($f)(lhs, rhs) {
+					Some(res) => stack.push(Value::from_typed_bytes($ty, res.to_ne_bytes())),
+					None => return Err(RuntimeError::new(RuntimeErrorKind::DivisionByZeroError, typed_tree.span))
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![$ty, $ty], im::vector![$ty]),
+			stringify!($op)
+		).into());
+	};
+	// Comparison operators that produce a bool
+	(@cmp $words: ident, $t: ident, $ty: expr, $op: ident, $f: expr) => {
+		$words.insert(concat!("__", stringify!($op), "_", stringify!($t)).into(), (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = pop_value(typed_tree, stack)?;
+				let rhs = $t::from_ne_bytes(rhs.as_bytes().expect("Expected bytes").try_into().expect("Expected correctly sized integer"));
+				let lhs = $t::from_ne_bytes(lhs.as_bytes().expect("Expected bytes").try_into().expect("Expected correctly sized integer"));
+
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [($f)(lhs, rhs) as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![$ty, $ty], im::vector![Type::new_bool()]),
+			stringify!($op)
+		).into());
+	};
+}
+
 pub fn builtin_functions() -> im::OrdMap<String, BuiltinWord> {
-	im::ordmap! {
-		"__println_str".into() => (
+	let mut words: im::OrdMap<String, BuiltinWord> = im::ordmap! {
+		"__print_str".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				print!("{}", value.as_strref().expect("Expected string"));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_strref(None)]),
+			"print"
+		)).impure(),
+		"__println_str".into() => BuiltinWord::from((
 			Rc::new(|typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNode>, types: &im::OrdMap<String, Type>, builtins: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
 				let value = if let Some(val) = stack.pop() {
 					val
 				} else {
-					return Err(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.cursor));
+					return Err(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span));
 				};
 				println!("{}", value.as_strref().expect("Expected string"));
 
 				Ok(())
 			}) as BuiltinWordFn,
-			StackEffect::new_popped(im::vector![Type::new_strref(None)])
+			StackEffect::new_popped(im::vector![Type::new_strref(None)]),
+			"println"
+		)).impure(),
+		"__print_bool".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				print!("{}", value.as_bytes().expect("Expected bytes")[0] != 0);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_bool()]),
+			"print"
+		)).impure(),
+		"__println_bool".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				println!("{}", value.as_bytes().expect("Expected bytes")[0] != 0);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_bool()]),
+			"println"
+		)).impure(),
+		"__eq_bool".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = pop_value(typed_tree, stack)?;
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [(lhs.as_bytes() == rhs.as_bytes()) as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_bool(), Type::new_bool()], im::vector![Type::new_bool()]),
+			"eq"
 		).into(),
-		"__println_u32".into() => (
+		"__ne_bool".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = pop_value(typed_tree, stack)?;
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [(lhs.as_bytes() != rhs.as_bytes()) as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_bool(), Type::new_bool()], im::vector![Type::new_bool()]),
+			"ne"
+		).into(),
+		"__print_char".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				print!("{}", value.as_char().expect("Expected char"));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_char()]),
+			"print"
+		)).impure(),
+		"__println_char".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				println!("{}", value.as_char().expect("Expected char"));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_char()]),
+			"println"
+		)).impure(),
+		"__ord_char".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				stack.push(Value::from_typed_bytes(Type::new_uint(32), (value.as_char().expect("Expected char") as u32).to_ne_bytes()));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_char()], im::vector![Type::new_uint(32)]),
+			"ord"
+		).into(),
+		"__chr_u32".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				let code = u32::from_ne_bytes(value.as_bytes().expect("Expected bytes").try_into().expect("Expected u32"));
+
+				match char::from_u32(code) {
+					Some(c) => stack.push(Value::from_typed_bytes(Type::new_char(), (c as u32).to_ne_bytes())),
+					None => return Err(RuntimeError::new(RuntimeErrorKind::InvalidCharError(code), typed_tree.span))
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_uint(32)], im::vector![Type::new_char()]),
+			"chr"
+		).into(),
+		"__call".into() => (
 			Rc::new(|typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNode>, types: &im::OrdMap<String, Type>, builtins: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
-				let value = if let Some(val) = stack.pop() {
-					val
-				} else {
-					return Err(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.cursor));
+				let quotation = pop_value(typed_tree, stack)?;
+				call_quotation(typed_tree, quotation, fns, types, builtins, stack)
+			}) as BuiltinWordFn,
+			// The precise effects of the quotation words depend on the quotations they're used with, so are worked out by the analyser
+			StackEffect::new_popped(im::vector![Type::new_generic("Q")]),
+			"call"
+		).into(),
+		"__curry".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let quotation = pop_value(typed_tree, stack)?;
+				let value = pop_value(typed_tree, stack)?;
+				stack.push(Value::new_closure(pushed_closure_effect(typed_tree), im::vector![value], im::vector![quotation]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_generic("Q"), Type::new_generic("T")]),
+			"curry"
+		).into(),
+		"__compose".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let second = pop_value(typed_tree, stack)?;
+				let first = pop_value(typed_tree, stack)?;
+				stack.push(Value::new_closure(pushed_closure_effect(typed_tree), im::Vector::new(), im::vector![first, second]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_generic("Q2"), Type::new_generic("Q1")]),
+			"compose"
+		).into(),
+		"__try".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNode>, types: &im::OrdMap<String, Type>, builtins: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let handler = pop_value(typed_tree, stack)?;
+				let body = pop_value(typed_tree, stack)?;
+
+				// Copies of the body's arguments are kept, so the stack can be restored for the handler if the body throws
+				let num_args = match &typed_tree.tree {
+					TypedTree::BuiltinWord { name: _, effect } => effect.popped().len() - 2,
+					_ => unreachable!()
+				};
+				let depth = stack.len().checked_sub(num_args).ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+				let args: Vec<Value> = stack[depth..].iter().map(Value::deep_clone).collect();
+
+				match call_quotation(typed_tree, body, fns, types, builtins, stack) {
+					Err(err) => match err.kind() {
+						RuntimeErrorKind::ThrownError(thrown) => {
+							stack.truncate(depth);
+							stack.extend(args);
+							stack.push(Value::new_string(thrown.clone()));
+
+							call_quotation(typed_tree, handler, fns, types, builtins, stack)
+						}
+						_ => Err(err)
+					}
+					Ok(()) => Ok(())
+				}
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_generic("Q2"), Type::new_generic("Q1")]),
+			"try"
+		).into(),
+		"__throw".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				let thrown = string_contents(typed_tree, value)?;
+
+				Err(RuntimeError::new(RuntimeErrorKind::ThrownError(thrown), typed_tree.span))
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_string()]),
+			"throw"
+		).into(),
+		"__dup".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?.clone();
+				stack.push(value);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T")], im::vector![Type::new_generic("T"), Type::new_generic("T")]),
+			"dup"
+		)).copying(),
+		"__size_of".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				// The size is of the type the analyser instantiated the builtin with, as it is the same for all values of the type
+				pop_value(typed_tree, stack)?;
+				let (size, _) = match &typed_tree.tree {
+					TypedTree::BuiltinWord { name: _, effect } => effect.popped()[0].layout().expect("Expected sized type"),
+					_ => unreachable!()
+				};
+				stack.push(Value::from_typed_bytes(Type::new_uint(64), (size as u64).to_ne_bytes()));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T")], im::vector![Type::new_uint(64)]),
+			"size_of"
+		).into(),
+		"__clone".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				// Unlike dup, the copy shares no heap contents with the original
+				let value = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?.deep_clone();
+				stack.push(value);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T")], im::vector![Type::new_generic("T"), Type::new_generic("T")]),
+			"clone"
+		).into(),
+		"__drop".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				pop_value(typed_tree, stack)?;
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_generic("T")]),
+			"drop"
+		).into(),
+		"__array_get".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let index = pop_index(typed_tree, stack)?;
+				let array = pop_value(typed_tree, stack)?;
+
+				let elem = match &array.inner {
+					ValueInner::Array(elems) => elems.get(index).cloned().ok_or(RuntimeError::new(RuntimeErrorKind::IndexOutOfBoundsError { index, len: elems.len() }, typed_tree.span))?,
+					_ => unreachable!()
 				};
-				println!("{}", u32::from_ne_bytes(value.as_bytes().expect("Expected bytes").try_into().expect("Expected 4 bytes")));
+
+				stack.push(array);
+				stack.push(elem);
 
 				Ok(())
 			}) as BuiltinWordFn,
-			StackEffect::new_popped(im::vector![Type::new_uint(32)])
+			StackEffect::new(im::vector![Type::new_uint(32), Type::new_array(Type::new_generic("T"), None)], im::vector![Type::new_array(Type::new_generic("T"), None), Type::new_generic("T")]),
+			"get"
+		)).copying(),
+		"__array_set".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let elem = pop_value(typed_tree, stack)?;
+				let index = pop_index(typed_tree, stack)?;
+				let mut array = pop_value(typed_tree, stack)?;
+
+				match &mut array.inner {
+					ValueInner::Array(elems) => {
+						if index >= elems.len() {
+							return Err(RuntimeError::new(RuntimeErrorKind::IndexOutOfBoundsError { index, len: elems.len() }, typed_tree.span));
+						}
+						elems.set(index, elem);
+					}
+					_ => unreachable!()
+				}
+
+				stack.push(array);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T"), Type::new_uint(32), Type::new_array(Type::new_generic("T"), None)], im::vector![Type::new_array(Type::new_generic("T"), None)]),
+			"set"
 		).into(),
-		"__hello".into() => (
+		"__array_len".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let array = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let len = match &array.inner {
+					ValueInner::Array(elems) => elems.len() as u32,
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_uint(32), len.to_ne_bytes()));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_array(Type::new_generic("T"), None)], im::vector![Type::new_array(Type::new_generic("T"), None), Type::new_uint(32)]),
+			"len"
+		).into(),
+		"__vec_push".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let elem = pop_value(typed_tree, stack)?;
+				let vec = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				match &vec.inner {
+					ValueInner::Vector { elems } => elems.borrow_mut().push(elem),
+					_ => unreachable!()
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T"), Type::new_vec(Type::new_generic("T"))], im::vector![Type::new_vec(Type::new_generic("T"))]),
+			"push"
+		).into(),
+		"__vec_pop".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let vec = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let elem = match &vec.inner {
+					ValueInner::Vector { elems } => elems.borrow_mut().pop().ok_or(RuntimeError::new(RuntimeErrorKind::EmptyPopError, typed_tree.span))?,
+					_ => unreachable!()
+				};
+
+				stack.push(elem);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_vec(Type::new_generic("T"))], im::vector![Type::new_vec(Type::new_generic("T")), Type::new_generic("T")]),
+			"pop"
+		).into(),
+		"__vec_get".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let index = pop_index(typed_tree, stack)?;
+				let vec = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let elem = match &vec.inner {
+					ValueInner::Vector { elems } => {
+						let elems = elems.borrow();
+						elems.get(index).cloned().ok_or(RuntimeError::new(RuntimeErrorKind::IndexOutOfBoundsError { index, len: elems.len() }, typed_tree.span))?
+					}
+					_ => unreachable!()
+				};
+
+				stack.push(elem);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_uint(32), Type::new_vec(Type::new_generic("T"))], im::vector![Type::new_vec(Type::new_generic("T")), Type::new_generic("T")]),
+			"get"
+		)).copying(),
+		"__vec_len".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let vec = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let len = match &vec.inner {
+					ValueInner::Vector { elems } => elems.borrow().len() as u32,
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_uint(32), len.to_ne_bytes()));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_vec(Type::new_generic("T"))], im::vector![Type::new_vec(Type::new_generic("T")), Type::new_uint(32)]),
+			"len"
+		).into(),
+		"__vec_concat".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				match (&lhs.inner, &rhs.inner) {
+					(ValueInner::Vector { elems }, ValueInner::Vector { elems: rhs_elems }) => {
+						// Cloned first, as both may be the same vector
+						let rhs_elems = rhs_elems.borrow().clone();
+						elems.borrow_mut().extend(rhs_elems);
+					}
+					_ => unreachable!()
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_vec(Type::new_generic("T")), Type::new_vec(Type::new_generic("T"))], im::vector![Type::new_vec(Type::new_generic("T"))]),
+			"concat"
+		).into(),
+		"__string_push".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let byte = pop_value(typed_tree, stack)?.as_bytes().expect("Expected bytes")[0];
+				let string = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				// Only ASCII bytes are whole chars, so pushing any other byte could leave the string invalid UTF-8
+				if !byte.is_ascii() {
+					return Err(RuntimeError::new(RuntimeErrorKind::Utf8Error(vec![byte]), typed_tree.span));
+				}
+
+				match &string.inner {
+					ValueInner::String { bytes } => bytes.borrow_mut().push(byte),
+					_ => unreachable!()
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_uint(8), Type::new_string()], im::vector![Type::new_string()]),
+			"push"
+		).into(),
+		"__string_pop".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let string = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let byte = match &string.inner {
+					ValueInner::String { bytes } => {
+						let mut bytes = bytes.borrow_mut();
+						match bytes.last() {
+							// Popping a byte of a multi-byte char would leave the rest of it behind
+							Some(byte) if !byte.is_ascii() => return Err(RuntimeError::new(RuntimeErrorKind::Utf8Error(vec![*byte]), typed_tree.span)),
+							_ => bytes.pop().ok_or(RuntimeError::new(RuntimeErrorKind::EmptyPopError, typed_tree.span))?
+						}
+					},
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_uint(8), [byte]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_string()], im::vector![Type::new_string(), Type::new_uint(8)]),
+			"pop"
+		).into(),
+		"__string_get".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let index = pop_index(typed_tree, stack)?;
+				let string = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let byte = match &string.inner {
+					ValueInner::String { bytes } => {
+						let bytes = bytes.borrow();
+						*bytes.get(index).ok_or(RuntimeError::new(RuntimeErrorKind::IndexOutOfBoundsError { index, len: bytes.len() }, typed_tree.span))?
+					}
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_uint(8), [byte]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_uint(32), Type::new_string()], im::vector![Type::new_string(), Type::new_uint(8)]),
+			"get"
+		).into(),
+		"__string_len".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let string = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let len = match &string.inner {
+					ValueInner::String { bytes } => bytes.borrow().len() as u32,
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_uint(32), len.to_ne_bytes()));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_string()], im::vector![Type::new_string(), Type::new_uint(32)]),
+			"len"
+		).into(),
+		"__string_concat".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				match (&lhs.inner, &rhs.inner) {
+					(ValueInner::String { bytes }, ValueInner::String { bytes: rhs_bytes }) => {
+						let rhs_bytes = rhs_bytes.borrow().clone();
+						bytes.borrow_mut().extend(rhs_bytes);
+					}
+					_ => unreachable!()
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_string(), Type::new_string()], im::vector![Type::new_string()]),
+			"concat"
+		).into(),
+		"__string_from_str".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				stack.push(Value::new_string(value.as_strref().expect("Expected string")));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_strref(None)], im::vector![Type::new_string()]),
+			"to_string"
+		).into(),
+		"__print_string".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				print!("{}", string_contents(typed_tree, value)?);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_string()]),
+			"print"
+		)).impure(),
+		"__println_string".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				println!("{}", string_contents(typed_tree, value)?);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_popped(im::vector![Type::new_string()]),
+			"println"
+		)).impure(),
+		"__map_insert".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				let key = pop_value(typed_tree, stack)?;
+				let map = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				match &map.inner {
+					// The key is copied so that mutating the original afterwards can't change its hash
+					ValueInner::Map { entries } => entries.borrow_mut().insert(key.deep_clone(), value),
+					_ => unreachable!()
+				};
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("V"), Type::new_generic("K"), Type::new_map(Type::new_generic("K"), Type::new_generic("V"))], im::vector![Type::new_map(Type::new_generic("K"), Type::new_generic("V"))]),
+			"insert"
+		).into(),
+		"__map_get".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let key = pop_value(typed_tree, stack)?;
+				let map = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let (value, found) = match (&map.inner, map.ty.as_map()) {
+					(ValueInner::Map { entries }, Some((_, value_ty))) => match entries.borrow().get(&key) {
+						Some(value) => (value.clone(), true),
+						None => (zero_value(typed_tree, value_ty)?, false)
+					},
+					_ => unreachable!()
+				};
+
+				stack.push(value);
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [found as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("K"), Type::new_map(Type::new_generic("K"), Type::new_generic("V"))], im::vector![Type::new_map(Type::new_generic("K"), Type::new_generic("V")), Type::new_generic("V"), Type::new_bool()]),
+			"get"
+		)).copying().zeroing(),
+		"__map_remove".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let key = pop_value(typed_tree, stack)?;
+				let map = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let (value, removed) = match (&map.inner, map.ty.as_map()) {
+					(ValueInner::Map { entries }, Some((_, value_ty))) => match entries.borrow_mut().remove(&key) {
+						Some(value) => (value, true),
+						None => (zero_value(typed_tree, value_ty)?, false)
+					},
+					_ => unreachable!()
+				};
+
+				stack.push(value);
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [removed as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("K"), Type::new_map(Type::new_generic("K"), Type::new_generic("V"))], im::vector![Type::new_map(Type::new_generic("K"), Type::new_generic("V")), Type::new_generic("V"), Type::new_bool()]),
+			"remove"
+		)).zeroing(),
+		"__map_contains".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let key = pop_value(typed_tree, stack)?;
+				let map = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let contains = match &map.inner {
+					ValueInner::Map { entries } => entries.borrow().contains_key(&key),
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [contains as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("K"), Type::new_map(Type::new_generic("K"), Type::new_generic("V"))], im::vector![Type::new_map(Type::new_generic("K"), Type::new_generic("V")), Type::new_bool()]),
+			"contains"
+		).into(),
+		"__map_len".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let map = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let len = match &map.inner {
+					ValueInner::Map { entries } => entries.borrow().len() as u32,
+					_ => unreachable!()
+				};
+
+				stack.push(Value::from_typed_bytes(Type::new_uint(32), len.to_ne_bytes()));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_map(Type::new_generic("K"), Type::new_generic("V"))], im::vector![Type::new_map(Type::new_generic("K"), Type::new_generic("V")), Type::new_uint(32)]),
+			"len"
+		).into(),
+		"__box".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				stack.push(Value::new_reference(value));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T")], im::vector![Type::new_ref(Type::new_generic("T"))]),
+			"box"
+		).into(),
+		"__cell".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				stack.push(Value::new_mut_reference(value));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T")], im::vector![Type::new_mut_ref(Type::new_generic("T"))]),
+			"cell"
+		).into(),
+		"__deref".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let reference = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let value = match &reference.inner {
+					ValueInner::Reference { to } => to.as_ref().clone(),
+					_ => unreachable!()
+				};
+
+				stack.push(value);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_ref(Type::new_generic("T"))], im::vector![Type::new_ref(Type::new_generic("T")), Type::new_generic("T")]),
+			"deref"
+		)).copying(),
+		"__deref_mut".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let reference = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				let value = match &reference.inner {
+					ValueInner::MutReference { to } => to.borrow().clone(),
+					_ => unreachable!()
+				};
+
+				stack.push(value);
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_mut_ref(Type::new_generic("T"))], im::vector![Type::new_mut_ref(Type::new_generic("T")), Type::new_generic("T")]),
+			"deref"
+		)).copying(),
+		"__store".into() => (
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let value = pop_value(typed_tree, stack)?;
+				let reference = stack.last().ok_or(RuntimeError::new(RuntimeErrorKind::StackUnderflowError, typed_tree.span))?;
+
+				match &reference.inner {
+					ValueInner::MutReference { to } => *to.borrow_mut() = value,
+					_ => unreachable!()
+				}
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_generic("T"), Type::new_mut_ref(Type::new_generic("T"))], im::vector![Type::new_mut_ref(Type::new_generic("T"))]),
+			"store"
+		).into(),
+		"__read_line".into() => BuiltinWord::from((
+			Rc::new(|typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let mut line = String::new();
+				std::io::stdin().read_line(&mut line).map_err(|err| RuntimeError::new(RuntimeErrorKind::IoError(err.to_string()), typed_tree.span))?;
+
+				if line.ends_with('\n') {
+					line.pop();
+				}
+
+				stack.push(Value::new_string(line));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new_pushed(im::vector![Type::new_string()]),
+			"read_line"
+		)).impure(),
+		"__hello".into() => BuiltinWord::from((
 			Rc::new(|typed_tree: &TypedTreeNode, fns: &im::OrdMap<String, TypedTreeNode>, types: &im::OrdMap<String, Type>, builtins: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
 				println!("Hello from tower interpreter");
 
 				Ok(())
 			}) as BuiltinWordFn,
 			StackEffect::none()
-		).into()
+		)).impure()
+	};
+
+	// Chars are stored as their code points, so are compared as u32s
+	for (op, f) in [("eq", u32::eq as fn(&u32, &u32) -> bool), ("ne", u32::ne), ("lt", u32::lt), ("le", u32::le), ("gt", u32::gt), ("ge", u32::ge)] {
+		words.insert(format!("__{op}_char"), (
+			Rc::new(move |typed_tree: &TypedTreeNode, _: &im::OrdMap<String, TypedTreeNode>, _: &im::OrdMap<String, Type>, _: &im::OrdMap<String, BuiltinWord>, stack: &mut Vec<Value>| -> Result<(), RuntimeError> {
+				let rhs = pop_value(typed_tree, stack)?;
+				let lhs = pop_value(typed_tree, stack)?;
+				let rhs = u32::from_ne_bytes(rhs.as_bytes().expect("Expected bytes").try_into().expect("Expected char"));
+				let lhs = u32::from_ne_bytes(lhs.as_bytes().expect("Expected bytes").try_into().expect("Expected char"));
+
+				stack.push(Value::from_typed_bytes(Type::new_bool(), [f(&lhs, &rhs) as u8]));
+
+				Ok(())
+			}) as BuiltinWordFn,
+			StackEffect::new(im::vector![Type::new_char(), Type::new_char()], im::vector![Type::new_bool()]),
+			op
+		).into());
 	}
+
+	int_builtins!(words,
+		u128 => Type::new_uint(128),
+		u64 => Type::new_uint(64),
+		u32 => Type::new_uint(32),
+		u16 => Type::new_uint(16),
+		u8 => Type::new_uint(8),
+		i128 => Type::new_int(128),
+		i64 => Type::new_int(64),
+		i32 => Type::new_int(32),
+		i16 => Type::new_int(16),
+		i8 => Type::new_int(8)
+	);
+
+	words
+
 		// "call".into() => (
 		// 	Rc::new(|stack: &mut Box<dyn TowerStack>, symbols: &HashMap<String, AnnotatedASTNode>| -> Result<(), RuntimeError> {
 		// 		let string = stack.pop_fnptr()?;
@@ -265,4 +1122,38 @@ pub fn builtin_functions() -> im::OrdMap<String, BuiltinWord> {
 	// 		StackEffect::new(im::vector![TowerType::Generic("A".into()), TowerType::Generic("A".into())], im::vector![TowerType::Generic("B".into())])
 	// 	)
 	// }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use crate::{analyser::{tree::TypedTree, ttype::Type, value::Value}, parser::{source_map::SourceMap, tree::Span}};
+
+	use super::builtin_functions;
+
+	/// Calls the builtin with lhs and rhs on the stack, returning the i32 it pushes or the error it raises
+	fn call_i32(name: &str, lhs: i32, rhs: i32) -> Result<i32, String> {
+		let builtins = builtin_functions();
+		let builtin = builtins.get(name).expect("Expected builtin");
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "");
+		let node = TypedTree::BuiltinWord { name: name.to_string(), effect: builtin.effect.clone() }.wrap(Span::point(file, 0));
+
+		let mut stack = vec![Value::from_typed_bytes(Type::new_int(32), lhs.to_ne_bytes()), Value::from_typed_bytes(Type::new_int(32), rhs.to_ne_bytes())];
+		(builtin.f)(&node, &im::OrdMap::new(), &im::OrdMap::new(), &builtins, &mut stack).map_err(|e| e.to_string())?;
+
+		Ok(i32::from_ne_bytes(stack.pop().expect("Expected result").as_bytes().expect("Expected bytes").try_into().expect("Expected i32")))
+	}
+
+	#[test]
+	fn signed_division_overflow_wraps() {
+		assert_eq!(call_i32("__div_i32", i32::MIN, -1), Ok(i32::MIN));
+		assert_eq!(call_i32("__rem_i32", i32::MIN, -1), Ok(0));
+		assert_eq!(call_i32("__div_i32", -7, 2), Ok(-3));
+		assert_eq!(call_i32("__rem_i32", -7, 2), Ok(-1));
+	}
+
+	#[test]
+	fn division_by_zero_errors() {
+		assert_eq!(call_i32("__div_i32", 1, 0), Err("attempted to divide by zero".to_string()));
+		assert_eq!(call_i32("__rem_i32", i32::MIN, 0), Err("attempted to divide by zero".to_string()));
+	}
+}
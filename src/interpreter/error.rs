@@ -1,31 +1,39 @@
 use std::{fmt::Display, io::{self, Write}};
 
-use crate::parser::scanner::Scanner;
+use crate::parser::{source_map::SourceMap, tree::Span};
 
 pub struct RuntimeError {
 	kind: RuntimeErrorKind,
-	cursor: usize
+	span: Span
 }
 
 impl RuntimeError {
-	pub fn new(kind: RuntimeErrorKind, cursor: usize) -> RuntimeError {
+	pub fn new(kind: RuntimeErrorKind, span: Span) -> RuntimeError {
 		RuntimeError {
 			kind,
-			cursor
+			span
 		}
 	}
 
-	/// Pretty-prints the error, including context retrieved from the scanner
-	pub fn print_error(&self, scanner: &Scanner, file_name: &str, mut writer: impl Write) -> Result<(), io::Error> {
+	pub fn kind(&self) -> &RuntimeErrorKind {
+		&self.kind
+	}
+
+	pub fn span(&self) -> Span {
+		self.span
+	}
+
+	/// Pretty-prints the error, including context retrieved from the file it is in
+	pub fn print_error(&self, sources: &SourceMap, mut writer: impl Write) -> Result<(), io::Error> {
 		// BUG: Alignment is off when there are multi-byte or multi code point characters such as ✨ in the context line before the cursor
 
-		let context = scanner.get_context(self.cursor);
-		let (col, row) = scanner.get_col_row(self.cursor);
+		let context = sources.get_context(self.span.file, self.span.start);
+		let (col, row) = sources.get_col_row(self.span.file, self.span.start);
 		let row_str = format!("{row}");
 		let num_tabs = context.chars().filter(|&c| c == '\t').count();
-		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".to_string() ].join("");
+		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".repeat(sources.get_underline_len(self.span)) ].join("");
 
-		writeln!(writer, "Runtime Error at {file_name}:{col}:{row} - {self}")?;
+		writeln!(writer, "Runtime Error at {}:{col}:{row} - {self}", sources.path(self.span.file))?;
 		writeln!(writer, "{} | ", " ".repeat(row_str.len()))?;
 		writeln!(writer, "{row} | {}", context.replace("\t", "    "))?;
 		writeln!(writer, "{cursor_indicator}")?;
@@ -49,6 +57,33 @@ impl Display for RuntimeError {
 			RuntimeErrorKind::ModuleNotFoundError => {
 				write!(f, "no module found")
 			},
+			RuntimeErrorKind::DivisionByZeroError => {
+				write!(f, "attempted to divide by zero")
+			},
+			RuntimeErrorKind::IndexOutOfBoundsError { index, len } => {
+				write!(f, "index {index} is out of bounds for length {len}")
+			},
+			RuntimeErrorKind::EmptyPopError => {
+				write!(f, "attempted to pop from an empty collection")
+			},
+			RuntimeErrorKind::IoError(err) => {
+				write!(f, "I/O error - {err}")
+			},
+			RuntimeErrorKind::ExternError(err) => {
+				write!(f, "error calling extern function {err}")
+			},
+			RuntimeErrorKind::ThrownError(err) => {
+				write!(f, "uncaught error - {err}")
+			},
+			RuntimeErrorKind::InvalidCharError(code) => {
+				write!(f, "{code:#x} is not a valid char (unicode scalar value)")
+			},
+			RuntimeErrorKind::NoZeroValueError(tname) => {
+				write!(f, "type {tname} has no zero value to push for a missing map key")
+			},
+			RuntimeErrorKind::Returning => {
+				write!(f, "return outside of any function called by name")
+			},
 		}
 	}
 }
@@ -58,5 +93,22 @@ pub enum RuntimeErrorKind {
 	StackUnderflowError,
 	Utf8Error(Vec<u8>),
 	FunctionMissingError(String),
-	ModuleNotFoundError
+	ModuleNotFoundError,
+	DivisionByZeroError,
+	IndexOutOfBoundsError {
+		index: usize,
+		len: usize
+	},
+	EmptyPopError,
+	IoError(String),
+	ExternError(String),
+	/// An error thrown by tower code with throw, which unwinds to the nearest try
+	ThrownError(String),
+	/// A code point converted to a char is a surrogate or above the maximum code point
+	InvalidCharError(u32),
+	/// A map's value type has no zero value to push for a missing key. The analyser rejects these maps, so this is only reached by
+	/// bypassing it
+	NoZeroValueError(String),
+	/// Not an error, but a return unwinding through quotation calls to the function called by name that it ends
+	Returning
 }
\ No newline at end of file
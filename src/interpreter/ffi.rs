@@ -0,0 +1,171 @@
+use std::{cell::RefCell, collections::HashMap, ffi::{c_char, c_int, c_void, CStr, CString}};
+
+use libffi::middle::{Arg, Cif, CodePtr, Type as FfiType};
+
+use crate::analyser::{stack_effect::StackEffect, tree::TypedTreeNode, ttype::{OpaqueTypeKind, Type}, value::Value};
+
+use super::{builtin::pop_value, error::{RuntimeError, RuntimeErrorKind}};
+
+extern "C" {
+	fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+	fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+	fn dlclose(handle: *mut c_void) -> c_int;
+	fn dlerror() -> *const c_char;
+}
+
+const RTLD_NOW: c_int = 2;
+
+/// The dynamic libraries loaded by call_extern, which stay loaded until the thread exits, and the functions found in them
+#[derive(Default)]
+struct Libraries {
+	handles: HashMap<String, *mut c_void>,
+	functions: HashMap<(String, String), *mut c_void>
+}
+
+impl Drop for Libraries {
+	fn drop(&mut self) {
+		for handle in self.handles.values() {
+			unsafe { dlclose(*handle) };
+		}
+	}
+}
+
+thread_local! {
+	static LIBRARIES: RefCell<Libraries> = RefCell::new(Libraries::default());
+}
+
+/// Returns the message of the last dlopen/dlsym error
+unsafe fn last_dl_error() -> String {
+	let err = dlerror();
+
+	if err.is_null() {
+		"unknown error".to_string()
+	} else {
+		CStr::from_ptr(err).to_string_lossy().into_owned()
+	}
+}
+
+/// Returns the address of the C function name in the dynamic library library, loading the library if it hasn't been already
+fn find_extern(library: &str, name: &str) -> Result<*mut c_void, String> {
+	LIBRARIES.with_borrow_mut(|libraries| {
+		if let Some(f) = libraries.functions.get(&(library.to_string(), name.to_string())) {
+			return Ok(*f);
+		}
+
+		let handle = match libraries.handles.get(library) {
+			Some(handle) => *handle,
+			None => {
+				let c_library = CString::new(library).map_err(|_| "library name contains a null byte".to_string())?;
+				let handle = unsafe { dlopen(c_library.as_ptr(), RTLD_NOW) };
+				if handle.is_null() {
+					return Err(unsafe { last_dl_error() });
+				}
+
+				libraries.handles.insert(library.to_string(), handle);
+				handle
+			}
+		};
+
+		let c_name = CString::new(name).map_err(|_| "function name contains a null byte".to_string())?;
+		let f = unsafe { dlsym(handle, c_name.as_ptr()) };
+		if f.is_null() {
+			return Err(unsafe { last_dl_error() });
+		}
+
+		libraries.functions.insert((library.to_string(), name.to_string()), f);
+		Ok(f)
+	})
+}
+
+/// Returns the libffi type describing how a value of type ty is passed to or returned from C
+fn ffi_type(ty: &Type) -> FfiType {
+	match ty {
+		Type::Reference { to: _, mutable: _ } => FfiType::pointer(),
+		Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => FfiType::u8(),
+		Type::Opaque { size: Some(size), kind } => match (size, kind == &OpaqueTypeKind::SignedInt) {
+			(1, false) => FfiType::u8(),
+			(1, true) => FfiType::i8(),
+			(2, false) => FfiType::u16(),
+			(2, true) => FfiType::i16(),
+			(4, false) => FfiType::u32(),
+			(4, true) => FfiType::i32(),
+			(8, false) => FfiType::u64(),
+			(8, true) => FfiType::i64(),
+			_ => unreachable!("Extern types are checked by the analyser")
+		},
+		_ => unreachable!("Extern types are checked by the analyser")
+	}
+}
+
+/// Calls the C function name in the dynamic library library with arguments popped off the stack as described by effect, pushing its
+/// return value, if any. The function is called through libffi using the types in effect, so it has to match the C declaration
+pub fn call_extern(typed_tree: &TypedTreeNode, library: &str, name: &str, effect: &StackEffect, stack: &mut Vec<Value>) -> Result<(), RuntimeError> {
+	let extern_error = |msg: String| RuntimeError::new(RuntimeErrorKind::ExternError(format!("{name} in {library}: {msg}")), typed_tree.span);
+
+	let f = find_extern(library, name).map_err(extern_error)?;
+
+	// Strings passed to the function have to outlive the call
+	let mut c_strings = Vec::new();
+	// Each argument is stored in the first bytes of its own 64 bits, which is where libffi reads it from
+	let mut args = vec![0u64; effect.popped().len()];
+
+	// The last parameter is on top of the stack, so is popped first
+	for (i, ty) in effect.popped().iter().enumerate() {
+		let value = pop_value(typed_tree, stack)?;
+		let param = effect.popped().len() - 1 - i;
+
+		let bytes = match ty {
+			Type::Reference { to: _, mutable: _ } => {
+				let string = value.as_strref().ok_or_else(|| extern_error(format!("argument {param} of type {} is not a string", ty.name())))?;
+				let c_string = CString::new(string).map_err(|_| extern_error("string argument contains a null byte".to_string()))?;
+				let ptr = c_string.as_ptr() as usize;
+				c_strings.push(c_string);
+
+				ptr.to_ne_bytes().to_vec()
+			}
+			Type::Opaque { size: _, kind: _ } => value.as_bytes().expect("Expected bytes").to_vec(),
+			_ => unreachable!()
+		};
+
+		let mut buf = [0u8; 8];
+		buf[..bytes.len()].copy_from_slice(&bytes);
+		args[param] = u64::from_ne_bytes(buf);
+	}
+
+	// The parameters are listed from the bottom of the stack to the top, the reverse of popped
+	let cif = Cif::new(effect.popped().iter().rev().map(ffi_type), effect.pushed().front().map_or(FfiType::void(), ffi_type));
+	let arg_ptrs: Vec<Arg> = args.iter().map(Arg::new).collect();
+
+	// Integer return values are widened to the size of a register by libffi
+	let ret: u64 = unsafe { cif.call(CodePtr(f), &arg_ptrs) };
+
+	if let Some(ty) = effect.pushed().front() {
+		let value = match ty {
+			Type::Reference { to: _, mutable: _ } => {
+				if ret == 0 {
+					return Err(extern_error("returned a null string".to_string()));
+				}
+
+				let bytes = unsafe { CStr::from_ptr(ret as usize as *const c_char) }.to_bytes().to_vec();
+				if std::str::from_utf8(&bytes).is_err() {
+					return Err(RuntimeError::new(RuntimeErrorKind::Utf8Error(bytes), typed_tree.span));
+				}
+
+				Value::new_reference(Value::from_typed_bytes(Type::new_str(bytes.len()), bytes))
+			}
+			Type::Opaque { size: _, kind: OpaqueTypeKind::Bool } => Value::from_typed_bytes(ty.clone(), [(ret as u8 != 0) as u8]),
+			// The return value is truncated rather than its bytes taken, as which end of it holds the value depends on the host
+			Type::Opaque { size: Some(size), kind: _ } => Value::from_typed_bytes(ty.clone(), match size {
+				1 => (ret as u8).to_ne_bytes().to_vec(),
+				2 => (ret as u16).to_ne_bytes().to_vec(),
+				4 => (ret as u32).to_ne_bytes().to_vec(),
+				_ => ret.to_ne_bytes().to_vec()
+			}),
+			_ => unreachable!()
+		};
+
+		stack.push(value);
+	}
+
+	Ok(())
+}
@@ -1,30 +1,53 @@
-use tower::{analyser::{self, tree::{TypedTree, TypedTreeNode}}, compiler, interpreter::{builtin::builtin_functions, interp}, parser::{self, result::ScanResult, scanner::Scanner, tree::{ParseTree, ParseTreeNode}}};
+use tower::{analyser::{self, tree::{TypedTree, TypedTreeNode}}, compiler, interpreter::{builtin::builtin_functions, interp}, parser::{self, cfg::{self, Backend, CfgOptions}, result::ScanResult, source_map::SourceMap, tree::{ParseTree, ParseTreeNode}}};
 
 fn main() {
 	// compiler::compile_test_program();
 	// return;
 
+	// User-defined cfg flags and key-value pairs are passed with `--cfg flag` or `--cfg key=value`
+	let mut cfg_args = Vec::new();
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		if arg == "--cfg" {
+			match args.next() {
+				Some(cfg_arg) => cfg_args.push(cfg_arg),
+				None => {
+					eprintln!("Expected a flag or key=value after --cfg");
+					return;
+				}
+			}
+		}
+	}
+	let cfg_options = |backend| {
+		let mut options = CfgOptions::new(backend);
+		for arg in &cfg_args {
+			options.set_arg(arg);
+		}
+		options
+	};
+
 	let towercode = include_str!("../compilerdev.tower");
-	// let tokens = tokenise(towercode).unwrap();
-	// println!("TOKENS: {:?}", tokens);
 
-	let mut scanner = Scanner::new(&towercode, "compilerdev.tower");
-	let parse_tree = match parser::parse(&mut scanner) {
+	let mut sources = SourceMap::new();
+	let file = sources.add_file("compilerdev.tower", towercode);
+	// Parsing recovers from syntax errors, so the rest of the file is still analysed to report any other errors, but isn't run
+	let (parse_tree, syntax_errors) = parser::parse(&sources, file);
+	for e in &syntax_errors {
+		e.print_error(&sources, std::io::stderr()).unwrap();
+	}
+	let interp_tree = match cfg::apply(&parse_tree, &cfg_options(Backend::Interpreter)) {
 		ScanResult::Valid(tree) => tree,
 		ScanResult::WithErr(e) => {
-			e.print_error(&scanner, scanner.file_path(), std::io::stderr()).unwrap();
-			return;
-		}
-		ScanResult::Unrecognised => {
-			eprintln!("No module recognised");
+			e.print_error(&sources, std::io::stderr()).unwrap();
 			return;
 		}
+		ScanResult::Unrecognised => unreachable!()
 	};
 	let builtin_words = builtin_functions();
-	let typed_tree = match analyser::analyse(&parse_tree, &builtin_words) {
+	let typed_tree = match analyser::analyse(&interp_tree, &builtin_words) {
 		ScanResult::Valid(tree) => tree,
 		ScanResult::WithErr(e) => {
-			e.print_error(&scanner, scanner.file_path(), std::io::stderr()).unwrap();
+			e.print_error(&sources, std::io::stderr()).unwrap();
 			return;
 		}
 		ScanResult::Unrecognised => {
@@ -32,6 +55,14 @@ fn main() {
 			return;
 		}
 	};
+	let warnings = analyser::warnings(&interp_tree);
+	for warning in &warnings {
+		warning.print_warning(&sources, std::io::stderr()).unwrap();
+	}
+
+	if !syntax_errors.is_empty() {
+		return;
+	}
 
 	println!("\n=== PARSE TREE ===\n");
 
@@ -46,7 +77,7 @@ fn main() {
 	let stack = match interp(&typed_tree, &builtin_words) {
 		Ok(stack) => stack,
 		Err(e) => {
-			e.print_error(&scanner, "main.tower", std::io::stderr()).unwrap();
+			e.print_error(&sources, std::io::stderr()).unwrap();
 			return;
 		}
 	};
@@ -57,6 +88,40 @@ fn main() {
 
 	println!("Stack: [{}]", stack.iter().map(|v| format!("{v}")).collect::<Vec<String>>().join(", "));
 
+	// Items can be conditional on the backend, so the tree is filtered and analysed again for the compiler
+	let compiled_tree = match cfg::apply(&parse_tree, &cfg_options(Backend::Compiled)) {
+		ScanResult::Valid(tree) => tree,
+		ScanResult::WithErr(e) => {
+			e.print_error(&sources, std::io::stderr()).unwrap();
+			return;
+		}
+		ScanResult::Unrecognised => unreachable!()
+	};
+	let typed_tree = match analyser::analyse(&compiled_tree, &builtin_words) {
+		ScanResult::Valid(tree) => tree,
+		ScanResult::WithErr(e) => {
+			e.print_error(&sources, std::io::stderr()).unwrap();
+			return;
+		}
+		ScanResult::Unrecognised => {
+			eprintln!("Unrecognised parse tree");
+			return;
+		}
+	};
+	// Warnings in items shared by both backends have already been printed
+	for warning in analyser::warnings(&compiled_tree).iter().filter(|warning| !warnings.contains(warning)) {
+		warning.print_warning(&sources, std::io::stderr()).unwrap();
+	}
+
+	// Functions marked with export are declared in a header next to the tower file, for C programs to link against
+	if let Some(header) = compiler::generate_header(&typed_tree) {
+		let header_path = std::path::Path::new(sources.path(file)).with_extension("h");
+
+		if let Err(e) = std::fs::write(&header_path, header) {
+			eprintln!("Failed to write header {}: {e}", header_path.display());
+		}
+	}
+
 	println!("\n=== LLVM OUTPUT ===\n");
 
 	compiler::compile(typed_tree);
@@ -88,26 +153,35 @@ fn main() {
 fn dump_parse_tree(tree: &ParseTreeNode, depth: u32) -> String { // TODO: depth is not used - Use it or remove it
 	match &tree.tree {
 		ParseTree::Module { name, elems } => format!("Module(name: {name}, elems: [\n{}])", elems.iter().map(|(elem_name, elem)| format!("\t{elem_name}: {},\n", dump_parse_tree(elem, depth + 1))).collect::<String>()),
-		ParseTree::Function { name, body } => format!("Function(name: {name}, body: [\n{}\t])", body.iter().map(|node| format!("\t\t{},\n", dump_parse_tree(node, depth + 1))).collect::<String>()),
+		ParseTree::Function { name, body, exported, comptime, attributes, doc } => format!("Function(name: {name}, exported: {exported}, comptime: {comptime}, attributes: {attributes:?}, doc: {doc:?}, body: [\n{}\t])", body.iter().map(|node| format!("\t\t{},\n", dump_parse_tree(node, depth + 1))).collect::<String>()),
 		ParseTree::Literal(lit) => format!("Literal({lit:?})"),
 		ParseTree::Identifier(word) => format!("Identifier({word})"),
-		ParseTree::Struct { name, fields } => format!("Struct(name: {name}, fields: [\n{}\t])", fields.iter().map(|(fname, ftype)| format!("\t\t{fname}: {ftype},\n")).collect::<String>()),
+		ParseTree::Struct { name, fields, attributes, doc, field_docs } => format!("Struct(name: {name}, attributes: {attributes:?}, doc: {doc:?}, fields: [\n{}\t])", fields.iter().map(|(fname, ftype)| format!("\t\t{fname}: {} (doc: {:?}),\n", ftype.value, field_docs.get(fname))).collect::<String>()),
 		ParseTree::Enum { name, fields } => format!("Struct(name: {name}, [\n{}\t])", fields.iter().map(|(fname, ftype)| format!("\t\t{fname} {ftype},\n")).collect::<String>()),
+		ParseTree::Extern { name, library, params, returns } => format!("Extern(name: {name}, library: {library}, params: [{}], returns: [{}])", params.iter().map(|tname| tname.value.clone()).collect::<Vec<String>>().join(", "), returns.iter().map(|tname| tname.value.clone()).collect::<Vec<String>>().join(", ")),
+		ParseTree::StaticAssert { message, body } => format!("StaticAssert(message: {message:?}, body: [\n{}\t])", body.iter().map(|node| format!("\t\t{},\n", dump_parse_tree(node, depth + 1))).collect::<String>()),
+		ParseTree::Cfg { condition, name, item } => format!("Cfg(condition: {condition:?}, name: {name}, item: {})", dump_parse_tree(item, depth + 1)),
 		ParseTree::Constructor(ty) => format!("Constructor(of: {ty})"),
-		ParseTree::FieldAccess(ident) => format!("FieldAccess(field: {ident})")
+		ParseTree::FieldAccess(ident) => format!("FieldAccess(field: {ident})"),
+		ParseTree::Invalid => "Invalid".to_string()
 	}
 }
 
 fn dump_typed_tree(tree: &TypedTreeNode, depth: u32) -> String {
 	match &tree.tree {
 		TypedTree::Module { name, elems } => format!("Module(name: {name}, elems: [\n{}])", elems.iter().map(|(elem_name, elem)| format!("\t{elem_name}: {},\n", dump_typed_tree(elem, depth + 1))).collect::<String>()),
-		TypedTree::Function { name, effect, body } => format!("Function(name: {name}, effect: {effect}, body: [\n{}\t])", body.iter().map(|node| format!("\t\t{},\n", dump_typed_tree(node, depth + 1))).collect::<String>()),
-		TypedTree::Type(ty) => format!("Type({ty})"),
+		TypedTree::Function { name, effect, body, exported, attributes, doc } => format!("Function(name: {name}, effect: {effect}, exported: {exported}, attributes: {attributes:?}, doc: {doc:?}, body: [\n{}\t])", body.iter().map(|node| format!("\t\t{},\n", dump_typed_tree(node, depth + 1))).collect::<String>()),
+		TypedTree::Type { ty, doc, field_docs } => format!("Type(type: {ty}, doc: {doc:?}, field docs: {field_docs:?})"),
+		TypedTree::Extern { name, library, effect } => format!("Extern(name: {name}, library: {library}, effect: {effect})"),
+		TypedTree::StaticAssert { message } => format!("StaticAssert(message: {message:?})"),
+		TypedTree::Constant { name, ty, value: _, doc } => format!("Constant(name: {name}, type: {ty}, value: (unable to be displayed), doc: {doc:?})"),
 		TypedTree::Word(word) => format!("Word({word})"),
-		TypedTree::BuiltinWord(word) => format!("BuiltinWord({word})"),
+		TypedTree::BuiltinWord { name, effect } => format!("BuiltinWord(name: {name}, effect: {effect})"),
 		TypedTree::Literal { ty, value } => format!("Literal(type: {ty}, value: (unable to be displayed))"),
 		TypedTree::Constructor { ty, effect } => format!("Constructor(of: {ty}, effect: {effect})"),
-		TypedTree::FieldAccess { name } => format!("FieldAccess(field: {name})")
+		TypedTree::FieldAccess { name, of } => format!("FieldAccess(field: {name}, of: {of})"),
+		TypedTree::Return => "Return".to_string(),
+		TypedTree::Invalid => "Invalid".to_string()
 	}
 }
 
@@ -1,17 +1,26 @@
 pub mod scanner;
+pub mod lexer;
+pub mod tokens;
 pub mod result;
 pub mod tree;
 pub mod error;
+pub mod cfg;
+pub mod cst;
+pub mod source_map;
+pub mod incremental;
 
-use std::{num::IntErrorKind, path::Path};
+use std::path::Path;
 
+use cst::{CstKind, CstNode};
 use error::{SyntaxError, SyntaxErrorKind};
+use lexer::TokenKind;
 use result::ScanResult::{self, Valid, WithErr, Unrecognised};
 use scanner::Scanner;
-use tree::{ParseTree, ParseTreeNode, ParseTreeType, Literal};
-use unicode_xid::UnicodeXID;
+use source_map::{FileId, SourceMap};
+use tokens::TokenStream;
+use tree::{Attribute, CfgCondition, ParseTree, ParseTreeNode, ParseTreeType, Literal, Span, Spanned};
 
-use crate::{analyser::TowerType, brk};
+use crate::brk;
 
 type ParseResult<T> = ScanResult<T, SyntaxError>;
 
@@ -25,74 +34,257 @@ pub enum TokenType { // TODO: Evaluate these, and ideally have these represented
 	Literal,
 	Number,
 	KeywordFn,
+	KeywordExtern,
+	KeywordStruct,
 	Quote,
 	EscapeSequence,
 	Block,
 	Colon,
-	ConstructorArrow
+	ConstructorArrow,
+	LSquareParen,
+	RSquareParen,
+	Semicolon,
+	Comma,
+	RAngleParen,
+	LRoundParen,
+	RRoundParen,
+	StackEffectArrow,
+	Type
 }
 
-pub fn parse(scanner: &mut Scanner) -> ParseResult<ParseTreeNode> {
-	let cursor = scanner.cursor();
+/// Splits the content of the file into tokens, and parses them into a Module ParseTreeNode named after the file. Syntax errors don't stop
+/// parsing - an item or block that fails to parse is skipped up to the start of the next item or the end of the block, with a
+/// ParseTree::Invalid placeholder left in its place, and every error is returned alongside the tree
+pub fn parse(sources: &SourceMap, file: FileId) -> (ParseTreeNode, Vec<SyntaxError>) {
+	let (mut tokens, ret) = parse_tokens(sources, file);
 
-	let ret = brk!(module(scanner));
+	(ret, tokens.take_errors())
+}
 
-	scanner.take_any(s);
+/// Parses the file like parse, but returns its lossless concrete syntax tree, which keeps every character of the file including
+/// whitespace, comments and the exact spelling of literals. `cst::lower` turns it into the tree parse returns
+pub fn parse_cst(sources: &SourceMap, file: FileId) -> (CstNode, Vec<SyntaxError>) {
+	let (mut tokens, _) = parse_tokens(sources, file);
 
-	if scanner.has_next() {
-		return WithErr(SyntaxError::new(SyntaxErrorKind::Unexpected, ParseTreeType::Module, scanner.cursor()));
-	}
+	(tokens.build_cst(sources.content(file)), tokens.take_errors())
+}
+
+/// Splits the content of the file into tokens and parses them, returning the tokens along with the CST nodes and errors recorded while
+/// parsing them
+fn parse_tokens(sources: &SourceMap, file: FileId) -> (TokenStream, ParseTreeNode) {
+	let mut scanner = Scanner::new(file, sources.content(file));
+
+	let (tokens, errors) = lexer::tokenise(&mut scanner);
+	let mut tokens = TokenStream::new(tokens, errors, file, scanner.cursor());
 
-	Valid(ret.wrap(scanner.file_path(), cursor))
+	let modname = Path::new(sources.path(file)).file_stem().expect("Invalid file path?");
+	let ret = module(&mut tokens, modname.to_str().expect("Invalid UTF-8 in file path"));
+
+	(tokens, ret.wrap(Span::new(file, 0, scanner.cursor())))
 }
 
 /// Returns a Module ASTNode
-fn module(scanner: &mut Scanner) -> ParseResult<ParseTree> {
+fn module(tokens: &mut TokenStream, name: &str) -> ParseTree {
 	eprintln!("module");
 
-	let (nodes, err) = scanner.take_any(|scanner| {
-		scanner.take_any(s);
+	let mut elems = im::OrdMap::new();
+
+	loop {
+		let start = tokens.position();
+		// Items cover the doc comments before them
+		let cursor = tokens.doc_cursor();
+		let marker = tokens.start_documented();
+
+		match item(tokens) {
+			Valid((name, node)) => {
+				tokens.complete(marker, item_kind(&node.tree));
+
+				elems.insert(name, node);
+			}
+			WithErr(e) => {
+				tokens.report(e);
 
-		scanner.take_choice(vec![
-			Box::new(|scanner| {
-				let cursor = scanner.cursor();
-				let (name, body) = brk!(function(scanner));
+				// The placeholder takes the name of the item if it got that far, so that uses of it aren't reported as errors too
+				let name = item_name(tokens, start).unwrap_or_else(|| format!("invalid {cursor}"));
 
-				Valid((name, body.wrap(scanner.file_path(), cursor)))
-			}),
-			Box::new(|scanner| {
-				let cursor = scanner.cursor();
-				let (name, structure) = brk!(structure(scanner));
+				skip_to_item(tokens);
+				tokens.complete(marker, CstKind::Error);
+
+				elems.insert(name, ParseTree::Invalid.wrap(tokens.span_from(cursor)));
+			}
+			Unrecognised if tokens.has_next() => {
+				tokens.report(SyntaxError::new(SyntaxErrorKind::Unexpected, ParseTreeType::Module, tokens.point()));
+
+				tokens.pop();
+				skip_to_item(tokens);
+				tokens.complete(marker, CstKind::Skipped);
+			}
+			Unrecognised => {
+				tokens.abandon(marker);
+				break;
+			}
+		}
+	}
+
+	eprintln!("module end");
 
-				Valid((name, structure.wrap(scanner.file_path(), cursor)))
-			})
-		])
+	ParseTree::Module {
+		name: name.to_string(),
+		elems
+	}
+}
+
+/// Returns an item, preceded by any doc comments and then attributes, paired with the name it is stored under in the module
+fn item(tokens: &mut TokenStream) -> ParseResult<(String, ParseTreeNode)> {
+	let cursor = tokens.doc_cursor();
+	let doc = tokens.doc();
+	let (item_attributes, err) = tokens.take_any(|tokens| {
+		let attribute_cursor = tokens.cursor();
+		let attribute = brk!(tokens.node(CstKind::Attribute, attribute));
+
+		Valid((attribute, attribute_cursor))
 	});
 	if let Some(e) = err {
 		return WithErr(e);
 	}
 
-	let elems = nodes.into_iter().collect();
+	let mut conditions = Vec::new();
+	let mut attributes = Vec::new();
+	for (attribute, attribute_cursor) in item_attributes {
+		match attribute {
+			ItemAttribute::Cfg(condition) => conditions.push(condition),
+			ItemAttribute::Attribute(attribute) => attributes.push((attribute, attribute_cursor))
+		}
+	}
 
-	eprintln!("module end");
+	let item = tokens.take_choice(vec![
+		Box::new(|tokens| {
+			let cursor = tokens.cursor();
+			let (name, body) = brk!(function(tokens));
+
+			Valid((name, body.wrap(tokens.span_from(cursor))))
+		}),
+		Box::new(|tokens| {
+			let cursor = tokens.cursor();
+			let (name, structure) = brk!(structure(tokens));
 
-	let modname = Path::new(scanner.file_path()).file_stem().expect("Invalid file path?");
+			Valid((name, structure.wrap(tokens.span_from(cursor))))
+		}),
+		Box::new(|tokens| {
+			let cursor = tokens.cursor();
+			let (name, extern_fn) = brk!(extern_function(tokens));
 
-	Valid(ParseTree::Module {
-		name: modname.to_str().expect("Invalid UTF-8 in file path").to_string(),
-		elems
+			Valid((name, extern_fn.wrap(tokens.span_from(cursor))))
+		}),
+		Box::new(|tokens| {
+			let cursor = tokens.cursor();
+			let (name, assertion) = brk!(static_assert(tokens));
+
+			Valid((name, assertion.wrap(tokens.span_from(cursor))))
+		})
+	]);
+
+	let (name, mut item) = if conditions.is_empty() && attributes.is_empty() {
+		brk!(item)
+	} else {
+		brk!(item.require(SyntaxError::expected(vec![TokenType::KeywordFn, TokenType::KeywordStruct, TokenType::KeywordExtern], ParseTreeType::Item, tokens.point())))
+	};
+
+	brk!(attach_attributes(&mut item, attributes));
+
+	if let ParseTree::Function { doc: item_doc, .. } | ParseTree::Struct { doc: item_doc, .. } = &mut item.tree {
+		*item_doc = doc;
+	}
+
+	// The item covers its doc comments and attributes, so that it can be reparsed on its own
+	item.span = tokens.span_from(cursor);
+
+	if conditions.is_empty() {
+		return Valid((name, item));
+	}
+
+	// Multiple cfg attributes must all hold
+	let condition = if conditions.len() == 1 {
+		conditions.into_iter().next().unwrap()
+	} else {
+		CfgCondition::All(conditions.into_iter().collect())
+	};
+
+	// Items with the same name can be given for different configurations, so each is stored under a unique name until the cfg
+	// conditions are applied
+	Valid((format!("{name} cfg {cursor}"), ParseTree::Cfg { condition, name, item: Box::new(item) }.wrap(tokens.span_from(cursor))))
+}
+
+/// Returns the kind of CST node an item is parsed into
+fn item_kind(tree: &ParseTree) -> CstKind {
+	match tree {
+		ParseTree::Function { .. } => CstKind::Function,
+		ParseTree::Struct { .. } => CstKind::Struct,
+		ParseTree::Extern { .. } => CstKind::Extern,
+		ParseTree::StaticAssert { .. } => CstKind::StaticAssert,
+		ParseTree::Cfg { item, .. } => item_kind(&item.tree),
+		_ => unreachable!()
+	}
+}
+
+/// Returns whether the token starts an item or the attributes before one
+fn is_item_start(kind: &TokenKind) -> bool {
+	match kind {
+		TokenKind::Identifier(ident) => matches!(ident.as_str(), "fn" | "struct" | "extern" | "static_assert" | "export" | "const"),
+		TokenKind::Punct(c) => *c == '@',
+		_ => false
+	}
+}
+
+/// Skips tokens up to the start of the next item, or the end of the file
+fn skip_to_item(tokens: &mut TokenStream) {
+	while tokens.peek().is_some_and(|kind| !is_item_start(kind)) {
+		tokens.pop();
+	}
+}
+
+/// Returns the name of the function or struct which failed to parse between the position start and the current position, if its name
+/// was reached
+fn item_name(tokens: &mut TokenStream, start: usize) -> Option<String> {
+	let end = tokens.position();
+
+	tokens.peek_ahead(|tokens| {
+		tokens.set_position(start);
+
+		while tokens.position() < end && tokens.has_next() {
+			if tokens.take_keyword("fn") || tokens.take_keyword("struct") {
+				if let Valid(ParseTree::Identifier(ident)) = word(tokens) {
+					return Some(ident);
+				}
+
+				break;
+			}
+
+			tokens.pop();
+		}
+
+		None
 	})
 }
 
-/// Returns a Function ASTNode, paired with the function name
-fn function(scanner: &mut Scanner) -> ParseResult<(String, ParseTree)> {
+/// Returns a Function ASTNode, paired with the function name. Functions marked with `export` are compiled with a C-ABI wrapper, and
+/// `const fn`s are evaluated during analysis
+fn function(tokens: &mut TokenStream) -> ParseResult<(String, ParseTree)> {
 	eprintln!("function");
 
-	brk!(ParseResult::from(scanner.take_str("fn")));
+	let exported = tokens.take_keyword("export");
 
-	brk!(ParseResult::from(scanner.take_some(s)).require(SyntaxError::expected(vec![TokenType::Whitespace], ParseTreeType::Function, scanner.cursor())));//.ok_or(SyntaxError::expected(vec![TokenType::Whitespace], ASTNodeType::Function, scanner.cursor()));
+	let comptime = !exported && tokens.take_keyword("const");
 
-	let fn_name = match identifier(scanner).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Function, scanner.cursor())) {
+	if exported || comptime {
+		brk!(ParseResult::from(tokens.take_keyword("fn")).require(SyntaxError::expected(vec![TokenType::KeywordFn], ParseTreeType::Function, tokens.point())));
+	} else {
+		brk!(ParseResult::from(tokens.take_keyword("fn")));
+	}
+
+	brk!(separator(tokens, ParseTreeType::Function));
+
+	let fn_name = match tokens.node(CstKind::Name, word).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Function, tokens.point())) {
 		Valid(ParseTree::Identifier(s)) => s,
 		WithErr(e) => {
 			return WithErr(e);
@@ -100,9 +292,7 @@ fn function(scanner: &mut Scanner) -> ParseResult<(String, ParseTree)> {
 		_ => unreachable!()
 	};
 
-	scanner.take_any(s);
-
-	let fn_body = brk!(block(scanner).require(SyntaxError::expected(vec![TokenType::Block], ParseTreeType::Function, scanner.cursor())));//.ok_or(SyntaxError::expected(vec![TokenType::Block], scanner.cursor()))?;
+	let fn_body = brk!(tokens.node(CstKind::Block, block).require(SyntaxError::expected(vec![TokenType::Block], ParseTreeType::Function, tokens.point())));//.ok_or(SyntaxError::expected(vec![TokenType::Block], scanner.cursor()))?;
 
 	eprintln!("function end");
 
@@ -110,371 +300,506 @@ fn function(scanner: &mut Scanner) -> ParseResult<(String, ParseTree)> {
 		fn_name.to_string(),
 		ParseTree::Function {
 			name: fn_name.to_string(),
-			body: fn_body
+			body: fn_body,
+			exported,
+			comptime,
+			attributes: im::Vector::new(),
+			doc: None
 		}
 	))
 }
 
-fn structure(scanner: &mut Scanner) -> ParseResult<(String, ParseTree)> {
+fn structure(tokens: &mut TokenStream) -> ParseResult<(String, ParseTree)> {
 	eprintln!("struct");
 
-	brk!(scanner.take_str("struct").into());
-
-	brk!(ParseResult::from(scanner.take_some(s)).require(SyntaxError::expected(vec![TokenType::Whitespace], ParseTreeType::Struct, scanner.cursor())));
+	brk!(tokens.take_keyword("struct").into());
 
-	let name = match brk!(identifier(scanner).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Struct, scanner.cursor()))) {
+	let name = match brk!(tokens.node(CstKind::Name, identifier).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Struct, tokens.point()))) {
 		ParseTree::Identifier(s) => s,
 		_ => unreachable!()
 	};
 
-	scanner.take_any(s);
+	brk!(ParseResult::from(tokens.take_punct('{')).require(SyntaxError::expected(vec![TokenType::LCurlyParen], ParseTreeType::Struct, tokens.point())));
 
-	brk!(ParseResult::from(scanner.take('{')).require(SyntaxError::expected(vec![TokenType::LCurlyParen], ParseTreeType::Struct, scanner.cursor())));
-
-	let (fields, err) = scanner.take_any::<(String, String), SyntaxError>(|scanner| {
-		scanner.take_any(s);
-
-		let field_name = match brk!(identifier(scanner)) {
+	let (fields, err) = tokens.take_any::<(String, Spanned<String>, Option<String>), SyntaxError>(|tokens| tokens.documented_node(CstKind::Field, |tokens| {
+		let doc = tokens.doc();
+		let cursor = tokens.cursor();
+		let field_name = match brk!(tokens.node(CstKind::Name, identifier)) {
 			ParseTree::Identifier(s) => s,
 			_ => unreachable!()
 		};
 
-		scanner.take_any(s);
-
-		brk!(ParseResult::from(scanner.take(':')).require(SyntaxError::expected(vec![TokenType::Colon], ParseTreeType::Struct, scanner.cursor())));
-
-		scanner.take_any(s);
+		brk!(ParseResult::from(tokens.take_punct(':')).require(SyntaxError::expected(vec![TokenType::Colon], ParseTreeType::Struct, tokens.point())));
 
-		let field_type = match brk!(ParseResult::from(identifier(scanner)).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Struct, scanner.cursor()))) {
-			ParseTree::Identifier(s) => s,
-			_ => unreachable!()
-		};
+		let field_type = brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Struct, tokens.point())));
 
-		Valid((field_name, field_type))
-	});
+		Valid((field_name, Spanned::new(field_type, tokens.span_from(cursor)), doc))
+	}));
 	if let Some(e) = err {
 		return WithErr(e);
 	}
 
-	scanner.take_any(s);
+	brk!(ParseResult::from(tokens.take_punct('}')).require(SyntaxError::expected(vec![TokenType::RCurlyParen], ParseTreeType::Struct, tokens.point())));
 
-	brk!(ParseResult::from(scanner.take('}')).require(SyntaxError::expected(vec![TokenType::RCurlyParen], ParseTreeType::Struct, scanner.cursor())));
-
-	let fields: im::OrdMap<String, String> = fields.into_iter().collect();
+	let field_docs = fields.iter().filter_map(|(fname, _, doc)| Some((fname.clone(), doc.clone()?))).collect();
+	let fields: im::OrdMap<String, Spanned<String>> = fields.into_iter().map(|(fname, ftype, _)| (fname, ftype)).collect();
 
 	eprintln!("struct end");
 
 	Valid((
 		name.clone(),
-		ParseTree::Struct { name, fields }
+		ParseTree::Struct { name, fields, attributes: im::Vector::new(), doc: None, field_docs }
 	))
 }
 
-/// Returns a Block ASTNode
-fn block(scanner: &mut Scanner) -> ParseResult<im::Vector<ParseTreeNode>> {
-	eprintln!("block");
+/// Returns an Extern ASTNode declaring a C function, e.g. `extern "libc.so.6" fn strlen (&str -> u64)`, paired with the function name
+fn extern_function(tokens: &mut TokenStream) -> ParseResult<(String, ParseTree)> {
+	brk!(tokens.take_keyword("extern").into());
 
-	brk!(scanner.take('{').into());
+	let library = brk!(literal_string(tokens).require(SyntaxError::expected(vec![TokenType::Quote], ParseTreeType::Extern, tokens.point())));
 
-	let (nodes, err) = scanner.take_any(|scanner| -> ParseResult<ParseTreeNode> {
-		scanner.take_any(s);
+	brk!(ParseResult::from(tokens.take_keyword("fn")).require(SyntaxError::expected(vec![TokenType::KeywordFn], ParseTreeType::Extern, tokens.point())));
 
-		let cursor = scanner.cursor();
+	let name = match brk!(tokens.node(CstKind::Name, identifier).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Extern, tokens.point()))) {
+		ParseTree::Identifier(s) => s,
+		_ => unreachable!()
+	};
 
-		let ret = brk!(scanner.take_choice(vec![
-			Box::new(identifier),
-			Box::new(literal),
-			Box::new(constructor_struct),
-			Box::new(field_access)
-		]));
+	let (params, returns) = brk!(tokens.node(CstKind::StackEffect, stack_effect).require(SyntaxError::expected(vec![TokenType::LRoundParen], ParseTreeType::Extern, tokens.point())));
 
-		scanner.take_any(s);
+	Valid((
+		name.clone(),
+		ParseTree::Extern { name, library, params, returns }
+	))
+}
 
-		Valid(ret.wrap(scanner.file_path(), cursor))
-	});
-	if let Some(e) = err {
-		return WithErr(e);
-	}
+/// Returns a StaticAssert ASTNode, e.g. `static_assert "message" { 1u 1u __eq_u32 }`, paired with a name unique to it. The name contains
+/// a space, so can't clash with the name of any other item
+fn static_assert(tokens: &mut TokenStream) -> ParseResult<(String, ParseTree)> {
+	let cursor = tokens.cursor();
 
-	scanner.take_any(s);
+	brk!(tokens.take_keyword("static_assert").into());
 
-	brk!(ParseResult::from(scanner.take('}')).require(SyntaxError::expected(vec![TokenType::Identifier, TokenType::Literal, TokenType::RCurlyParen], ParseTreeType::Function, scanner.cursor())));
+	let message = brk!(literal_string(tokens).optional());
 
-	eprintln!("block end");
+	let body = brk!(tokens.node(CstKind::Block, block).require(SyntaxError::expected(vec![TokenType::Quote, TokenType::Block], ParseTreeType::StaticAssert, tokens.point())));
 
-	Valid(
-		nodes.into_iter().collect()
-	)
+	Valid((
+		format!("static_assert {cursor}"),
+		ParseTree::StaticAssert { message, body }
+	))
 }
 
-/// Returns an Identifier ASTNode
-fn identifier(scanner: &mut Scanner) -> ParseResult<ParseTree> {
-	eprintln!("identifier");
+/// An attribute preceding an item, which is either a cfg condition deciding whether the item is compiled, or an attribute recorded on
+/// the item
+enum ItemAttribute {
+	Cfg(CfgCondition),
+	Attribute(Attribute)
+}
 
-	let first = brk!(scanner.take_if(|c| {
-		UnicodeXID::is_xid_start(c) || c == '_'
-	}).into());
+/// Returns an attribute `@name`, or `@name(args)` for attributes taking arguments - `@cfg(condition)` and `@deprecated("message")`, whose
+/// message is optional
+fn attribute(tokens: &mut TokenStream) -> ParseResult<ItemAttribute> {
+	brk!(ParseResult::from(tokens.take_punct('@')));
 
-	let mut ident = scanner.take_until(|c| UnicodeXID::is_xid_continue(c));
+	let cursor = tokens.cursor();
+	let name = match brk!(adjacent(tokens, identifier).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Attribute, tokens.point()))) {
+		ParseTree::Identifier(name) => name,
+		_ => unreachable!()
+	};
 
-	ident.insert(0, first);
+	let attribute = match name.as_str() {
+		"cfg" => {
+			brk!(ParseResult::from(tokens.take_punct('(')).require(SyntaxError::expected(vec![TokenType::LRoundParen], ParseTreeType::Cfg, tokens.point())));
 
-	eprintln!("identifier end");
+			let condition = brk!(tokens.node(CstKind::CfgCondition, cfg_condition).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Cfg, tokens.point())));
 
-	Valid(ParseTree::Identifier(ident.into()))
-}
+			brk!(ParseResult::from(tokens.take_punct(')')).require(SyntaxError::expected(vec![TokenType::RRoundParen], ParseTreeType::Cfg, tokens.point())));
 
-/// Returns a Literal ASTNode
-fn literal(scanner: &mut Scanner) -> ParseResult<ParseTree> {
-	eprintln!("literal");
+			ItemAttribute::Cfg(condition)
+		}
+		"deprecated" => {
+			let message = brk!(tokens.try_take(|tokens| -> ParseResult<String> {
+				brk!(ParseResult::from(tokens.take_punct('(')));
 
-	let ret = brk!(scanner.take_choice(vec![
-		Box::new(literal_string),
-		Box::new(literal_integer),
-		Box::new(literal_float),
-		Box::new(literal_fnref)
-	]).map(|lit| ParseTree::Literal(lit)));
+				let message = brk!(literal_string(tokens).require(SyntaxError::expected(vec![TokenType::Quote], ParseTreeType::Attribute, tokens.point())));
 
-	eprintln!("literal end");
+				brk!(ParseResult::from(tokens.take_punct(')')).require(SyntaxError::expected(vec![TokenType::RRoundParen], ParseTreeType::Attribute, tokens.point())));
 
-	Valid(ret)
+				Valid(message)
+			}).optional());
+
+			ItemAttribute::Attribute(Attribute::Deprecated(message))
+		}
+		"inline" => ItemAttribute::Attribute(Attribute::Inline),
+		"noinline" => ItemAttribute::Attribute(Attribute::NoInline),
+		"cold" => ItemAttribute::Attribute(Attribute::Cold),
+		"must_use" => ItemAttribute::Attribute(Attribute::MustUse),
+		_ => return WithErr(SyntaxError::new(SyntaxErrorKind::UnknownAttribute { name }, ParseTreeType::Attribute, Span::point(tokens.file(), cursor)))
+	};
+
+	Valid(attribute)
 }
 
-/// Returns a Literal
-fn literal_string(scanner: &mut Scanner) -> ParseResult<Literal> {
-	eprintln!("literal_string");
+/// Records the attributes, each paired with its cursor, on the item, checking that each can be applied to it. Structs can only be
+/// deprecated, and externs and static assertions can't have any attributes
+fn attach_attributes(item: &mut ParseTreeNode, attributes: Vec<(Attribute, usize)>) -> ParseResult<()> {
+	let (item_attributes, is_function) = match &mut item.tree {
+		ParseTree::Function { attributes, .. } => (attributes, true),
+		ParseTree::Struct { attributes, .. } => (attributes, false),
+		_ => {
+			return match attributes.first() {
+				Some((attribute, cursor)) => WithErr(SyntaxError::new(SyntaxErrorKind::InvalidAttribute { name: attribute.name().to_string() }, ParseTreeType::Attribute, Span::point(item.span.file, *cursor))),
+				None => Valid(())
+			};
+		}
+	};
 
-	brk!(scanner.take('\"').into());
+	for (attribute, cursor) in attributes {
+		if !is_function && !matches!(attribute, Attribute::Deprecated(_)) {
+			return WithErr(SyntaxError::new(SyntaxErrorKind::InvalidAttribute { name: attribute.name().to_string() }, ParseTreeType::Attribute, Span::point(item.span.file, cursor)));
+		}
 
-	let (chars, err) = scanner.take_any::<char, SyntaxError>(|scanner| {
-		scanner.take_choice::<char, SyntaxError>(vec![
-			Box::new(|scanner| {
-				if !scanner.take('\\') {
-					return Unrecognised;
-				}
+		// A function can't be both inlined and not inlined
+		let conflicting = match attribute {
+			Attribute::Inline => Some(Attribute::NoInline),
+			Attribute::NoInline => Some(Attribute::Inline),
+			_ => None
+		};
+		if let Some(conflicting) = conflicting.filter(|conflicting| item_attributes.contains(conflicting)) {
+			return WithErr(SyntaxError::new(SyntaxErrorKind::ConflictingAttributes { first: conflicting.name().to_string(), second: attribute.name().to_string() }, ParseTreeType::Attribute, Span::point(item.span.file, cursor)));
+		}
 
-				scanner.take_choice::<char, SyntaxError>(vec![
-					Box::new(|scanner| {
-						ParseResult::from(scanner.take_of([
-							'\\',
-							'n',
-							't',
-							'r',
-							'0',
-							'"',
-						].iter())).map(|c| match c {
-							'\\' => '\\',
-							'n' => '\n',
-							't' => '\t',
-							'r' => '\r',
-							'0' => '\0',
-							'"' => '"',
-							_ => unreachable!()
-						})
-					}),
-					Box::new(|scanner| {
-						brk!(scanner.take('x').into());
-
-						let mut sb = String::new();
-
-						for _ in 0..2 {
-							sb.push(brk!(ParseResult::from(scanner.take_if(|c| {
-								c.is_ascii_hexdigit()
-							})).require(SyntaxError::expected(vec![TokenType::EscapeSequence], ParseTreeType::Literal, scanner.cursor()))))
-						}
-
-						let hex_val = u32::from_str_radix(&sb.trim(), 16).unwrap();
-
-						Valid(
-							unsafe { char::from_u32_unchecked(hex_val) } // FIXME: This may cause issues
-						)
-					})
-				]).require(SyntaxError::expected(vec![TokenType::EscapeSequence], ParseTreeType::Literal, scanner.cursor()))
-			}),
-			Box::new(|scanner| {
-				match scanner.pop() {
-					Some(c) => {
-						if c == '\"' {
-							Unrecognised
-						} else {
-							Valid(c)
-						}
-					}
-					None => {
-						WithErr(SyntaxError::expected(vec![TokenType::Quote], ParseTreeType::Literal, scanner.cursor()))
-					}
-				}
-			})
-		])
-	});
-	if let Some(e) = err {
-		return WithErr(e);
+		item_attributes.push_back(attribute);
 	}
 
-	let string = chars.into_iter().collect();
+	Valid(())
+}
 
-	scanner.advance(1);
+/// Returns a cfg condition, which is a flag `name`, a key-value pair `name = "value"`, or `all(...)`, `any(...)` or `not(...)` of other
+/// conditions
+fn cfg_condition(tokens: &mut TokenStream) -> ParseResult<CfgCondition> {
+	let name = match brk!(identifier(tokens)) {
+		ParseTree::Identifier(name) => name,
+		_ => unreachable!()
+	};
+
+	let is_combinator = matches!(name.as_str(), "all" | "any" | "not") && tokens.take_punct('(');
+
+	if is_combinator {
+		let mut conditions = im::Vector::new();
+
+		if let Some(first) = brk!(tokens.node(CstKind::CfgCondition, cfg_condition).optional()) {
+			conditions.push_back(first);
+
+			while tokens.take_punct(',') {
+				conditions.push_back(brk!(tokens.node(CstKind::CfgCondition, cfg_condition).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Cfg, tokens.point()))));
+			}
+		}
+
+		// not takes exactly one condition
+		if name == "not" && conditions.is_empty() {
+			return WithErr(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Cfg, tokens.point()));
+		} else if name == "not" && conditions.len() > 1 {
+			return WithErr(SyntaxError::expected(vec![TokenType::RRoundParen], ParseTreeType::Cfg, tokens.point()));
+		}
 
-	eprintln!("literal_string end @ {}", scanner.cursor());
+		brk!(ParseResult::from(tokens.take_punct(')')).require(SyntaxError::expected(vec![TokenType::Comma, TokenType::RRoundParen], ParseTreeType::Cfg, tokens.point())));
 
-	Valid(Literal::String(string))
+		return Valid(match name.as_str() {
+			"all" => CfgCondition::All(conditions),
+			"any" => CfgCondition::Any(conditions),
+			_ => CfgCondition::Not(Box::new(conditions.pop_front().unwrap()))
+		});
+	}
+
+	let value = brk!(tokens.try_take(|tokens| -> ParseResult<String> {
+		brk!(ParseResult::from(tokens.take_punct('=')));
+
+		literal_string(tokens).require(SyntaxError::expected(vec![TokenType::Quote], ParseTreeType::Cfg, tokens.point()))
+	}).optional());
+
+	match value {
+		Some(value) => Valid(CfgCondition::KeyValue(name, value)),
+		None => Valid(CfgCondition::Flag(name))
+	}
 }
 
-/// Returns a Literal
-fn literal_integer(scanner: &mut Scanner) -> ParseResult<Literal> { // TODO: To allow better type inference, we could perhaps only store the literal strings for now, and then turn the strings into integers once we've decided the types
-	// let i = 1000000000000000000; // Rust seems to default to i32 unless explicitly told otherwise for literals
-	let negative = scanner.take('-');
+/// Returns the popped and pushed type names of a stack effect declaration `(type, ... -> type, ...)`, each listed from the bottom of the
+/// stack to the top
+fn stack_effect(tokens: &mut TokenStream) -> ParseResult<(im::Vector<Spanned<String>>, im::Vector<Spanned<String>>)> {
+	brk!(tokens.take_punct('(').into());
 
-	let start_of_int = scanner.cursor();
+	let popped = brk!(type_list(tokens));
 
-	let (num, denary) = brk!(scanner.take_choice::<(u128, bool), SyntaxError>(vec![
-		Box::new(move |scanner| { // Parse binary literal
-			literal_integer_radix(scanner, 2).map(|n| (n, false))
-		}),
-		Box::new(move |scanner| { // Parse octal literal
-			literal_integer_radix(scanner, 8).map(|n| (n, false))
-		}),
-		Box::new(move |scanner| { // Parse hex literal
-			literal_integer_radix(scanner, 16).map(|n| (n, false))
-		}),
-		Box::new(move |scanner| { // Parse denary literal
-			literal_integer_radix(scanner, 10).map(|n| (n, true))
-		})
-	]));
+	brk!(arrow(tokens).require(SyntaxError::expected(vec![TokenType::Comma, TokenType::StackEffectArrow], ParseTreeType::Type, tokens.point())));
 
-	let start_of_suffix = scanner.cursor();
+	let pushed = brk!(type_list(tokens));
 
-	let suffix = brk!(scanner.try_take(|scanner| {
-			let signed = brk!(scanner.take_of(['u', 'i'].iter()).into()) == 'i';
+	brk!(ParseResult::from(tokens.take_punct(')')).require(SyntaxError::expected(vec![TokenType::Comma, TokenType::RRoundParen], ParseTreeType::Type, tokens.point())));
 
-			let bits = brk!(literal_integer_radix(scanner, 10).optional());
+	Valid((popped, pushed))
+}
 
-			Valid((signed, bits))
-		}
-	).optional());
+/// Returns the names in a possibly empty comma-separated list of type names, each with its span
+fn type_list(tokens: &mut TokenStream) -> ParseResult<im::Vector<Spanned<String>>> {
+	let mut types = im::Vector::new();
 
-	let num_type = match suffix {
-		Some((signed, Some(bits))) => {
-			if !signed && negative {
-				return WithErr(SyntaxError::new(SyntaxErrorKind::NegativeUnsignedLiteral, ParseTreeType::Literal, start_of_suffix));
-			}
+	let cursor = tokens.cursor();
+	if let Some(first) = brk!(tokens.node(CstKind::Type, type_name).optional()) {
+		types.push_back(Spanned::new(first, tokens.span_from(cursor)));
 
-			match bits {
-				8 => { if signed { TowerType::I8 } else { TowerType::U8 } },
-				16 => { if signed { TowerType::I16 } else { TowerType::U16 } },
-				32 => { if signed { TowerType::I32 } else { TowerType::U32 } },
-				64 => { if signed { TowerType::I64 } else { TowerType::U64 } },
-				128 => { if signed { TowerType::I128 } else { TowerType::U128 } },
-				_ => return WithErr(SyntaxError::new(SyntaxErrorKind::InvalidIntegerSize, ParseTreeType::Literal, start_of_suffix + 1))
-			}
+		while tokens.take_punct(',') {
+			let cursor = tokens.cursor();
+			let tname = brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Type, tokens.point())));
+
+			types.push_back(Spanned::new(tname, tokens.span_from(cursor)));
 		}
-		Some((signed, None)) => {
-			if !signed && negative {
-				return WithErr(SyntaxError::new(SyntaxErrorKind::NegativeUnsignedLiteral, ParseTreeType::Literal, start_of_suffix));
-			}
+	}
+
+	Valid(types)
+}
 
-			if signed {
-				TowerType::I32
-			} else {
-				TowerType::U32
+/// Returns a Block ASTNode
+fn block(tokens: &mut TokenStream) -> ParseResult<im::Vector<ParseTreeNode>> {
+	eprintln!("block");
+
+	brk!(tokens.take_punct('{').into());
+
+	let mut first = true;
+	// The element that failed to parse, if any, which is left as a placeholder covering it and the rest of the block
+	let mut failed = None;
+	let (mut nodes, err) = tokens.take_any(|tokens| -> ParseResult<ParseTreeNode> {
+		let cursor = tokens.cursor();
+		let separated = first || !tokens.is_adjacent();
+		let marker = tokens.start();
+
+		let ret = match tokens.take_choice(vec![
+			Box::new(literal),
+			Box::new(word),
+			Box::new(constructor_struct),
+			Box::new(field_access),
+			// Tokens that failed to lex are left as placeholders, their errors having already been reported
+			Box::new(|tokens| tokens.take_map(|kind| (kind == &TokenKind::Invalid).then_some(ParseTree::Invalid)).into())
+		]) {
+			Valid(ret) => ret,
+			WithErr(e) => {
+				failed = Some((marker, cursor));
+				return WithErr(e);
 			}
+			Unrecognised => return Unrecognised
+		};
+
+		// Items are separated by whitespace, so that e.g. `"this"isvalid` isn't a string followed by a word. Field accesses directly
+		// follow the struct they access
+		if !separated && !matches!(ret, ParseTree::FieldAccess(_)) {
+			failed = Some((marker, cursor));
+			return WithErr(SyntaxError::expected(vec![TokenType::Whitespace], ParseTreeType::Function, Span::point(tokens.file(), cursor)));
 		}
-		None => {
-			if !denary {
-				TowerType::U32
-			} else {
-				TowerType::I32
-			}
+
+		first = false;
+
+		tokens.complete(marker, element_kind(&ret));
+
+		Valid(ret.wrap(tokens.span_from(cursor)))
+	});
+	if let Some(e) = err {
+		let (marker, cursor) = failed.expect("Block element failed without being recorded");
+
+		tokens.report(e);
+
+		// The rest of the block is skipped, stopping at the start of the next item in case the block is never closed
+		while tokens.peek().is_some_and(|kind| kind != &TokenKind::Punct('}') && !is_item_start(kind)) {
+			tokens.pop();
 		}
-	};
 
-	let literal = match num_type {
-		TowerType::U128 => Literal::U128(num),
-		TowerType::U64 => Literal::U64(brk!(downcast_uint(num, TowerType::U64, start_of_int))),
-		TowerType::U32 => Literal::U32(brk!(downcast_uint(num, TowerType::U32, start_of_int))),
-		TowerType::U16 => Literal::U16(brk!(downcast_uint(num, TowerType::U16, start_of_int))),
-		TowerType::U8 => Literal::U8(brk!(downcast_uint(num, TowerType::U8, start_of_int))),
-		TowerType::I128 => Literal::I128(num as i128),
-		TowerType::I64 => Literal::I64(brk!(downcast_uint::<u64, _>(num, TowerType::U64, start_of_int)) as i64),
-		TowerType::I32 => Literal::I32(brk!(downcast_uint::<u32, _>(num, TowerType::U32, start_of_int)) as i32),
-		TowerType::I16 => Literal::I16(brk!(downcast_uint::<u16, _>(num, TowerType::U16, start_of_int)) as i16),
-		TowerType::I8 => Literal::I8(brk!(downcast_uint::<u8, _>(num, TowerType::U8, start_of_int)) as i8),
+		tokens.complete(marker, CstKind::Error);
+
+		nodes.push(ParseTree::Invalid.wrap(tokens.span_from(cursor)));
+	}
+
+	brk!(ParseResult::from(tokens.take_punct('}')).require(SyntaxError::expected(vec![TokenType::Identifier, TokenType::Literal, TokenType::RCurlyParen], ParseTreeType::Function, tokens.point())));
+
+	eprintln!("block end");
+
+	Valid(
+		nodes.into_iter().collect()
+	)
+}
+
+/// Returns the kind of CST node a block element is parsed into
+fn element_kind(tree: &ParseTree) -> CstKind {
+	match tree {
+		ParseTree::Identifier(_) => CstKind::Word,
+		ParseTree::Literal(_) => CstKind::Literal,
+		ParseTree::Constructor(_) => CstKind::Constructor,
+		ParseTree::FieldAccess(_) => CstKind::FieldAccess,
+		ParseTree::Invalid => CstKind::Error,
 		_ => unreachable!()
-	};
+	}
+}
 
-	Valid(literal)
+/// Returns an Identifier ASTNode
+fn identifier(tokens: &mut TokenStream) -> ParseResult<ParseTree> {
+	eprintln!("identifier");
+
+	let ident = brk!(tokens.take_identifier().into());
+
+	eprintln!("identifier end");
+
+	Valid(ParseTree::Identifier(ident))
+}
+
+/// Returns an Identifier ASTNode naming a word, which is either an identifier or a symbolic identifier
+fn word(tokens: &mut TokenStream) -> ParseResult<ParseTree> {
+	tokens.take_choice(vec![
+		Box::new(identifier),
+		Box::new(symbolic_identifier)
+	])
 }
 
-fn downcast_uint<T, F>(num: F, num_type: TowerType, cursor: usize) -> ParseResult<T> where T: TryFrom<F>, F: ToString + Copy {
-	match num.try_into().ok() {
-		Some(n) => Valid(n),
-		None => {
-			WithErr(SyntaxError::new(SyntaxErrorKind::LiteralIntegerOverflow { num: num.to_string(), target_type: num_type }, ParseTreeType::Literal, cursor))
+/// The characters symbolic identifiers are made up of
+const SYMBOL_CHARS: [char; 14] = ['+', '-', '*', '/', '%', '<', '>', '=', '!', '&', '|', '^', '~', '?'];
+
+/// Returns an Identifier ASTNode made up of adjacent symbol characters, e.g. `+` or `<=>`. To tell them apart from other syntax, `->` is
+/// reserved for constructors, and a `&` followed by a word is a function reference, so `&&` is a reference to the word `&`. A `-`
+/// followed by a digit is lexed as a negative number literal instead. `.` isn't a symbol character, as it starts field accesses
+fn symbolic_identifier(tokens: &mut TokenStream) -> ParseResult<ParseTree> {
+	let mut ident = String::new();
+
+	while ident.is_empty() || tokens.is_adjacent() {
+		match tokens.take_map(|kind| match kind {
+			&TokenKind::Punct(c) if SYMBOL_CHARS.contains(&c) => Some(c),
+			_ => None
+		}) {
+			Some(c) => ident.push(c),
+			None => break
 		}
 	}
+
+	let before_word = tokens.is_adjacent() && matches!(tokens.peek(), Some(TokenKind::Identifier(_)));
+
+	if ident.is_empty() || ident == "->" {
+		return Unrecognised;
+	}
+
+	if ident.starts_with('&') && (ident.len() > 1 || before_word) {
+		return Unrecognised;
+	}
+
+	Valid(ParseTree::Identifier(ident))
 }
 
-/// Parses an integer (including prefix, not including suffix) using radix (radix ∈ [2, 8, 10, 16])
-fn literal_integer_radix(scanner: &mut Scanner, radix: u8) -> ParseResult<u128> {
-	let start_of_int = scanner.cursor();
+/// Returns the name of a type, which is either an identifier optionally with type parameters `ident<type, ...>`, a reference `&type` or an array `[type; len]`.
+/// The name is normalised, so that e.g. `[ u32 ;4]` is returned as `[u32; 4]`
+fn type_name(tokens: &mut TokenStream) -> ParseResult<String> {
+	if tokens.take_punct('&') {
+		// `&mutex` is lexed as a single identifier, so is still a reference to the type `mutex`
+		let mutable = tokens.take_keyword("mut");
 
-	let is_radix_digit = match radix {
-		2 => |c: char| { c == '0' || c == '1' },
-		8 => |c: char| { ['0', '1', '2', '3', '4', '5', '6', '7'].iter().find(|&&oc| oc == c).is_some() },
-		10 => |c: char| { c.is_ascii_digit() },
-		16 => |c: char| { c.is_ascii_hexdigit() },
-		_ => unimplemented!("Illegal radix value: {radix}")
-	};
+		let inner = brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Type, tokens.point())));
+
+		return Valid(if mutable { format!("&mut {inner}") } else { format!("&{inner}") });
+	}
+
+	if tokens.take_punct('[') {
+		let elem = brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Type, tokens.point())));
+
+		brk!(ParseResult::from(tokens.take_punct(';')).require(SyntaxError::expected(vec![TokenType::Semicolon], ParseTreeType::Type, tokens.point())));
+
+		// The length is an unsuffixed denary integer, which is lexed as an i32
+		let len = brk!(ParseResult::from(tokens.take_map(|kind| match kind {
+			&TokenKind::Literal(Literal::I32(len)) if len >= 0 => Some(len),
+			_ => None
+		})).require(SyntaxError::expected(vec![TokenType::Number], ParseTreeType::Type, tokens.point())));
+
+		brk!(ParseResult::from(tokens.take_punct(']')).require(SyntaxError::expected(vec![TokenType::RSquareParen], ParseTreeType::Type, tokens.point())));
+
+		return Valid(format!("[{elem}; {len}]"));
+	}
 
-	let prefix = match radix {
-		2 => "0b",
-		8 => "0o",
-		10 => "",
-		16 => "0x",
+	let name = match brk!(identifier(tokens)) {
+		ParseTree::Identifier(s) => s,
 		_ => unreachable!()
 	};
 
-	let prefix_required = !prefix.is_empty();
+	// Type parameters directly follow the type name, so that e.g. `-> Foo <` is a constructor followed by the word `<`
+	if tokens.is_adjacent() && tokens.take_punct('<') {
+		let mut params = vec![brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Type, tokens.point())))];
 
-	brk!(scanner.take_str(prefix).into());
+		while tokens.take_punct(',') {
+			params.push(brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Type, tokens.point()))));
+		}
 
-	let result = ParseResult::from(scanner.take_some::<char, SyntaxError>(|scanner| {
-		scanner.take_if(is_radix_digit).into()
-	}));
-	let (chars, err) = match prefix_required {
-		true => brk!(result.require(SyntaxError::expected(vec![TokenType::Number], ParseTreeType::Literal, scanner.cursor()))),
-		false => brk!(result)
-	};
-	if let Some(e) = err {
-		return WithErr(e);
+		brk!(ParseResult::from(tokens.take_punct('>')).require(SyntaxError::expected(vec![TokenType::Comma, TokenType::RAngleParen], ParseTreeType::Type, tokens.point())));
+
+		return Valid(format!("{name}<{}>", params.join(", ")));
 	}
 
-	let num_str: String = chars.into_iter().collect();
+	Valid(name)
+}
 
-	let num = match u128::from_str_radix(&num_str, radix as u32) {
-		Ok(v) => v,
-		Err(e) => {
-			match e.kind() {
-				IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
-					return WithErr(SyntaxError::new(SyntaxErrorKind::LiteralIntegerOverflow { num: format!("{prefix}{num_str}"), target_type: TowerType::U128 }, ParseTreeType::Literal, start_of_int))
-				}
-				_ => unreachable!()
-			}
-		}
-	};
+/// Returns a Literal ASTNode
+fn literal(tokens: &mut TokenStream) -> ParseResult<ParseTree> {
+	eprintln!("literal");
 
-	Valid(num)
+	let ret = brk!(literal_value(tokens).map(ParseTree::Literal));
+
+	eprintln!("literal end");
+
+	Valid(ret)
 }
 
 /// Returns a Literal
-fn literal_float(scanner: &mut Scanner) -> ParseResult<Literal> { // TODO
-	Unrecognised
+fn literal_value(tokens: &mut TokenStream) -> ParseResult<Literal> {
+	tokens.take_choice(vec![
+		Box::new(|tokens| tokens.take_literal().into()),
+		Box::new(literal_fnref),
+		Box::new(literal_array)
+	])
 }
 
-fn literal_fnref(scanner: &mut Scanner) -> ParseResult<Literal> {
-	brk!(scanner.take('&').into());
+/// Returns a Literal
+fn literal_array(tokens: &mut TokenStream) -> ParseResult<Literal> {
+	brk!(tokens.take_punct('[').into());
 
-	let ident = match brk!(identifier(scanner).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Constructor, scanner.cursor()))) {
+	let mut first = true;
+	let (elems, err) = tokens.take_any(|tokens| {
+		let cursor = tokens.cursor();
+		let separated = first || !tokens.is_adjacent();
+
+		let elem = brk!(tokens.node(CstKind::Literal, literal_value));
+
+		if !separated {
+			return WithErr(SyntaxError::expected(vec![TokenType::Whitespace], ParseTreeType::Literal, Span::point(tokens.file(), cursor)));
+		}
+
+		first = false;
+
+		Valid(elem)
+	});
+	if let Some(e) = err {
+		return WithErr(e);
+	}
+
+	brk!(ParseResult::from(tokens.take_punct(']')).require(SyntaxError::expected(vec![TokenType::Literal, TokenType::RSquareParen], ParseTreeType::Literal, tokens.point())));
+
+	Valid(Literal::Array(elems))
+}
+
+/// Returns the contents of a string literal, for syntax that takes a string such as extern library names
+fn literal_string(tokens: &mut TokenStream) -> ParseResult<String> {
+	tokens.take_map(|kind| match kind {
+		TokenKind::Literal(Literal::String(s)) => Some(s.clone()),
+		_ => None
+	}).into()
+}
+
+fn literal_fnref(tokens: &mut TokenStream) -> ParseResult<Literal> {
+	brk!(tokens.take_punct('&').into());
+
+	// Without a word directly after it, & is a word itself
+	let ident = match brk!(adjacent(tokens, word)) {
 		ParseTree::Identifier(s) => s,
 		_ => unreachable!()
 	};
@@ -482,23 +807,20 @@ fn literal_fnref(scanner: &mut Scanner) -> ParseResult<Literal> {
 	Valid(Literal::FnPtr(ident))
 }
 
-fn constructor_struct(scanner: &mut Scanner) -> ParseResult<ParseTree> {
-	brk!(scanner.take_str("->").into());
+fn constructor_struct(tokens: &mut TokenStream) -> ParseResult<ParseTree> {
+	brk!(arrow(tokens));
 
-	brk!(ParseResult::from(scanner.take_some(s)).require(SyntaxError::expected(vec![TokenType::Whitespace], ParseTreeType::Constructor, scanner.cursor())));
+	brk!(separator(tokens, ParseTreeType::Constructor));
 
-	let ident = match brk!(identifier(scanner).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::Constructor, scanner.cursor()))) {
-		ParseTree::Identifier(s) => s,
-		_ => unreachable!()
-	};
+	let tname = brk!(tokens.node(CstKind::Type, type_name).require(SyntaxError::expected(vec![TokenType::Type], ParseTreeType::Constructor, tokens.point())));
 
-	Valid(ParseTree::Constructor(ident))
+	Valid(ParseTree::Constructor(tname))
 }
 
-fn field_access(scanner: &mut Scanner) -> ParseResult<ParseTree> {
-	brk!(scanner.take('.').into());
+fn field_access(tokens: &mut TokenStream) -> ParseResult<ParseTree> {
+	brk!(tokens.take_punct('.').into());
 
-	let ident = match brk!(identifier(scanner).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::FieldAccess, scanner.cursor()))) {
+	let ident = match brk!(adjacent(tokens, identifier).require(SyntaxError::expected(vec![TokenType::Identifier], ParseTreeType::FieldAccess, tokens.point()))) {
 		ParseTree::Identifier(s) => s,
 		_ => unreachable!()
 	};
@@ -506,6 +828,166 @@ fn field_access(scanner: &mut Scanner) -> ParseResult<ParseTree> {
 	Valid(ParseTree::FieldAccess(ident))
 }
 
-fn s(scanner: &mut Scanner) -> ParseResult<()> {
-	scanner.take_if(|c| c.is_whitespace()).map(|_| ()).into()
-}
\ No newline at end of file
+/// Takes an arrow `->`, which is lexed as two adjacent Punct tokens
+fn arrow(tokens: &mut TokenStream) -> ParseResult<()> {
+	tokens.try_take(|tokens| {
+		brk!(tokens.take_punct('-').into());
+
+		ParseResult::from(tokens.is_adjacent() && tokens.take_punct('>'))
+	})
+}
+
+/// Parses with f only if the next token directly follows the last token taken, e.g. for the name after the `@` of an attribute
+fn adjacent<T>(tokens: &mut TokenStream, f: impl FnOnce(&mut TokenStream) -> ParseResult<T>) -> ParseResult<T> {
+	if tokens.is_adjacent() {
+		f(tokens)
+	} else {
+		Unrecognised
+	}
+}
+
+/// Requires whitespace or a comment before the next token, e.g. between `fn` and a symbolic function name
+fn separator(tokens: &mut TokenStream, while_parsing: ParseTreeType) -> ParseResult<()> {
+	if tokens.is_adjacent() {
+		WithErr(SyntaxError::expected(vec![TokenType::Whitespace], while_parsing, tokens.point()))
+	} else {
+		Valid(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, source_map::SourceMap, tree::{Attribute, Literal, ParseTree, ParseTreeNode, Span}};
+
+	/// Parses content, returning the module's items and the message of each syntax error
+	fn parse_items(content: &str) -> (im::OrdMap<String, ParseTreeNode>, Vec<String>) {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tree, errors) = parse(&sources, file);
+
+		match tree.tree {
+			ParseTree::Module { name: _, elems } => (elems, errors.iter().map(|e| e.to_string()).collect()),
+			_ => panic!("Expected module")
+		}
+	}
+
+	/// Returns the body of the function item
+	fn body(item: &ParseTreeNode) -> Vec<ParseTree> {
+		match &item.tree {
+			ParseTree::Function { body, .. } => body.iter().map(|elem| elem.tree.clone()).collect(),
+			_ => panic!("Expected function")
+		}
+	}
+
+	/// Returns the attributes of the function or struct item
+	fn attributes(item: &ParseTreeNode) -> Vec<Attribute> {
+		match &item.tree {
+			ParseTree::Function { attributes, .. } | ParseTree::Struct { attributes, .. } => attributes.iter().cloned().collect(),
+			_ => panic!("Expected function or struct")
+		}
+	}
+
+	#[test]
+	fn attributes_are_recorded() {
+		let (items, errors) = parse_items("@inline @must_use fn a { 1u }\n@cold\n@deprecated(\"old\") fn b { }\n@deprecated struct P { x: u32 }\n");
+
+		assert!(errors.is_empty());
+		assert_eq!(attributes(&items["a"]), vec![Attribute::Inline, Attribute::MustUse]);
+		assert_eq!(attributes(&items["b"]), vec![Attribute::Cold, Attribute::Deprecated(Some("old".to_string()))]);
+		assert_eq!(attributes(&items["P"]), vec![Attribute::Deprecated(None)]);
+	}
+
+	#[test]
+	fn invalid_attributes_error() {
+		let (_, errors) = parse_items("@fast fn a { }\n@inline struct P { x: u32 }\n@inline @noinline fn b { }\n");
+
+		assert_eq!(errors.len(), 3);
+		assert!(errors[0].ends_with("unknown attribute fast"));
+		assert!(errors[1].ends_with("attribute inline can't be applied to this item"));
+		assert!(errors[2].ends_with("attribute noinline conflicts with attribute inline"));
+	}
+
+	#[test]
+	fn symbolic_names() {
+		let (items, errors) = parse_items("fn + { __add_u32 }\nfn <=> { }\nfn & { }\nstruct P { x: i32 }\nfn main { 1u 2u + -1i <=> & &+ && &<=> 3i -> P }\n");
+
+		assert!(errors.is_empty());
+		assert!(items.contains_key("+") && items.contains_key("<=>") && items.contains_key("&"));
+		assert_eq!(body(&items["main"]), vec![
+			ParseTree::Literal(Literal::U32(1)),
+			ParseTree::Literal(Literal::U32(2)),
+			ParseTree::Identifier("+".to_string()),
+			ParseTree::Literal(Literal::I32(-1)),
+			ParseTree::Identifier("<=>".to_string()),
+			ParseTree::Identifier("&".to_string()),
+			ParseTree::Literal(Literal::FnPtr("+".to_string())),
+			ParseTree::Literal(Literal::FnPtr("&".to_string())),
+			ParseTree::Literal(Literal::FnPtr("<=>".to_string())),
+			ParseTree::Literal(Literal::I32(3)),
+			ParseTree::Constructor("P".to_string())
+		]);
+	}
+
+	#[test]
+	fn invalid_symbolic_names() {
+		// A symbolic name has to be separated from `fn`, like any other name
+		let (_, errors) = parse_items("fn+ { }\n");
+		assert_eq!(errors.len(), 1);
+
+		// `->` is reserved for constructors, and `&` followed by more symbol characters is a function reference
+		let (_, errors) = parse_items("fn -> { }\nfn &| { }\n");
+		assert_eq!(errors.len(), 2);
+	}
+
+	#[test]
+	fn recovers_from_errors() {
+		let (items, errors) = parse_items("fn a { ( }\nfn b { 1u }\nstruct { x: u32 }\nfn c { 2u ) 3u }\nfn main { b }\n");
+
+		assert_eq!(errors, vec![
+			"while parsing Function, expected [Identifier, Literal, RCurlyParen]",
+			"while parsing Struct, expected [Identifier]",
+			"while parsing Function, expected [Identifier, Literal, RCurlyParen]"
+		]);
+		assert_eq!(body(&items["b"]), vec![ParseTree::Literal(Literal::U32(1))]);
+		// Items that failed to parse are kept as placeholders, under their name if it was parsed
+		assert_eq!(items.keys().collect::<Vec<_>>(), vec!["a", "b", "c", "invalid 23", "main"]);
+		assert!(["a", "c", "invalid 23"].iter().all(|name| items[*name].tree == ParseTree::Invalid));
+	}
+
+	#[test]
+	fn nodes_span_their_text() {
+		let content = "fn main { 12u hello -> P .x }\nstruct P { x: u32\n\ty: [u8; 2] }\nextern \"libc.so.6\" fn abs (i32 -> i32)\n";
+		let (items, errors) = parse_items(content);
+		assert!(errors.is_empty());
+
+		let chars: Vec<char> = content.chars().collect();
+		let text = |span: Span| chars[span.start..span.end].iter().collect::<String>();
+
+		assert_eq!(text(items["main"].span), "fn main { 12u hello -> P .x }");
+		let ParseTree::Function { body, .. } = &items["main"].tree else { panic!("Expected function") };
+		assert_eq!(body.iter().map(|elem| text(elem.span)).collect::<Vec<_>>(), vec!["12u", "hello", "-> P", ".x"]);
+
+		let ParseTree::Struct { fields, .. } = &items["P"].tree else { panic!("Expected struct") };
+		assert_eq!(text(fields["x"].span), "x: u32");
+		assert_eq!(text(fields["y"].span), "y: [u8; 2]");
+
+		let ParseTree::Extern { params, returns, .. } = &items["abs"].tree else { panic!("Expected extern") };
+		assert_eq!((text(params[0].span), text(returns[0].span)), ("i32".to_string(), "i32".to_string()));
+	}
+
+	#[test]
+	fn doc_comments_attach_to_items() {
+		let (items, errors) = parse_items("## Adds one\n## to the top\n@inline fn a { 1u __add_u32 }\n\n# not a doc\nfn b { }\n## A point\nstruct P {\n\t## Across\n\tx: u32\n\ty: u32\n}\n");
+		assert!(errors.is_empty());
+
+		let ParseTree::Function { doc, .. } = &items["a"].tree else { panic!("Expected function") };
+		assert_eq!(doc.as_deref(), Some("Adds one\nto the top"));
+		let ParseTree::Function { doc, .. } = &items["b"].tree else { panic!("Expected function") };
+		assert_eq!(doc, &None);
+
+		let ParseTree::Struct { doc, field_docs, .. } = &items["P"].tree else { panic!("Expected struct") };
+		assert_eq!(doc.as_deref(), Some("A point"));
+		assert_eq!(field_docs.get("x").map(String::as_str), Some("Across"));
+		assert_eq!(field_docs.get("y"), None);
+	}
+}
@@ -0,0 +1,176 @@
+use super::{error::{SyntaxError, SyntaxErrorKind}, result::ScanResult::{self, Valid, WithErr}, tree::{CfgCondition, ParseTree, ParseTreeNode, ParseTreeType}};
+
+/// The backend that a program is being prepared for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+	Interpreter,
+	Compiled
+}
+
+impl Backend {
+	/// Returns the value of the `backend` key for this backend
+	pub fn name(&self) -> &'static str {
+		match self {
+			Backend::Interpreter => "interpreter",
+			Backend::Compiled => "compiled"
+		}
+	}
+}
+
+/// The flags and key-value pairs that cfg conditions are checked against
+#[derive(Debug, Clone)]
+pub struct CfgOptions {
+	flags: im::HashSet<String>,
+	values: im::HashSet<(String, String)>
+}
+
+impl CfgOptions {
+	/// Returns the options for the backend running on the host target, setting the `backend`, `target_os` and `target_arch` keys
+	pub fn new(backend: Backend) -> Self {
+		let mut options = CfgOptions { flags: im::HashSet::new(), values: im::HashSet::new() };
+
+		options.set("backend", backend.name());
+		options.set("target_os", std::env::consts::OS);
+		options.set("target_arch", std::env::consts::ARCH);
+
+		options
+	}
+
+	pub fn set_flag(&mut self, flag: impl Into<String>) {
+		self.flags.insert(flag.into());
+	}
+
+	/// Sets the key to the value. A key can be set to multiple values, and a condition on it holds if any of them match
+	pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+		self.values.insert((key.into(), value.into()));
+	}
+
+	/// Sets a flag or key-value pair given on the command line with `--cfg`, as either `flag` or `key=value`. The value may be quoted
+	pub fn set_arg(&mut self, arg: &str) {
+		match arg.split_once('=') {
+			Some((key, value)) => {
+				let value = value.trim();
+				let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+
+				self.set(key.trim(), value);
+			}
+			None => self.set_flag(arg.trim())
+		}
+	}
+
+	pub fn holds(&self, condition: &CfgCondition) -> bool {
+		match condition {
+			CfgCondition::Flag(flag) => self.flags.contains(flag),
+			CfgCondition::KeyValue(key, value) => self.values.contains(&(key.clone(), value.clone())),
+			CfgCondition::All(conditions) => conditions.iter().all(|c| self.holds(c)),
+			CfgCondition::Any(conditions) => conditions.iter().any(|c| self.holds(c)),
+			CfgCondition::Not(condition) => !self.holds(condition)
+		}
+	}
+}
+
+/// Returns the module tree with the items whose cfg conditions don't hold removed, and the rest stored under their own names, so that
+/// disabled items are never analysed. Fails if more than one item with the same name is enabled
+pub fn apply(tree: &ParseTreeNode, options: &CfgOptions) -> ScanResult<ParseTreeNode, SyntaxError> {
+	let ParseTree::Module { name, elems } = &tree.tree else {
+		return Valid(tree.clone());
+	};
+
+	let mut enabled = im::OrdMap::new();
+
+	for (elem_name, elem) in elems {
+		let (elem_name, elem) = match &elem.tree {
+			ParseTree::Cfg { condition, name, item } => {
+				if !options.holds(condition) {
+					continue;
+				}
+
+				(name, item.as_ref())
+			}
+			_ => (elem_name, elem)
+		};
+
+		if enabled.insert(elem_name.clone(), elem.clone()).is_some() {
+			return WithErr(SyntaxError::new(SyntaxErrorKind::DuplicateItem { name: elem_name.clone() }, ParseTreeType::Module, elem.span));
+		}
+	}
+
+	Valid(ParseTree::Module { name: name.clone(), elems: enabled }.wrap(tree.span))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::parser::{self, result::ScanResult::{Valid, WithErr}, source_map::SourceMap, tree::{CfgCondition, ParseTree}};
+
+	use super::{apply, Backend, CfgOptions};
+
+	/// Parses content and applies the options to it, returning the names of the enabled items and the body of the one named f, or the
+	/// message of the error it fails with
+	fn enabled(content: &str, options: &CfgOptions) -> Result<(Vec<String>, String), String> {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tree, errors) = parser::parse(&sources, file);
+		assert!(errors.is_empty(), "Expected test content to parse");
+
+		match apply(&tree, options) {
+			Valid(tree) => {
+				let ParseTree::Module { name: _, elems } = tree.tree else { panic!("Expected module") };
+				let body = match elems.get("f").map(|f| &f.tree) {
+					Some(ParseTree::Function { body, .. }) => format!("{:?}", body.iter().map(|elem| &elem.tree).collect::<Vec<_>>()),
+					_ => String::new()
+				};
+
+				Ok((elems.keys().cloned().collect(), body))
+			}
+			WithErr(e) => Err(e.to_string()),
+			_ => panic!("Expected module to be recognised")
+		}
+	}
+
+	#[test]
+	fn conditions_hold() {
+		let mut options = CfgOptions::new(Backend::Interpreter);
+		options.set_arg("debug");
+		options.set_arg("level = \"2\"");
+
+		let flag = |name: &str| CfgCondition::Flag(name.to_string());
+		let key_value = |key: &str, value: &str| CfgCondition::KeyValue(key.to_string(), value.to_string());
+
+		assert!(options.holds(&flag("debug")));
+		assert!(!options.holds(&flag("release")));
+		assert!(options.holds(&key_value("level", "2")));
+		assert!(options.holds(&key_value("backend", "interpreter")));
+		assert!(options.holds(&key_value("target_os", std::env::consts::OS)));
+		assert!(options.holds(&CfgCondition::All(im::vector![flag("debug"), CfgCondition::Not(Box::new(flag("release")))])));
+		assert!(!options.holds(&CfgCondition::All(im::vector![flag("debug"), flag("release")])));
+		assert!(options.holds(&CfgCondition::Any(im::vector![flag("release"), key_value("backend", "interpreter")])));
+		assert!(!options.holds(&CfgCondition::Any(im::vector![])));
+	}
+
+	#[test]
+	fn items_are_chosen_by_backend() {
+		let content = "@cfg(backend = \"interpreter\") fn f { 1u }\n@cfg(not(backend = \"interpreter\")) fn f { 2u }\n@cfg(debug) fn g { }\nfn main { f }\n";
+
+		let (names, body) = enabled(content, &CfgOptions::new(Backend::Interpreter)).expect("Expected one f to be enabled");
+		assert_eq!(names, vec!["f", "main"]);
+		assert!(body.contains("U32(1)"));
+
+		let mut options = CfgOptions::new(Backend::Compiled);
+		options.set_flag("debug");
+		let (names, body) = enabled(content, &options).expect("Expected one f to be enabled");
+		assert_eq!(names, vec!["f", "g", "main"]);
+		assert!(body.contains("U32(2)"));
+	}
+
+	#[test]
+	fn duplicate_enabled_items_error() {
+		let content = "@cfg(any(a, b)) fn f { }\n@cfg(b) fn f { }\n";
+		let mut options = CfgOptions::new(Backend::Interpreter);
+
+		options.set_flag("a");
+		assert!(enabled(content, &options).is_ok());
+
+		options.set_flag("b");
+		assert_eq!(enabled(content, &options), Err("while parsing Module, item f is defined more than once for the current configuration".to_string()));
+	}
+}
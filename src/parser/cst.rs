@@ -0,0 +1,491 @@
+use std::path::Path;
+
+use super::{item_name, lexer::{Token, TokenKind}, source_map::{FileId, SourceMap}, tokens::TokenStream, tree::{Attribute, CfgCondition, Literal, ParseTree, ParseTreeNode, Span, Spanned}, ItemAttribute};
+
+/// The kind of a node in the concrete syntax tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CstKind {
+	Module,
+	Function,
+	Struct,
+	Extern,
+	StaticAssert,
+	/// An attribute before an item, e.g. `@inline` or `@cfg(debug)`
+	Attribute,
+	CfgCondition,
+	/// The name of an item or a struct field
+	Name,
+	/// A struct field `name: type`
+	Field,
+	/// The `(type, ... -> type, ...)` declaration of an extern function
+	StackEffect,
+	Type,
+	Block,
+	Word,
+	Literal,
+	Constructor,
+	FieldAccess,
+	/// An item or block element that failed to parse, along with the tokens skipped while recovering, which stands for a
+	/// ParseTree::Invalid placeholder
+	Error,
+	/// Tokens between items which don't start one, skipped while recovering, which don't stand for anything in the parse tree
+	Skipped
+}
+
+/// A token in the concrete syntax tree, with the exact text it was lexed from, e.g. `0xff` rather than just its value 255
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstToken {
+	pub kind: TokenKind,
+	pub text: String,
+	pub span: Span
+}
+
+impl CstToken {
+	pub fn is_trivia(&self) -> bool {
+		matches!(self.kind, TokenKind::Whitespace | TokenKind::Comment(_) | TokenKind::DocComment(_))
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstElement {
+	Node(CstNode),
+	Token(CstToken)
+}
+
+impl CstElement {
+	pub fn span(&self) -> Span {
+		match self {
+			CstElement::Node(node) => node.span,
+			CstElement::Token(token) => token.span
+		}
+	}
+}
+
+/// A node in the concrete syntax tree, which is lossless - every character of the file is in exactly one token, including whitespace
+/// and comments, so the text of the tree is the text of the file. Whitespace and comments between the tokens of a node are kept in
+/// it, while those before its first token or after its last belong to its parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode {
+	pub kind: CstKind,
+	/// The span from the start of the node's first token that isn't trivia to the end of its last
+	pub span: Span,
+	pub children: Vec<CstElement>
+}
+
+impl CstNode {
+	/// Returns the text the node was parsed from, exactly as it was written
+	pub fn text(&self) -> String {
+		self.tokens().iter().map(|token| token.text.as_str()).collect()
+	}
+
+	/// Returns every token in the node, including those of its descendants, in order
+	pub fn tokens(&self) -> Vec<&CstToken> {
+		let mut tokens = Vec::new();
+
+		for child in &self.children {
+			match child {
+				CstElement::Node(node) => tokens.extend(node.tokens()),
+				CstElement::Token(token) => tokens.push(token)
+			}
+		}
+
+		tokens
+	}
+
+	/// Returns the child nodes
+	pub fn nodes(&self) -> impl Iterator<Item = &CstNode> {
+		self.children.iter().filter_map(|child| match child {
+			CstElement::Node(node) => Some(node),
+			CstElement::Token(_) => None
+		})
+	}
+
+	/// Returns the first child node of the kind
+	pub fn node(&self, kind: CstKind) -> Option<&CstNode> {
+		self.nodes().find(|node| node.kind == kind)
+	}
+
+	/// Returns the kinds of the child tokens which aren't trivia
+	fn token_kinds(&self) -> impl Iterator<Item = &TokenKind> {
+		self.children.iter().filter_map(|child| match child {
+			CstElement::Token(token) if !token.is_trivia() => Some(&token.kind),
+			_ => None
+		})
+	}
+
+	fn has_keyword(&self, keyword: &str) -> bool {
+		self.token_kinds().any(|kind| matches!(kind, TokenKind::Identifier(ident) if ident == keyword))
+	}
+
+	fn has_punct(&self, c: char) -> bool {
+		self.token_kinds().any(|kind| kind == &TokenKind::Punct(c))
+	}
+
+	fn identifier(&self) -> Option<&str> {
+		self.token_kinds().find_map(|kind| match kind {
+			TokenKind::Identifier(ident) => Some(ident.as_str()),
+			_ => None
+		})
+	}
+
+	fn string(&self) -> Option<String> {
+		self.token_kinds().find_map(|kind| match kind {
+			TokenKind::Literal(Literal::String(s)) => Some(s.clone()),
+			_ => None
+		})
+	}
+}
+
+/// The start and end of a CST node, recorded by the TokenStream as it is parsed. Positions are indices of tokens, rather than chars
+#[derive(Debug, Clone)]
+pub enum Event {
+	/// The start of a node before the token at position, whose kind is filled in once the node has been parsed
+	Start {
+		kind: Option<CstKind>,
+		position: usize
+	},
+	/// The end of the last node started and not yet finished, after the token before position
+	Finish {
+		position: usize
+	}
+}
+
+/// Builds the CST of a whole file from its tokens and the events recorded while parsing them. Tokens outside any node, such as
+/// whitespace between items and a trailing comment, belong to the Module node
+pub fn build(file: FileId, content: &str, tokens: &[Token], events: &[Event], end: usize) -> CstNode {
+	let content_chars: Vec<char> = content.chars().collect();
+	let mut stack = vec![CstNode { kind: CstKind::Module, span: Span::new(file, 0, end), children: Vec::new() }];
+	let mut next = 0;
+
+	let mut take_tokens = |stack: &mut Vec<CstNode>, position: usize| {
+		while next < position {
+			let token = &tokens[next];
+
+			stack.last_mut().unwrap().children.push(CstElement::Token(CstToken {
+				kind: token.kind.clone(),
+				text: content_chars[token.start..token.end].iter().collect(),
+				span: Span::new(file, token.start, token.end)
+			}));
+
+			next += 1;
+		}
+	};
+
+	for event in events {
+		match *event {
+			Event::Start { kind, position } => {
+				take_tokens(&mut stack, position);
+
+				let start = tokens.get(position).map_or(end, |token| token.start);
+				stack.push(CstNode { kind: kind.expect("CST node wasn't completed"), span: Span::point(file, start), children: Vec::new() });
+			}
+			Event::Finish { position } => {
+				take_tokens(&mut stack, position);
+
+				let mut node = stack.pop().unwrap();
+				node.span.end = node.children.iter()
+					.filter(|child| !matches!(child, CstElement::Token(token) if token.is_trivia()))
+					.map(|child| child.span().end)
+					.max()
+					.unwrap_or(node.span.start);
+
+				stack.last_mut().unwrap().children.push(CstElement::Node(node));
+			}
+		}
+	}
+
+	take_tokens(&mut stack, tokens.len());
+
+	assert!(stack.len() == 1, "CST node wasn't finished");
+
+	stack.pop().unwrap()
+}
+
+/// Lowers the CST of a file to the parse tree parse returns for it, so that the analyser works the same on either
+pub fn lower(sources: &SourceMap, cst: &CstNode) -> ParseTreeNode {
+	let modname = Path::new(sources.path(cst.span.file)).file_stem().expect("Invalid file path?");
+
+	let mut elems = im::OrdMap::new();
+
+	for node in cst.nodes() {
+		match node.kind {
+			CstKind::Error => {
+				elems.insert(invalid_item_name(node), ParseTree::Invalid.wrap(node.span));
+			}
+			CstKind::Skipped => (),
+			_ => {
+				let (name, item) = lower_item(node);
+
+				elems.insert(name, item);
+			}
+		}
+	}
+
+	ParseTree::Module {
+		name: modname.to_str().expect("Invalid UTF-8 in file path").to_string(),
+		elems
+	}.wrap(cst.span)
+}
+
+/// Returns the name the parser stores the placeholder for an item that failed to parse under
+fn invalid_item_name(node: &CstNode) -> String {
+	let tokens: Vec<Token> = node.tokens().into_iter().map(|token| Token { kind: token.kind.clone(), start: token.span.start, end: token.span.end }).collect();
+	let len = tokens.len();
+
+	let mut tokens = TokenStream::new(tokens, Vec::new(), node.span.file, node.span.end);
+	tokens.set_position(len);
+
+	item_name(&mut tokens, 0).unwrap_or_else(|| format!("invalid {}", node.span.start))
+}
+
+/// Lowers an item, paired with the name it is stored under in the module
+fn lower_item(node: &CstNode) -> (String, ParseTreeNode) {
+	let mut conditions = Vec::new();
+	let mut attributes = im::Vector::new();
+	for attribute in node.nodes().filter(|child| child.kind == CstKind::Attribute) {
+		match lower_attribute(attribute) {
+			ItemAttribute::Cfg(condition) => conditions.push(condition),
+			ItemAttribute::Attribute(attribute) => attributes.push_back(attribute)
+		}
+	}
+
+	let (name, tree) = match node.kind {
+		CstKind::Function => {
+			let name = lower_name(node);
+
+			(name.clone(), ParseTree::Function {
+				name,
+				body: lower_block(node),
+				exported: node.has_keyword("export"),
+				comptime: node.has_keyword("const"),
+				attributes,
+				doc: lower_doc(node)
+			})
+		}
+		CstKind::Struct => {
+			let name = lower_name(node);
+			// A field's span starts at its name, after its doc comments
+			let fields = node.nodes()
+				.filter(|child| child.kind == CstKind::Field)
+				.map(|field| (lower_name(field), Spanned::new(lower_type(field.node(CstKind::Type).unwrap()), field.node(CstKind::Name).unwrap().span.to(field.span))))
+				.collect();
+			let field_docs = node.nodes()
+				.filter(|child| child.kind == CstKind::Field)
+				.filter_map(|field| Some((lower_name(field), lower_doc(field)?)))
+				.collect();
+
+			(name.clone(), ParseTree::Struct { name, fields, attributes, doc: lower_doc(node), field_docs })
+		}
+		CstKind::Extern => {
+			let name = lower_name(node);
+			let (params, returns) = lower_stack_effect(node.node(CstKind::StackEffect).unwrap());
+
+			(name.clone(), ParseTree::Extern { name, library: node.string().unwrap(), params, returns })
+		}
+		CstKind::StaticAssert => {
+			// Static assertions are named after their keyword, which cfg attributes come before
+			let keyword = node.children.iter()
+				.find_map(|child| match child {
+					CstElement::Token(token) if token.kind == TokenKind::Identifier("static_assert".to_string()) => Some(token.span.start),
+					_ => None
+				})
+				.unwrap_or(node.span.start);
+
+			(format!("static_assert {keyword}"), ParseTree::StaticAssert { message: node.string(), body: lower_block(node) })
+		}
+		kind => unreachable!("{kind:?} isn't an item")
+	};
+
+	let item = tree.wrap(node.span);
+
+	if conditions.is_empty() {
+		return (name, item);
+	}
+
+	let condition = if conditions.len() == 1 {
+		conditions.into_iter().next().unwrap()
+	} else {
+		CfgCondition::All(conditions.into_iter().collect())
+	};
+
+	(format!("{name} cfg {}", node.span.start), ParseTree::Cfg { condition, name, item: Box::new(item) }.wrap(node.span))
+}
+
+fn lower_attribute(node: &CstNode) -> ItemAttribute {
+	match node.identifier().unwrap() {
+		"cfg" => ItemAttribute::Cfg(lower_cfg_condition(node.node(CstKind::CfgCondition).unwrap())),
+		"deprecated" => ItemAttribute::Attribute(Attribute::Deprecated(node.string())),
+		"inline" => ItemAttribute::Attribute(Attribute::Inline),
+		"noinline" => ItemAttribute::Attribute(Attribute::NoInline),
+		"cold" => ItemAttribute::Attribute(Attribute::Cold),
+		"must_use" => ItemAttribute::Attribute(Attribute::MustUse),
+		name => unreachable!("unknown attribute {name} wasn't reported")
+	}
+}
+
+fn lower_cfg_condition(node: &CstNode) -> CfgCondition {
+	let name = node.identifier().unwrap().to_string();
+
+	if node.has_punct('(') {
+		let mut conditions: im::Vector<CfgCondition> = node.nodes().map(lower_cfg_condition).collect();
+
+		return match name.as_str() {
+			"all" => CfgCondition::All(conditions),
+			"any" => CfgCondition::Any(conditions),
+			_ => CfgCondition::Not(Box::new(conditions.pop_front().unwrap()))
+		};
+	}
+
+	match node.string() {
+		Some(value) => CfgCondition::KeyValue(name, value),
+		None => CfgCondition::Flag(name)
+	}
+}
+
+/// Returns the text of the doc comments the node starts with, with a line for each comment, if there are any
+fn lower_doc(node: &CstNode) -> Option<String> {
+	let lines: Vec<&str> = node.children.iter()
+		.map_while(|child| match child {
+			CstElement::Token(token) if token.is_trivia() => Some(&token.kind),
+			_ => None
+		})
+		.filter_map(|kind| match kind {
+			TokenKind::DocComment(text) => Some(text.strip_prefix(' ').unwrap_or(text)),
+			_ => None
+		})
+		.collect();
+
+	(!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Returns the name in the node's Name node
+fn lower_name(node: &CstNode) -> String {
+	lower_word(node.node(CstKind::Name).unwrap())
+}
+
+/// Returns the word made up of the node's tokens, which is either an identifier or adjacent symbol characters
+fn lower_word(node: &CstNode) -> String {
+	node.token_kinds().map(|kind| match kind {
+		TokenKind::Identifier(ident) => ident.clone(),
+		TokenKind::Punct(c) => c.to_string(),
+		kind => unreachable!("{kind:?} isn't part of a word")
+	}).collect()
+}
+
+/// Returns the type name a Type node is parsed as, e.g. `&mut Map<u32, [u8; 4]>`
+fn lower_type(node: &CstNode) -> String {
+	let mut inner = node.nodes().map(lower_type);
+
+	if node.has_punct('&') {
+		let inner = inner.next().unwrap();
+
+		return if node.has_keyword("mut") { format!("&mut {inner}") } else { format!("&{inner}") };
+	}
+
+	if node.has_punct('[') {
+		let len = node.token_kinds().find_map(|kind| match kind {
+			TokenKind::Literal(Literal::I32(len)) => Some(*len),
+			_ => None
+		}).unwrap();
+
+		return format!("[{}; {len}]", inner.next().unwrap());
+	}
+
+	let name = node.identifier().unwrap();
+
+	if node.has_punct('<') {
+		return format!("{name}<{}>", inner.collect::<Vec<String>>().join(", "));
+	}
+
+	name.to_string()
+}
+
+/// Returns the popped and pushed type names of a StackEffect node, each with its span
+fn lower_stack_effect(node: &CstNode) -> (im::Vector<Spanned<String>>, im::Vector<Spanned<String>>) {
+	let mut popped = im::Vector::new();
+	let mut pushed = im::Vector::new();
+	let mut after_arrow = false;
+
+	for child in &node.children {
+		match child {
+			CstElement::Token(token) if token.kind == TokenKind::Punct('-') => after_arrow = true,
+			CstElement::Node(node) => {
+				let types = if after_arrow { &mut pushed } else { &mut popped };
+
+				types.push_back(Spanned::new(lower_type(node), node.span));
+			}
+			_ => ()
+		}
+	}
+
+	(popped, pushed)
+}
+
+/// Returns the elements of the node's Block node
+fn lower_block(node: &CstNode) -> im::Vector<ParseTreeNode> {
+	node.node(CstKind::Block).unwrap().nodes().map(|elem| {
+		let tree = match elem.kind {
+			CstKind::Word => ParseTree::Identifier(lower_word(elem)),
+			CstKind::Literal => ParseTree::Literal(lower_literal(elem)),
+			CstKind::Constructor => ParseTree::Constructor(lower_type(elem.node(CstKind::Type).unwrap())),
+			CstKind::FieldAccess => ParseTree::FieldAccess(elem.identifier().unwrap().to_string()),
+			CstKind::Error => ParseTree::Invalid,
+			kind => unreachable!("{kind:?} isn't a block element")
+		};
+
+		tree.wrap(elem.span)
+	}).collect()
+}
+
+fn lower_literal(node: &CstNode) -> Literal {
+	match node.token_kinds().next() {
+		Some(TokenKind::Punct('[')) => Literal::Array(node.nodes().map(lower_literal).collect()),
+		// The word after the & is made up of the rest of the tokens
+		Some(TokenKind::Punct('&')) => Literal::FnPtr(lower_word(node).split_off(1)),
+		Some(TokenKind::Literal(literal)) => literal.clone(),
+		kind => unreachable!("{kind:?} doesn't start a literal")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::parser::{self, source_map::SourceMap, tree::Literal};
+
+	use super::{lower, CstKind, TokenKind};
+
+	/// A file using every kind of item, with comments, odd spacing and literals written in ways that don't round trip through their value
+	const CONTENT: &str = "# leading comment\n## Adds one\n@inline @cfg(debug)\nfn  add1 { 1u __add_u32 } # trailing\n\nstruct P {\n\t## The x\n\tx: u32\n}\nextern \"libc.so.6\" fn abs (i32 -> i32)\nstatic_assert \"ok\" { 0xffu8 255u8 __eq_u8 }\nconst fn p { 1_0u -> P }\nfn main { p .x add1 drop }\n";
+
+	#[test]
+	fn cst_is_lossless() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", CONTENT);
+		let (cst, errors) = parser::parse_cst(&sources, file);
+
+		assert!(errors.is_empty());
+		assert_eq!(cst.text(), CONTENT);
+		assert!(cst.tokens().windows(2).all(|pair| pair[0].span.end == pair[1].span.start));
+
+		let kinds: Vec<CstKind> = cst.nodes().map(|node| node.kind).collect();
+		assert_eq!(kinds, vec![CstKind::Function, CstKind::Struct, CstKind::Extern, CstKind::StaticAssert, CstKind::Function, CstKind::Function]);
+
+		let assertion = cst.nodes().find(|node| node.kind == CstKind::StaticAssert).expect("Expected static_assert node");
+		let literal = assertion.node(CstKind::Block).and_then(|block| block.node(CstKind::Literal)).expect("Expected literal node");
+		assert_eq!(literal.text(), "0xffu8");
+		assert_eq!(literal.tokens()[0].kind, TokenKind::Literal(Literal::U8(255)));
+	}
+
+	#[test]
+	fn lowers_to_parse_tree() {
+		for content in [CONTENT, "fn a { ( }\nfn b { 1u }\n} fn c { b }\n", "struct { }\nfn main { }"] {
+			let mut sources = SourceMap::new();
+			let file = sources.add_file("test.tower", content);
+			let (cst, cst_errors) = parser::parse_cst(&sources, file);
+			let (tree, errors) = parser::parse(&sources, file);
+
+			assert_eq!(cst.text(), content);
+			assert_eq!(lower(&sources, &cst), tree);
+			assert_eq!(cst_errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(), errors.iter().map(|e| e.to_string()).collect::<Vec<_>>());
+		}
+	}
+}
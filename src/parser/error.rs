@@ -1,45 +1,62 @@
 use std::{fmt::{self, Display}, io::{self, Write}};
 
-use crate::{analyser::{ttype::Type, TowerType}, parser::{scanner::Scanner, tree::ParseTreeType, TokenType}};
+use crate::{analyser::{ttype::Type, TowerType}, parser::{source_map::SourceMap, tree::{ParseTreeType, Span}, TokenType}};
 
 // TODO: Is there any better way to handle errors? Or will this do?
 
 #[derive(Clone)]
 pub struct SyntaxError {
 	kind: SyntaxErrorKind,
-	cursor: usize,
+	/// The code the error is about, which is empty when it points to a single character, e.g. an unexpected token
+	span: Span,
 	while_parsing: ParseTreeType
 }
 
 impl SyntaxError {
-	pub fn new(kind: SyntaxErrorKind, while_parsing: ParseTreeType, cursor: usize) -> Self {
+	pub fn new(kind: SyntaxErrorKind, while_parsing: ParseTreeType, span: Span) -> Self {
 		SyntaxError {
 			kind,
-			cursor,
+			span,
 			while_parsing
 		}
 	}
 
-	/// Syntax sugar for `SyntaxError::new(SyntaxErrorKind::Expected(types), cursor)`
-	pub fn expected(types: Vec<TokenType>, while_parsing: ParseTreeType, cursor: usize) -> Self {
-		SyntaxError::new(SyntaxErrorKind::Expected(types), while_parsing, cursor)
+	/// Syntax sugar for `SyntaxError::new(SyntaxErrorKind::Expected(types), span)`
+	pub fn expected(types: Vec<TokenType>, while_parsing: ParseTreeType, span: Span) -> Self {
+		SyntaxError::new(SyntaxErrorKind::Expected(types), while_parsing, span)
 	}
 
-	pub fn empty(cursor: usize) -> Self {
-		SyntaxError::new(SyntaxErrorKind::None, ParseTreeType::None, cursor)
+	pub fn span(&self) -> Span {
+		self.span
 	}
 
-	/// Pretty-prints the error, including context retrieved from the scanner
-	pub fn print_error(&self, scanner: &Scanner, file_name: &str, mut writer: impl Write) -> Result<(), io::Error> {
+	/// Returns the char index of the start of the error's span
+	pub fn cursor(&self) -> usize {
+		self.span.start
+	}
+
+	/// Returns the error moved delta chars through the file, e.g. after an edit before it
+	pub fn shifted(mut self, delta: isize) -> Self {
+		self.span = self.span.shifted(delta);
+		self
+	}
+
+	pub fn empty(span: Span) -> Self {
+		SyntaxError::new(SyntaxErrorKind::None, ParseTreeType::None, span)
+	}
+
+	/// Pretty-prints the error, including context retrieved from the file it is in
+	pub fn print_error(&self, sources: &SourceMap, mut writer: impl Write) -> Result<(), io::Error> {
 		// BUG: Alignment is off when there are multi-byte or multi code point characters such as ✨ in the context line before the cursor
 
-		let context = scanner.get_context(self.cursor);
-		let (col, row) = scanner.get_col_row(self.cursor);
+		let file = self.span.file;
+		let context = sources.get_context(file, self.span.start);
+		let (col, row) = sources.get_col_row(file, self.span.start);
 		let row_str = format!("{row}");
 		let num_tabs = context.chars().filter(|&c| c == '\t').count();
-		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".to_string() ].join("");
+		let cursor_indicator = [ " ".repeat(row_str.len()), " | ".to_string(), "    ".repeat(num_tabs), " ".repeat(col.saturating_sub(1 + num_tabs)), "^".repeat(sources.get_underline_len(self.span)) ].join("");
 
-		writeln!(writer, "Syntax Error at {file_name}:{col}:{row} - {self}")?;
+		writeln!(writer, "Syntax Error at {}:{col}:{row} - {self}", sources.path(file))?;
 		writeln!(writer, "{} | ", " ".repeat(row_str.len()))?;
 		writeln!(writer, "{row} | {}", context.replace("\t", "    "))?;
 		writeln!(writer, "{cursor_indicator}")?;
@@ -87,6 +104,33 @@ impl Display for SyntaxError {
 			SyntaxErrorKind::UnconstructableType { tname } => {
 				write!(f, "while parsing {:?}, type {tname} cannot be constructed (is not a struct or enum variant)", self.while_parsing)
 			}
+			SyntaxErrorKind::UnknownAttribute { name } => {
+				write!(f, "while parsing {:?}, unknown attribute {name}", self.while_parsing)
+			}
+			SyntaxErrorKind::InvalidAttribute { name } => {
+				write!(f, "while parsing {:?}, attribute {name} can't be applied to this item", self.while_parsing)
+			}
+			SyntaxErrorKind::ConflictingAttributes { first, second } => {
+				write!(f, "while parsing {:?}, attribute {second} conflicts with attribute {first}", self.while_parsing)
+			}
+			SyntaxErrorKind::UnknownEscape { escape } => {
+				write!(f, "while parsing {:?}, unknown escape sequence \\{escape}", self.while_parsing)
+			}
+			SyntaxErrorKind::InvalidHexEscape { value } => {
+				write!(f, "while parsing {:?}, escape sequence \\x{value:02X} is out of range, only ASCII characters (at most \\x7F) can be written with \\x outside of byte literals", self.while_parsing)
+			}
+			SyntaxErrorKind::InvalidUnicodeEscape { code } => {
+				write!(f, "while parsing {:?}, escape sequence \\u{{{code}}} is not a unicode scalar value", self.while_parsing)
+			}
+			SyntaxErrorKind::NonAsciiByte { c } => {
+				write!(f, "while parsing {:?}, byte literals can only contain ASCII characters, found {c:?}", self.while_parsing)
+			}
+			SyntaxErrorKind::InvalidCharLiteral { len } => {
+				write!(f, "while parsing {:?}, char literals must contain exactly one character, found {len}", self.while_parsing)
+			}
+			SyntaxErrorKind::DuplicateItem { name } => {
+				write!(f, "while parsing {:?}, item {name} is defined more than once for the current configuration", self.while_parsing)
+			}
 		}
 	}
 }
@@ -120,5 +164,76 @@ pub enum SyntaxErrorKind {
 	},
 	UnconstructableType {
 		tname: String,
+	},
+	UnknownAttribute {
+		name: String,
+	},
+	/// The attribute can't be applied to the kind of item it precedes
+	InvalidAttribute {
+		name: String,
+	},
+	ConflictingAttributes {
+		first: String,
+		second: String,
+	},
+	UnknownEscape {
+		escape: char,
+	},
+	/// A `\xHH` escape outside of a byte literal is above `\x7F`, so isn't an ASCII character
+	InvalidHexEscape {
+		value: u8,
+	},
+	/// The hex digits of a `\u{...}` escape don't make up a unicode scalar value
+	InvalidUnicodeEscape {
+		code: String,
+	},
+	NonAsciiByte {
+		c: char,
+	},
+	InvalidCharLiteral {
+		len: usize,
+	},
+	DuplicateItem {
+		name: String,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::parser::{self, source_map::SourceMap};
+
+	/// Returns the output of printing each syntax error in the file
+	fn printed_errors(sources: &SourceMap, file: parser::source_map::FileId) -> Vec<String> {
+		let (_, errors) = parser::parse(sources, file);
+
+		errors.iter().map(|e| {
+			let mut out = Vec::new();
+			e.print_error(sources, &mut out).unwrap();
+			String::from_utf8(out).unwrap()
+		}).collect()
+	}
+
+	#[test]
+	fn errors_point_to_their_own_file() {
+		let mut sources = SourceMap::new();
+		let first = sources.add_file("first.tower", "fn main { }\n");
+		let second = sources.add_file("second.tower", "fn main {\n\t1u (\n}\n");
+
+		assert!(printed_errors(&sources, first).is_empty());
+
+		let errors = printed_errors(&sources, second);
+		assert_eq!(errors.len(), 1);
+		assert!(errors[0].starts_with("Syntax Error at second.tower:5:2 - "));
+		assert!(errors[0].contains("2 |     1u (\n"));
+	}
+
+	#[test]
+	fn span_is_underlined() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "fn main { 300u8 drop }\n");
+
+		let errors = printed_errors(&sources, file);
+		assert_eq!(errors.len(), 1);
+		assert!(errors[0].ends_with("  |           ^^^^^\n"));
 	}
 }
@@ -0,0 +1,201 @@
+use super::{error::SyntaxError, lexer, scanner::Scanner, source_map::{FileId, SourceMap, TextEdit}, tokens::TokenStream, tree::{ParseTree, ParseTreeNode, Span}};
+
+/// Applies the edit to the file, and returns the tree and errors a full parse of the new content would, given the tree and errors of
+/// the content before the edit. Only the top-level items the edit touches are reparsed, along with any whitespace, comments and junk
+/// between them - every other item is reused, with those after the edit shifted to their new positions. Errors are returned in the order
+/// they occur in the file
+///
+/// The item after the edit is reparsed too if the edit is between items, as the edit may change its doc comments. Items that failed to
+/// parse next to the edit are also reparsed, as how far they extend depends on where the next item starts, along with the item after
+/// them, as their junk may become its doc comments. If the edit removes or renames a named item, the whole file is reparsed instead, as
+/// it may have been hiding another item of the same name which the tree doesn't hold
+pub fn reparse(sources: &mut SourceMap, file: FileId, tree: &ParseTreeNode, errors: &[SyntaxError], edit: &TextEdit) -> (ParseTreeNode, Vec<SyntaxError>) {
+	let (modname, elems) = match &tree.tree {
+		ParseTree::Module { name, elems } => (name.clone(), elems),
+		_ => unreachable!("Only modules can be reparsed")
+	};
+
+	let mut items: Vec<(&String, &ParseTreeNode)> = elems.iter().collect();
+	items.sort_by_key(|(_, node)| node.span.start);
+
+	sources.edit(file, edit);
+	let delta = edit.delta();
+	let len = sources.content(file).chars().count();
+
+	// The items from lo up to hi are reparsed, which are those touching the edit and any neighbouring items that failed to parse
+	let mut lo = items.partition_point(|(_, node)| node.span.end < edit.start);
+	let mut hi = items.partition_point(|(_, node)| node.span.start <= edit.end).max(lo);
+	// An edit between items can turn the comments before the next item into its doc comments, or stop them being so
+	let gap_start = if hi > 0 { items[hi - 1].1.span.end } else { 0 };
+	if hi < items.len() && edit.end >= gap_start {
+		hi += 1;
+	}
+	while lo > 0 && matches!(items[lo - 1].1.tree, ParseTree::Invalid) {
+		lo -= 1;
+	}
+
+	let region_start = if lo > 0 { items[lo - 1].1.span.end } else { 0 };
+
+	let (region, region_errors, hi) = loop {
+		while hi < items.len() && matches!(items[hi].1.tree, ParseTree::Invalid) {
+			hi += 1;
+		}
+		// The junk an item that failed to parse is made of can become comments, and so doc comments of the item after it
+		if hi > 0 && hi < items.len() && matches!(items[hi - 1].1.tree, ParseTree::Invalid) {
+			hi += 1;
+		}
+
+		let region_end = if hi < items.len() { items[hi].1.span.start.saturating_add_signed(delta) } else { len };
+
+		let mut scanner = Scanner::new(file, sources.content(file));
+		scanner.advance(region_start);
+		let (region_tokens, lex_errors) = lexer::tokenise_until(&mut scanner, region_end);
+
+		// A token running past the next item, e.g. an unclosed string, swallows it
+		if scanner.cursor() > region_end {
+			hi += 1;
+			continue;
+		}
+
+		let mut tokens = TokenStream::new(region_tokens, lex_errors, file, region_end);
+		let region = match super::module(&mut tokens, &modname) {
+			ParseTree::Module { elems, .. } => elems,
+			_ => unreachable!()
+		};
+
+		// An item that fails to parse at the end of the region may have been cut short by it
+		let last_invalid = region.values().max_by_key(|node| node.span.start).is_some_and(|node| matches!(node.tree, ParseTree::Invalid));
+		if last_invalid && hi < items.len() {
+			hi += 1;
+			continue;
+		}
+
+		break (region, tokens.take_errors(), hi);
+	};
+
+	let removed = items[lo..hi].iter().any(|(name, _)| !name.contains(' ') && !region.contains_key(*name));
+	if removed {
+		return super::parse(sources, file);
+	}
+
+	let old_region_end = if hi < items.len() { items[hi].1.span.start } else { (len as isize - delta) as usize };
+
+	// Items are inserted in the order they occur in the file, so that a later item hides an earlier one of the same name as in a full parse
+	let mut region: Vec<(String, ParseTreeNode)> = region.into_iter().collect();
+	region.sort_by_key(|(_, node)| node.span.start);
+
+	let elems = items[..lo].iter().map(|(name, node)| ((*name).clone(), (*node).clone()))
+		.chain(region)
+		.chain(items[hi..].iter().map(|(name, node)| shift_item(name, node, delta)))
+		.collect();
+
+	let mut errors: Vec<SyntaxError> = errors.iter()
+		.filter(|error| error.cursor() < region_start || error.cursor() > old_region_end)
+		.map(|error| if error.cursor() > old_region_end { error.clone().shifted(delta) } else { error.clone() })
+		.chain(region_errors)
+		.collect();
+	errors.sort_by_key(|error| error.cursor());
+
+	(ParseTree::Module { name: modname, elems }.wrap(Span::new(file, 0, len)), errors)
+}
+
+/// Returns the item shifted delta chars through the file, along with the name it is stored under, as names of items without one of
+/// their own are made from their cursor
+fn shift_item(name: &str, node: &ParseTreeNode, delta: isize) -> (String, ParseTreeNode) {
+	let mut node = node.shifted(delta);
+
+	if let ParseTree::Cfg { name, .. } = &mut node.tree {
+		*name = shift_name(name, delta);
+	}
+
+	(shift_name(name, delta), node)
+}
+
+/// Shifts the cursor in a name of the form `static_assert {cursor}`, `invalid {cursor}` or `{name} cfg {cursor}`, leaving other names as
+/// they are
+fn shift_name(name: &str, delta: isize) -> String {
+	let (prefix, cursor) = match name.rsplit_once(' ').and_then(|(prefix, cursor)| Some((prefix, cursor.parse::<usize>().ok()?))) {
+		Some(split) => split,
+		None => return name.to_string()
+	};
+
+	let prefix = match prefix.strip_suffix(" cfg") {
+		Some(name) => format!("{} cfg", shift_name(name, delta)),
+		None => prefix.to_string()
+	};
+
+	format!("{prefix} {}", cursor.saturating_add_signed(delta))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::parser::{self, source_map::{SourceMap, TextEdit}};
+
+	use super::reparse;
+
+	/// Reparses content after replacing the chars from start up to end with text, checking the tree and errors match a full parse of the
+	/// new content
+	fn check_reparse(content: &str, start: usize, end: usize, text: &str) {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tree, errors) = parser::parse(&sources, file);
+
+		let (tree, errors) = reparse(&mut sources, file, &tree, &errors, &TextEdit { start, end, text: text.to_string() });
+
+		let mut expected_sources = SourceMap::new();
+		let expected_file = expected_sources.add_file("test.tower", sources.content(file));
+		let (expected_tree, expected_errors) = parser::parse(&expected_sources, expected_file);
+
+		assert_eq!(tree, expected_tree);
+		assert_eq!(errors.iter().map(|e| (e.cursor(), e.to_string())).collect::<Vec<_>>(), expected_errors.iter().map(|e| (e.cursor(), e.to_string())).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn edit_inside_item() {
+		check_reparse("fn a { 1u }\nfn b { 2u }\nfn main { a b }\n", 19, 21, "3u 4u");
+	}
+
+	#[test]
+	fn edit_shifts_later_items() {
+		check_reparse("fn a { 1u }\nstatic_assert { true }\n@cfg(interpreter) fn b { }\nfn c { x x }", 7, 9, "");
+	}
+
+	#[test]
+	fn edit_breaks_item() {
+		check_reparse("fn a { 1u }\nfn b { 2u }\nfn c { 3u }\n", 17, 19, "(");
+		check_reparse("fn a { 1u }\nfn b { 2u }\nfn c { 3u }\n", 22, 23, "\"");
+	}
+
+	#[test]
+	fn edit_fixes_item() {
+		check_reparse("fn a { 1u \nfn b { 2u }\nfn c { 3u }\n", 10, 10, "}");
+	}
+
+	#[test]
+	fn edit_renames_item() {
+		check_reparse("fn a { 1u }\nfn b { 2u }\nfn a { 3u }\n", 15, 16, "a");
+		check_reparse("fn a { 1u }\nfn a { 2u }\n", 15, 16, "b");
+	}
+
+	#[test]
+	fn edit_makes_doc_comment() {
+		check_reparse("// adds\nfn a { 1u }\nfn main { a }\n", 0, 2, "##");
+		check_reparse("fn z { }\n# adds\nfn a { 1u }\n", 9, 10, "##");
+	}
+
+	#[test]
+	fn edit_removes_doc_comment() {
+		check_reparse("## adds\nfn a { 1u }\nfn main { a }\n", 0, 2, "#");
+		check_reparse("## adds\n## one\nfn a { 1u }\n", 8, 16, "");
+	}
+
+	#[test]
+	fn edit_turns_invalid_item_into_doc_comment() {
+		check_reparse("# c\n## Doc a\n@i@nline\nfn a { 1u 2u add }\nfn main { a }\n", 12, 13, "1u");
+	}
+
+	#[test]
+	fn edit_doc_comment_of_field() {
+		check_reparse("struct P {\n\t## x\n\tx: u32\n}\nfn main { }\n", 14, 15, "the x");
+	}
+}
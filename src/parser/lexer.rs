@@ -0,0 +1,581 @@
+use std::num::IntErrorKind;
+
+use unicode_xid::UnicodeXID;
+
+use crate::{analyser::TowerType, brk};
+
+use super::{error::{SyntaxError, SyntaxErrorKind}, result::ScanResult::{self, Valid, WithErr, Unrecognised}, scanner::Scanner, tree::{Literal, ParseTreeType, Span}, ParseResult, TokenType, SYMBOL_CHARS};
+
+/// The punctuation characters which aren't symbol characters, and so can't be part of a symbolic identifier
+const PUNCT_CHARS: [char; 11] = ['{', '}', '[', ']', '(', ')', ',', ':', ';', '.', '@'];
+
+/// A token, spanning the characters from start up to end
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+	pub kind: TokenKind,
+	pub start: usize,
+	pub end: usize
+}
+
+impl Token {
+	/// Returns whether the token is whitespace or a comment, which the parser skips over
+	pub fn is_trivia(&self) -> bool {
+		matches!(self.kind, TokenKind::Whitespace | TokenKind::Comment(_) | TokenKind::DocComment(_))
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+	Whitespace,
+	/// A comment from a `#` to the end of the line, holding the text after the `#`
+	Comment(String),
+	/// A doc comment from a `##` to the end of the line, holding the text after the `##`. Doc comments before an item or struct field
+	/// document it
+	DocComment(String),
+	/// An identifier, which may be a keyword such as `fn`
+	Identifier(String),
+	Literal(Literal),
+	/// Characters that couldn't be lexed, e.g. a literal with an unknown escape sequence, whose error has already been reported
+	Invalid,
+	/// A single punctuation or symbol character. Multi-character syntax such as `->` and symbolic identifiers such as `<=>` are made up
+	/// of adjacent Punct tokens
+	Punct(char)
+}
+
+/// Splits the scanner's content into tokens, covering every character. A malformed literal or a character that can't start a token
+/// doesn't stop lexing - the characters are covered by an Invalid token instead, and the error is returned alongside the tokens
+pub fn tokenise(scanner: &mut Scanner) -> (Vec<Token>, Vec<SyntaxError>) {
+	tokenise_until(scanner, usize::MAX)
+}
+
+/// Splits the scanner's content into tokens like tokenise, but stops at the first token starting at or after the char index end. The
+/// last token may go past end
+pub fn tokenise_until(scanner: &mut Scanner, end: usize) -> (Vec<Token>, Vec<SyntaxError>) {
+	let mut tokens = Vec::new();
+	let mut errors = Vec::new();
+
+	while scanner.has_next() && scanner.cursor() < end {
+		let start = scanner.cursor();
+
+		// Literals come first, as string literals can be prefixed by what would otherwise be an identifier, e.g. `r"raw"`, and negative
+		// number literals by a symbol character
+		let kind = match scanner.take_choice(vec![
+			Box::new(whitespace),
+			Box::new(comment),
+			Box::new(|scanner| literal(scanner).map(TokenKind::Literal)),
+			Box::new(identifier),
+			Box::new(punct)
+		]) {
+			Valid(kind) => kind,
+			WithErr(e) => {
+				errors.push(e);
+
+				// Literals are taken up to their end even when they are malformed, so lexing carries on after them
+				if scanner.cursor() == start {
+					scanner.advance(1);
+				}
+
+				TokenKind::Invalid
+			}
+			Unrecognised => {
+				errors.push(SyntaxError::new(SyntaxErrorKind::Unexpected, ParseTreeType::Token, Span::point(scanner.file(), start)));
+
+				scanner.take_until(|c| !c.is_whitespace());
+
+				TokenKind::Invalid
+			}
+		};
+
+		tokens.push(Token { kind, start, end: scanner.cursor() });
+	}
+
+	(tokens, errors)
+}
+
+fn whitespace(scanner: &mut Scanner) -> ParseResult<TokenKind> {
+	let whitespace = scanner.take_until(|c| c.is_whitespace());
+
+	ParseResult::from(!whitespace.is_empty()).map(|_| TokenKind::Whitespace)
+}
+
+/// Returns a comment, which starts with `#` and ends at the end of the line, so also covers shebangs. Comments starting with `##` are doc
+/// comments
+fn comment(scanner: &mut Scanner) -> ParseResult<TokenKind> {
+	brk!(scanner.take('#').into());
+
+	if scanner.take('#') {
+		return Valid(TokenKind::DocComment(scanner.take_until(|c| c != '\n')));
+	}
+
+	Valid(TokenKind::Comment(scanner.take_until(|c| c != '\n')))
+}
+
+fn identifier(scanner: &mut Scanner) -> ParseResult<TokenKind> {
+	let first = brk!(scanner.take_if(|c| {
+		UnicodeXID::is_xid_start(c) || c == '_'
+	}).into());
+
+	let mut ident = scanner.take_until(UnicodeXID::is_xid_continue);
+
+	ident.insert(0, first);
+
+	Valid(TokenKind::Identifier(ident))
+}
+
+fn punct(scanner: &mut Scanner) -> ParseResult<TokenKind> {
+	scanner.take_if(|c| SYMBOL_CHARS.contains(&c) || PUNCT_CHARS.contains(&c)).map(TokenKind::Punct).into()
+}
+
+/// Returns a Literal of a string, char or integer literal. Array literals and function references are made up of multiple tokens,
+/// so are left to the parser
+fn literal(scanner: &mut Scanner) -> ParseResult<Literal> {
+	scanner.take_choice(vec![
+		Box::new(literal_string),
+		Box::new(literal_byte_string),
+		Box::new(literal_char),
+		Box::new(literal_integer)
+	])
+}
+
+/// Returns a Literal::String of a string literal `"..."`, or of a raw string literal `r"..."`, in which backslashes aren't escapes. Raw
+/// string literals can be delimited with any number of `#`s to contain quotes, e.g. `r#"say "hi""#`
+fn literal_string(scanner: &mut Scanner) -> ParseResult<Literal> {
+	eprintln!("literal_string");
+
+	let chars = brk!(quoted_chars(scanner, '"', false));
+
+	eprintln!("literal_string end @ {}", scanner.cursor());
+
+	Valid(Literal::String(chars.into_iter().collect()))
+}
+
+/// Returns a Literal::ByteString of a byte string literal `b"..."` or raw byte string literal `br"..."`, which can only contain ASCII
+/// characters and escapes
+fn literal_byte_string(scanner: &mut Scanner) -> ParseResult<Literal> {
+	brk!(scanner.take('b').into());
+
+	let chars = brk!(quoted_chars(scanner, '"', true));
+
+	Valid(Literal::ByteString(chars.into_iter().map(|c| c as u8).collect()))
+}
+
+/// Returns a Literal::Char of a char literal `'c'`, or a Literal::U8 of a byte literal `b'c'`, which hold exactly one character or
+/// escape sequence
+fn literal_char(scanner: &mut Scanner) -> ParseResult<Literal> {
+	let byte = scanner.take('b');
+
+	let start = scanner.cursor();
+
+	let chars = brk!(quoted_chars(scanner, '\'', byte));
+
+	if chars.len() != 1 {
+		return WithErr(SyntaxError::new(SyntaxErrorKind::InvalidCharLiteral { len: chars.len() }, ParseTreeType::Literal, Span::point(scanner.file(), start)));
+	}
+
+	Valid(match byte {
+		true => Literal::U8(chars[0] as u8),
+		false => Literal::Char(chars[0])
+	})
+}
+
+/// Returns the characters between a pair of quotes, with escape sequences replaced by the characters they stand for. If quote is `"`,
+/// the quotes can be preceded by `r` and any number of `#`s for a raw string, which is closed by a quote and the same number of `#`s.
+/// For byte literals, the characters are all at most `\u{FF}`, each standing for a byte
+fn quoted_chars(scanner: &mut Scanner, quote: char, byte: bool) -> ParseResult<Vec<char>> {
+	let (raw, closing) = brk!(scanner.try_take(|scanner| -> ParseResult<(bool, String)> {
+		let raw = quote == '"' && scanner.take('r');
+		let hashes = if raw { scanner.take_until(|c| c == '#') } else { String::new() };
+
+		brk!(scanner.take(quote).into());
+
+		Valid((raw, format!("{quote}{hashes}")))
+	}));
+
+	let mut chars = Vec::new();
+	// The rest of the literal is still taken after an invalid escape sequence or character, so that lexing can carry on after it
+	let mut err = None;
+
+	while !scanner.take_str(&closing) {
+		let cursor = scanner.cursor();
+
+		match scanner.peek() {
+			Some('\\') if !raw => match escape_sequence(scanner, byte) {
+				Valid(c) => chars.push(c),
+				WithErr(e) => { err.get_or_insert(e); }
+				Unrecognised => unreachable!()
+			},
+			Some(c) => {
+				scanner.advance(1);
+
+				if byte && !c.is_ascii() {
+					err.get_or_insert(SyntaxError::new(SyntaxErrorKind::NonAsciiByte { c }, ParseTreeType::Literal, Span::point(scanner.file(), cursor)));
+				}
+
+				chars.push(c);
+			}
+			None => return WithErr(SyntaxError::expected(vec![TokenType::Quote], ParseTreeType::Literal, Span::point(scanner.file(), cursor)))
+		}
+	}
+
+	if let Some(e) = err {
+		return WithErr(e);
+	}
+
+	Valid(chars)
+}
+
+/// Returns the character an escape sequence stands for, which is one of `\\ \n \t \r \0 \" \'`, `\xHH` with HH at most 7F, or
+/// `\u{H}` with 1 to 6 hex digits making up a unicode scalar value. In byte literals, `\xHH` can be any byte and `\u{H}` isn't allowed
+fn escape_sequence(scanner: &mut Scanner, byte: bool) -> ParseResult<char> {
+	let start = scanner.cursor();
+
+	brk!(scanner.take('\\').into());
+
+	let escape = brk!(ParseResult::from(scanner.pop()).require(SyntaxError::expected(vec![TokenType::EscapeSequence], ParseTreeType::Literal, scanner.point())));
+
+	Valid(match escape {
+		'\\' => '\\',
+		'n' => '\n',
+		't' => '\t',
+		'r' => '\r',
+		'0' => '\0',
+		'"' => '"',
+		'\'' => '\'',
+		'x' => {
+			let mut sb = String::new();
+
+			for _ in 0..2 {
+				sb.push(brk!(ParseResult::from(scanner.take_if(|c| {
+					c.is_ascii_hexdigit()
+				})).require(SyntaxError::expected(vec![TokenType::Number], ParseTreeType::Literal, scanner.point()))))
+			}
+
+			let value = u8::from_str_radix(&sb, 16).unwrap();
+
+			// Strings are UTF-8, so only ASCII characters can be written as a single byte
+			if !byte && !value.is_ascii() {
+				return WithErr(SyntaxError::new(SyntaxErrorKind::InvalidHexEscape { value }, ParseTreeType::Literal, Span::point(scanner.file(), start)));
+			}
+
+			char::from(value)
+		}
+		'u' if !byte => {
+			brk!(ParseResult::from(scanner.take('{')).require(SyntaxError::expected(vec![TokenType::LCurlyParen], ParseTreeType::Literal, scanner.point())));
+
+			let digits = scanner.take_until(|c| c.is_ascii_hexdigit());
+
+			if digits.is_empty() {
+				return WithErr(SyntaxError::expected(vec![TokenType::Number], ParseTreeType::Literal, scanner.point()));
+			}
+
+			brk!(ParseResult::from(scanner.take('}')).require(SyntaxError::expected(vec![TokenType::Number, TokenType::RCurlyParen], ParseTreeType::Literal, scanner.point())));
+
+			// Surrogates and code points above 10FFFF aren't unicode scalar values
+			match u32::from_str_radix(&digits, 16).ok().filter(|_| digits.len() <= 6).and_then(char::from_u32) {
+				Some(c) => c,
+				None => return WithErr(SyntaxError::new(SyntaxErrorKind::InvalidUnicodeEscape { code: digits }, ParseTreeType::Literal, Span::point(scanner.file(), start)))
+			}
+		}
+		_ => return WithErr(SyntaxError::new(SyntaxErrorKind::UnknownEscape { escape }, ParseTreeType::Literal, Span::point(scanner.file(), start)))
+	})
+}
+
+/// Returns a Literal
+fn literal_integer(scanner: &mut Scanner) -> ParseResult<Literal> { // TODO: To allow better type inference, we could perhaps only store the literal strings for now, and then turn the strings into integers once we've decided the types
+	// let i = 1000000000000000000; // Rust seems to default to i32 unless explicitly told otherwise for literals
+	let start_of_literal = scanner.cursor();
+
+	let negative = scanner.take('-');
+
+	let (digits, radix) = brk!(scanner.take_choice::<(String, u8), SyntaxError>(vec![
+		Box::new(move |scanner| { // Parse binary literal
+			integer_digits(scanner, 2).map(|d| (d, 2))
+		}),
+		Box::new(move |scanner| { // Parse octal literal
+			integer_digits(scanner, 8).map(|d| (d, 8))
+		}),
+		Box::new(move |scanner| { // Parse hex literal
+			integer_digits(scanner, 16).map(|d| (d, 16))
+		}),
+		Box::new(move |scanner| { // Parse denary literal
+			integer_digits(scanner, 10).map(|d| (d, 10))
+		})
+	]));
+
+	let start_of_suffix = scanner.cursor();
+
+	let suffix = brk!(scanner.try_take(|scanner| {
+			let signed = brk!(scanner.take_of(['u', 'i'].iter()).into()) == 'i';
+
+			let bits = brk!(literal_integer_radix(scanner, 10).optional());
+
+			Valid((signed, bits))
+		}
+	).optional());
+
+	let num_type = match suffix {
+		Some((signed, Some(bits))) => {
+			if !signed && negative {
+				return WithErr(SyntaxError::new(SyntaxErrorKind::NegativeUnsignedLiteral, ParseTreeType::Literal, Span::point(scanner.file(), start_of_suffix)));
+			}
+
+			match bits {
+				8 => { if signed { TowerType::I8 } else { TowerType::U8 } },
+				16 => { if signed { TowerType::I16 } else { TowerType::U16 } },
+				32 => { if signed { TowerType::I32 } else { TowerType::U32 } },
+				64 => { if signed { TowerType::I64 } else { TowerType::U64 } },
+				128 => { if signed { TowerType::I128 } else { TowerType::U128 } },
+				_ => return WithErr(SyntaxError::new(SyntaxErrorKind::InvalidIntegerSize, ParseTreeType::Literal, Span::point(scanner.file(), start_of_suffix + 1)))
+			}
+		}
+		Some((signed, None)) => {
+			if !signed && negative {
+				return WithErr(SyntaxError::new(SyntaxErrorKind::NegativeUnsignedLiteral, ParseTreeType::Literal, Span::point(scanner.file(), start_of_suffix)));
+			}
+
+			if signed {
+				TowerType::I32
+			} else {
+				TowerType::U32
+			}
+		}
+		None => {
+			// Unsuffixed binary, octal and hex literals are unsigned unless negated
+			if radix == 10 || negative {
+				TowerType::I32
+			} else {
+				TowerType::U32
+			}
+		}
+	};
+
+	let overflow = SyntaxError::new(SyntaxErrorKind::LiteralIntegerOverflow {
+		num: format!("{}{}{digits}", if negative { "-" } else { "" }, radix_prefix(radix)),
+		target_type: num_type.clone()
+	}, ParseTreeType::Literal, scanner.span_from(start_of_literal));
+
+	let Ok(magnitude) = u128::from_str_radix(&digits, radix as u32) else {
+		return WithErr(overflow);
+	};
+
+	// Negating the magnitude rather than the parsed value lets the most negative value of each signed type through
+	let signed = if negative {
+		0i128.checked_sub_unsigned(magnitude)
+	} else {
+		i128::try_from(magnitude).ok()
+	};
+
+	let literal = match num_type {
+		TowerType::U128 => Some(Literal::U128(magnitude)),
+		TowerType::U64 => magnitude.try_into().ok().map(Literal::U64),
+		TowerType::U32 => magnitude.try_into().ok().map(Literal::U32),
+		TowerType::U16 => magnitude.try_into().ok().map(Literal::U16),
+		TowerType::U8 => magnitude.try_into().ok().map(Literal::U8),
+		TowerType::I128 => signed.map(Literal::I128),
+		TowerType::I64 => signed.and_then(|n| n.try_into().ok()).map(Literal::I64),
+		TowerType::I32 => signed.and_then(|n| n.try_into().ok()).map(Literal::I32),
+		TowerType::I16 => signed.and_then(|n| n.try_into().ok()).map(Literal::I16),
+		TowerType::I8 => signed.and_then(|n| n.try_into().ok()).map(Literal::I8),
+		_ => unreachable!()
+	};
+
+	match literal {
+		Some(literal) => Valid(literal),
+		None => WithErr(overflow)
+	}
+}
+
+fn radix_prefix(radix: u8) -> &'static str {
+	match radix {
+		2 => "0b",
+		8 => "0o",
+		10 => "",
+		16 => "0x",
+		_ => unimplemented!("Illegal radix value: {radix}")
+	}
+}
+
+/// Parses an integer (including prefix, not including suffix) using radix (radix ∈ [2, 8, 10, 16]), and fails if it doesn't fit in a u128
+fn literal_integer_radix(scanner: &mut Scanner, radix: u8) -> ParseResult<u128> {
+	let start_of_int = scanner.cursor();
+
+	let digits = brk!(integer_digits(scanner, radix));
+
+	match u128::from_str_radix(&digits, radix as u32) {
+		Ok(num) => Valid(num),
+		Err(e) => {
+			match e.kind() {
+				IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+					WithErr(SyntaxError::new(SyntaxErrorKind::LiteralIntegerOverflow { num: format!("{}{digits}", radix_prefix(radix)), target_type: TowerType::U128 }, ParseTreeType::Literal, scanner.span_from(start_of_int)))
+				}
+				_ => unreachable!()
+			}
+		}
+	}
+}
+
+/// Parses the digits of an integer including its prefix, and returns them without the prefix or any `_` separators. Separators may appear
+/// anywhere after the prefix, but denary integers have to start with a digit so that they can't be confused with identifiers
+fn integer_digits(scanner: &mut Scanner, radix: u8) -> ParseResult<String> {
+	let is_radix_digit = match radix {
+		2 => |c: char| { c == '0' || c == '1' },
+		8 => |c: char| { ['0', '1', '2', '3', '4', '5', '6', '7'].iter().find(|&&oc| oc == c).is_some() },
+		10 => |c: char| { c.is_ascii_digit() },
+		16 => |c: char| { c.is_ascii_hexdigit() },
+		_ => unimplemented!("Illegal radix value: {radix}")
+	};
+
+	let prefix = radix_prefix(radix);
+
+	if prefix.is_empty() {
+		brk!(ParseResult::from(scanner.peek().is_some_and(is_radix_digit)));
+	} else {
+		brk!(scanner.take_str(prefix).into());
+	}
+
+	let digits: String = scanner.take_until(|c| is_radix_digit(c) || c == '_').chars().filter(|&c| c != '_').collect();
+
+	if digits.is_empty() {
+		return WithErr(SyntaxError::expected(vec![TokenType::Number], ParseTreeType::Literal, scanner.point()));
+	}
+
+	Valid(digits)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::parser::{scanner::Scanner, source_map::SourceMap, tree::Literal as Lit};
+
+	use super::{tokenise, tokenise_until, Token, TokenKind::{self, *}};
+
+	/// Tokenises content, returning its tokens and the cursor and message of each error
+	fn lex(content: &str) -> (Vec<Token>, Vec<(usize, String)>) {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tokens, errors) = tokenise(&mut Scanner::new(file, content));
+
+		(tokens, errors.iter().map(|e| (e.cursor(), e.to_string())).collect())
+	}
+
+	/// Tokenises content, returning the kinds of its tokens other than whitespace
+	fn kinds(content: &str) -> Vec<TokenKind> {
+		lex(content).0.into_iter().filter(|token| token.kind != Whitespace).map(|token| token.kind).collect()
+	}
+
+	#[test]
+	fn tokens_cover_content() {
+		let content = "fn main { 1u \"s\" } # comment\n## doc\n\"bad\\q\" ` x";
+		let (tokens, _) = lex(content);
+
+		assert_eq!(tokens.first().map(|token| token.start), Some(0));
+		assert_eq!(tokens.last().map(|token| token.end), Some(content.chars().count()));
+		assert!(tokens.windows(2).all(|pair| pair[0].end == pair[1].start));
+	}
+
+	#[test]
+	fn literals() {
+		assert_eq!(kinds("1_000u -5i8 0x1Fu8 0b101i32 1_0_u64 b'a' 'x' \"s\\n\" r\"raw\\n\""), vec![
+			Literal(Lit::U32(1000)),
+			Literal(Lit::I8(-5)),
+			Literal(Lit::U8(31)),
+			Literal(Lit::I32(5)),
+			Literal(Lit::U64(10)),
+			Literal(Lit::U8(b'a')),
+			Literal(Lit::Char('x')),
+			Literal(Lit::String("s\n".to_string())),
+			Literal(Lit::String("raw\\n".to_string()))
+		]);
+	}
+
+	#[test]
+	fn punctuation_and_comments() {
+		assert_eq!(kinds("a -> <=> &f @cfg # note\n## doc"), vec![
+			Identifier("a".to_string()),
+			Punct('-'), Punct('>'),
+			Punct('<'), Punct('='), Punct('>'),
+			Punct('&'), Identifier("f".to_string()),
+			Punct('@'), Identifier("cfg".to_string()),
+			Comment(" note".to_string()),
+			DocComment(" doc".to_string())
+		]);
+	}
+
+	#[test]
+	fn errors_become_invalid_tokens() {
+		let (tokens, errors) = lex("\"bad\\q\" x ` y");
+
+		assert_eq!(tokens.iter().filter(|token| token.kind != Whitespace).map(|token| &token.kind).collect::<Vec<_>>(), vec![&Invalid, &Identifier("x".to_string()), &Invalid, &Identifier("y".to_string())]);
+		assert_eq!(errors, vec![
+			(4, "while parsing Literal, unknown escape sequence \\q".to_string()),
+			(10, "while parsing Token, unexpected string".to_string())
+		]);
+	}
+
+	#[test]
+	fn tokenise_until_stops_at_end() {
+		let content = "fn a { } fn b { }";
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", content);
+		let (tokens, errors) = tokenise_until(&mut Scanner::new(file, content), 4);
+
+		assert!(errors.is_empty());
+		assert_eq!(tokens.last().map(|token| (token.start, token.end)), Some((3, 4)));
+	}
+
+	#[test]
+	fn integer_literals_at_their_limits() {
+		assert_eq!(kinds("-128i8 127i8 255u8 -0i8 0xFF_FFu16 0b1111_1111u8 0o777u16 -170_141_183_460_469_231_731_687_303_715_884_105_728i128 340282366920938463463374607431768211455u128"), vec![
+			Literal(Lit::I8(-128)),
+			Literal(Lit::I8(127)),
+			Literal(Lit::U8(255)),
+			Literal(Lit::I8(0)),
+			Literal(Lit::U16(65535)),
+			Literal(Lit::U8(255)),
+			Literal(Lit::U16(511)),
+			Literal(Lit::I128(i128::MIN)),
+			Literal(Lit::U128(u128::MAX))
+		]);
+	}
+
+	#[test]
+	fn overflowing_literals_error() {
+		let errors = |content: &str| lex(content).1;
+
+		assert_eq!(errors("x 128i8"), vec![(2, "while parsing Literal, integer literal 128 doesn't fit in target type I8".to_string())]);
+		assert_eq!(errors("x -129i8"), vec![(2, "while parsing Literal, integer literal -129 doesn't fit in target type I8".to_string())]);
+		assert_eq!(errors("x 0x1_0000u16"), vec![(2, "while parsing Literal, integer literal 0x10000 doesn't fit in target type U16".to_string())]);
+		assert_eq!(errors("170141183460469231731687303715884105728i128").len(), 1);
+		assert_eq!(errors("340282366920938463463374607431768211456u128").len(), 1);
+		assert_eq!(errors("-1u64").len(), 1);
+
+		// The whole literal is covered by the Invalid token
+		assert_eq!(lex("x 0x1_0000u16 y").0[2], Token { kind: Invalid, start: 2, end: 13 });
+	}
+
+	#[test]
+	fn string_and_char_literals() {
+		assert_eq!(kinds(r##""\u{263A}\x41\t\0\\\"\'" r#"say "hi""# r"C:\path" b"hi\n\xff" br"\n" '\n' '\u{1F600}' b'a'"##), vec![
+			Literal(Lit::String("\u{263A}A\t\0\\\"'".to_string())),
+			Literal(Lit::String("say \"hi\"".to_string())),
+			Literal(Lit::String("C:\\path".to_string())),
+			Literal(Lit::ByteString(vec![b'h', b'i', b'\n', 0xff])),
+			Literal(Lit::ByteString(vec![b'\\', b'n'])),
+			Literal(Lit::Char('\n')),
+			Literal(Lit::Char('\u{1F600}')),
+			Literal(Lit::U8(b'a'))
+		]);
+	}
+
+	#[test]
+	fn invalid_string_and_char_literals() {
+		let error = |content: &str| lex(content).1.into_iter().map(|(_, message)| message).collect::<Vec<_>>();
+
+		assert_eq!(error(r#""\x80""#), vec!["while parsing Literal, escape sequence \\x80 is out of range, only ASCII characters (at most \\x7F) can be written with \\x outside of byte literals"]);
+		assert_eq!(error(r#""\u{D800}""#), vec!["while parsing Literal, escape sequence \\u{D800} is not a unicode scalar value"]);
+		assert_eq!(error(r#""\u{110000}""#), vec!["while parsing Literal, escape sequence \\u{110000} is not a unicode scalar value"]);
+		assert_eq!(error(r#"b"\u{41}""#), vec!["while parsing Literal, unknown escape sequence \\u"]);
+		assert_eq!(error(r#"b"é""#), vec!["while parsing Literal, byte literals can only contain ASCII characters, found 'é'"]);
+		assert_eq!(error("'ab'"), vec!["while parsing Literal, char literals must contain exactly one character, found 2"]);
+		assert_eq!(error("''"), vec!["while parsing Literal, char literals must contain exactly one character, found 0"]);
+	}
+}
@@ -1,54 +1,37 @@
 
-use super::result::ScanResult::{self, Valid, WithErr, Unrecognised};
+use super::{result::ScanResult::{self, Valid, WithErr, Unrecognised}, source_map::FileId, tree::Span};
 
-pub struct Scanner<'a> {
-	file_path: String,
-	content: &'a str,
+pub struct Scanner {
+	file: FileId,
 	content_chars: Vec<char>,
 	cursor: usize
 }
 
-impl<'a> Scanner<'a> { // TODO: Introduce a better naming scheme, with separation of functions operating on the underlying data and higher order functions
-	pub fn new(content: &'a str, file_path: impl Into<String>) -> Self {
+impl Scanner { // TODO: Introduce a better naming scheme, with separation of functions operating on the underlying data and higher order functions
+	pub fn new(file: FileId, content: &str) -> Self {
 		Scanner {
-			file_path: file_path.into(),
-			content,
+			file,
 			content_chars: content.chars().collect(),
 			cursor: 0
 		}
 	}
 
-	pub fn file_path<'b>(&'b self) -> &'b str {
-		&self.file_path
-	}
-
 	pub fn cursor(&self) -> usize {
 		self.cursor
 	}
 
-	/// Returns the line pointed to by the cursor
-	pub fn get_context(&self, cursor: usize) -> String {
-		// Find the previous newline
-		let start = self.content_chars[0..cursor].iter().rposition(|&c| c == '\n').map(|i| i + 1).unwrap_or(0);
-
-		// Find the next newline or carriage return (as that comes before newline on windows)
-		let end = cursor + self.content_chars[cursor..].iter().position(|&c| c == '\n' || c == '\r').unwrap_or(self.content_chars.len() - cursor);
-
-		// Sadly a slice of chars is not the same as a string slice (chars are always 32-bit)
-		self.content_chars[start..end].iter().collect::<String>()
+	pub fn file(&self) -> FileId {
+		self.file
 	}
 
-	pub fn get_col_row(&self, cursor: usize) -> (usize, usize) {
-		let row = self.content_chars[0..cursor].iter().filter(|&&c| c == '\n').count();
-		let col = self.content_chars[0..cursor].iter().fold::<usize, _>(0, |acc, &c| {
-			if c == '\n' {
-				0
-			} else {
-				acc + 1
-			}
-		});
+	/// Returns the empty span at the cursor
+	pub fn point(&self) -> Span {
+		Span::point(self.file, self.cursor)
+	}
 
-		(col + 1, row + 1)
+	/// Returns the span from the char index start up to the cursor
+	pub fn span_from(&self, start: usize) -> Span {
+		Span::new(self.file, start, self.cursor)
 	}
 
 	pub fn has_next(&self) -> bool {
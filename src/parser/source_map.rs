@@ -0,0 +1,165 @@
+use super::tree::Span;
+
+/// Identifies a file loaded into a SourceMap. Spans store one rather than the file's path, so are cheap to copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+struct SourceFile {
+	path: String,
+	content: String,
+	content_chars: Vec<char>,
+	/// The char index of the start of each line, for looking up the line a cursor is on
+	line_starts: Vec<usize>
+}
+
+/// A change to the text of a file, replacing the chars from start up to end with text
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+	pub start: usize,
+	pub end: usize,
+	pub text: String
+}
+
+impl TextEdit {
+	/// Returns how far the edit moves the text after it, in chars
+	pub fn delta(&self) -> isize {
+		self.text.chars().count() as isize - (self.end - self.start) as isize
+	}
+}
+
+impl SourceFile {
+	fn new(path: String, content: String) -> Self {
+		let content_chars: Vec<char> = content.chars().collect();
+		let line_starts = std::iter::once(0).chain(content_chars.iter().enumerate().filter(|(_, &c)| c == '\n').map(|(i, _)| i + 1)).collect();
+
+		SourceFile {
+			path,
+			content,
+			content_chars,
+			line_starts
+		}
+	}
+}
+
+/// Owns the source of every file loaded, which parsing borrows and errors are rendered from
+#[derive(Default)]
+pub struct SourceMap {
+	files: Vec<SourceFile>
+}
+
+impl SourceMap {
+	pub fn new() -> Self {
+		SourceMap {
+			files: Vec::new()
+		}
+	}
+
+	/// Loads the content as the file at path, returning the id it can be looked up with
+	pub fn add_file(&mut self, path: impl Into<String>, content: impl Into<String>) -> FileId {
+		self.files.push(SourceFile::new(path.into(), content.into()));
+
+		FileId(self.files.len() as u32 - 1)
+	}
+
+	/// Applies the edit to the content of the file
+	pub fn edit(&mut self, file: FileId, edit: &TextEdit) {
+		let source = &self.files[file.0 as usize];
+		let content: String = source.content_chars[..edit.start].iter().chain(edit.text.chars().collect::<Vec<char>>().iter()).chain(source.content_chars[edit.end..].iter()).collect();
+
+		self.files[file.0 as usize] = SourceFile::new(source.path.clone(), content);
+	}
+
+	fn file(&self, file: FileId) -> &SourceFile {
+		&self.files[file.0 as usize]
+	}
+
+	pub fn path(&self, file: FileId) -> &str {
+		&self.file(file).path
+	}
+
+	pub fn content(&self, file: FileId) -> &str {
+		&self.file(file).content
+	}
+
+	/// Returns the 1-based column and row of the cursor in the file
+	pub fn get_col_row(&self, file: FileId, cursor: usize) -> (usize, usize) {
+		let line_starts = &self.file(file).line_starts;
+		let row = line_starts.partition_point(|&start| start <= cursor) - 1;
+
+		(cursor - line_starts[row] + 1, row + 1)
+	}
+
+	/// Returns the line pointed to by the cursor, without its line ending
+	pub fn get_context(&self, file: FileId, cursor: usize) -> String {
+		let content_chars = &self.file(file).content_chars;
+		let (col, _) = self.get_col_row(file, cursor);
+		let start = cursor + 1 - col;
+
+		// Find the next newline or carriage return (as that comes before newline on windows)
+		let end = cursor + content_chars[cursor..].iter().position(|&c| c == '\n' || c == '\r').unwrap_or(content_chars.len() - cursor);
+
+		content_chars[start..end].iter().collect()
+	}
+
+	/// Returns the number of chars of the span on the line it starts on, which is at least 1 so that empty spans are still pointed to
+	pub fn get_underline_len(&self, span: Span) -> usize {
+		let context_len = self.get_context(span.file, span.start).chars().count();
+		let (col, _) = self.get_col_row(span.file, span.start);
+		let line_end = span.start + context_len + 1 - col;
+
+		span.end.min(line_end).saturating_sub(span.start).max(1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::parser::tree::Span;
+
+	use super::{SourceMap, TextEdit};
+
+	#[test]
+	fn files_are_kept_apart() {
+		let mut sources = SourceMap::new();
+		let a = sources.add_file("a.tower", "fn a { }");
+		let b = sources.add_file("lib/b.tower", "fn b { }\n");
+
+		assert_ne!(a, b);
+		assert_eq!((sources.path(a), sources.content(a)), ("a.tower", "fn a { }"));
+		assert_eq!((sources.path(b), sources.content(b)), ("lib/b.tower", "fn b { }\n"));
+	}
+
+	#[test]
+	fn positions_count_chars() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "fn é {\r\n\t1u\n}");
+
+		assert_eq!(sources.get_col_row(file, 0), (1, 1));
+		assert_eq!(sources.get_col_row(file, 5), (6, 1));
+		assert_eq!(sources.get_col_row(file, 9), (2, 2));
+		assert_eq!(sources.get_col_row(file, 12), (1, 3));
+		assert_eq!(sources.get_context(file, 3), "fn é {");
+		assert_eq!(sources.get_context(file, 10), "\t1u");
+	}
+
+	#[test]
+	fn underline_stays_on_first_line() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "fn a {\n}");
+
+		assert_eq!(sources.get_underline_len(Span::new(file, 0, 2)), 2);
+		assert_eq!(sources.get_underline_len(Span::new(file, 3, 8)), 3);
+		assert_eq!(sources.get_underline_len(Span::point(file, 3)), 1);
+	}
+
+	#[test]
+	fn edit_replaces_chars() {
+		let mut sources = SourceMap::new();
+		let file = sources.add_file("test.tower", "fn é { 1u }\nfn b { }");
+		let edit = TextEdit { start: 7, end: 9, text: "2u 3u".to_string() };
+		sources.edit(file, &edit);
+
+		assert_eq!(edit.delta(), 3);
+		assert_eq!(sources.content(file), "fn é { 2u 3u }\nfn b { }");
+		assert_eq!(sources.get_col_row(file, 15), (1, 2));
+	}
+}
@@ -0,0 +1,293 @@
+use super::{cst::{self, CstKind, CstNode, Event}, error::SyntaxError, lexer::{Token, TokenKind}, result::ScanResult::{self, Valid, WithErr, Unrecognised}, source_map::FileId, tree::{Literal, Span}};
+
+/// The tokens of a file, which the parser takes from in order. Whitespace and comments are skipped over, so only matter to the parser
+/// where it checks whether tokens are adjacent
+pub struct TokenStream {
+	file: FileId,
+	tokens: Vec<Token>,
+	/// The index of the next token, which may be trivia
+	index: usize,
+	/// The char index of the end of the file, which errors at the end of the stream point to
+	end: usize,
+	/// The errors the parser has recovered from so far, starting with any from lexing
+	errors: Vec<SyntaxError>,
+	/// The starts and ends of the CST nodes parsed so far
+	events: Vec<Event>
+}
+
+/// The start of a CST node, which must be completed once the node has been parsed
+pub struct Marker(usize);
+
+impl TokenStream {
+	pub fn new(tokens: Vec<Token>, errors: Vec<SyntaxError>, file: FileId, end: usize) -> Self {
+		TokenStream {
+			file,
+			tokens,
+			index: 0,
+			end,
+			errors,
+			events: Vec::new()
+		}
+	}
+
+	pub fn file(&self) -> FileId {
+		self.file
+	}
+
+	/// Records an error the parser has recovered from. Errors at an Invalid token are caused by the lexing error already recorded for it,
+	/// so are left out
+	pub fn report(&mut self, error: SyntaxError) {
+		if !self.tokens.iter().any(|token| token.kind == TokenKind::Invalid && token.start == error.cursor()) {
+			self.errors.push(error);
+		}
+	}
+
+	/// Returns the errors recorded so far, in the order they appear in the file
+	pub fn take_errors(&mut self) -> Vec<SyntaxError> {
+		let mut errors = std::mem::take(&mut self.errors);
+		errors.sort_by_key(|e| e.cursor());
+
+		errors
+	}
+
+	/// Builds the lossless CST of the file from the tokens and the nodes parsed from them, with the text of each token taken from content
+	pub fn build_cst(&self, content: &str) -> CstNode {
+		cst::build(self.file, content, &self.tokens, &self.events, self.end)
+	}
+
+	/// Starts a CST node before the next token that isn't trivia
+	pub fn start(&mut self) -> Marker {
+		self.events.push(Event::Start { kind: None, position: self.next_index().unwrap_or(self.tokens.len()) });
+
+		Marker(self.events.len() - 1)
+	}
+
+	/// Starts a CST node before any doc comments before the next token that isn't trivia, so that they are part of the node they document
+	pub fn start_documented(&mut self) -> Marker {
+		self.events.push(Event::Start { kind: None, position: self.doc_index() });
+
+		Marker(self.events.len() - 1)
+	}
+
+	/// Completes the CST node started by the marker, after the last token taken
+	pub fn complete(&mut self, marker: Marker, kind: CstKind) {
+		if let Event::Start { kind: start_kind, .. } = &mut self.events[marker.0] {
+			*start_kind = Some(kind);
+		}
+
+		self.events.push(Event::Finish { position: self.index });
+	}
+
+	/// Removes the CST node started by the marker, along with any nodes started since
+	pub fn abandon(&mut self, marker: Marker) {
+		self.events.truncate(marker.0);
+	}
+
+	/// Parses with f inside a CST node of the kind, which is removed if f returns Unrecognised
+	pub fn node<R, E>(&mut self, kind: CstKind, f: impl FnOnce(&mut Self) -> ScanResult<R, E>) -> ScanResult<R, E> {
+		let marker = self.start();
+
+		self.finish_node(marker, kind, f)
+	}
+
+	/// Parses with f inside a CST node of the kind like node, but with any doc comments before it inside the node too
+	pub fn documented_node<R, E>(&mut self, kind: CstKind, f: impl FnOnce(&mut Self) -> ScanResult<R, E>) -> ScanResult<R, E> {
+		let marker = self.start_documented();
+
+		self.finish_node(marker, kind, f)
+	}
+
+	fn finish_node<R, E>(&mut self, marker: Marker, kind: CstKind, f: impl FnOnce(&mut Self) -> ScanResult<R, E>) -> ScanResult<R, E> {
+		let ret = f(self);
+
+		match ret {
+			Unrecognised => self.abandon(marker),
+			_ => self.complete(marker, kind)
+		}
+
+		ret
+	}
+
+	/// Calls f, then returns to the current position, discarding any CST nodes parsed by f
+	pub fn peek_ahead<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+		let index = self.index;
+		let events = self.events.len();
+
+		let ret = f(self);
+
+		self.index = index;
+		self.events.truncate(events);
+
+		ret
+	}
+
+	/// Returns the position of the stream, which can be returned to with set_position
+	pub fn position(&self) -> usize {
+		self.index
+	}
+
+	pub fn set_position(&mut self, position: usize) {
+		self.index = position;
+	}
+
+	/// Returns the index of the next token that isn't trivia
+	fn next_index(&self) -> Option<usize> {
+		(self.index..self.tokens.len()).find(|&i| !self.tokens[i].is_trivia())
+	}
+
+	/// Returns the char index of the start of the next token that isn't trivia, or the end of the file if there isn't one
+	pub fn cursor(&self) -> usize {
+		self.next_index().map_or(self.end, |i| self.tokens[i].start)
+	}
+
+	/// Returns the empty span at the cursor, which errors about the next token point to
+	pub fn point(&self) -> Span {
+		Span::point(self.file, self.cursor())
+	}
+
+	/// Returns the index of the first of the doc comments directly before the next token that isn't trivia, or of that token if there
+	/// are none. Doc comments can be separated by whitespace, but not by other comments
+	fn doc_index(&self) -> usize {
+		let next = self.next_index().unwrap_or(self.tokens.len());
+		let start = self.tokens[self.index..next].iter()
+			.rposition(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::DocComment(_)))
+			.map_or(self.index, |i| self.index + i + 1);
+
+		(start..next).find(|&i| matches!(self.tokens[i].kind, TokenKind::DocComment(_))).unwrap_or(next)
+	}
+
+	/// Returns the char index of the start of the doc comments before the next token that isn't trivia, or cursor if there are none
+	pub fn doc_cursor(&self) -> usize {
+		self.tokens.get(self.doc_index()).map_or(self.end, |token| token.start)
+	}
+
+	/// Returns the text of the doc comments before the next token that isn't trivia, with a line for each comment, if there are any. The
+	/// space usually written after the `##` is left out
+	pub fn doc(&self) -> Option<String> {
+		let next = self.next_index().unwrap_or(self.tokens.len());
+		let lines: Vec<&str> = self.tokens[self.doc_index()..next].iter()
+			.filter_map(|token| match &token.kind {
+				TokenKind::DocComment(text) => Some(text.strip_prefix(' ').unwrap_or(text)),
+				_ => None
+			})
+			.collect();
+
+		(!lines.is_empty()).then(|| lines.join("\n"))
+	}
+
+	/// Returns the span from the char index start to the end of the last token taken that isn't trivia, which is empty if no token has
+	/// been taken since start
+	pub fn span_from(&self, start: usize) -> Span {
+		let end = self.tokens[..self.index].iter().rev().find(|token| !token.is_trivia()).map_or(start, |token| token.end);
+
+		Span::new(self.file, start, end.max(start))
+	}
+
+	pub fn has_next(&self) -> bool {
+		self.next_index().is_some()
+	}
+
+	pub fn peek(&self) -> Option<&TokenKind> {
+		self.next_index().map(|i| &self.tokens[i].kind)
+	}
+
+	/// Returns whether the next token directly follows the last token taken, with no whitespace or comments between them
+	pub fn is_adjacent(&self) -> bool {
+		self.index > 0 && self.tokens.get(self.index).is_some_and(|token| !token.is_trivia())
+	}
+
+	/// Returns the next token that isn't trivia if there is one, and advances past it
+	pub fn pop(&mut self) -> Option<TokenKind> {
+		let i = self.next_index()?;
+		self.index = i + 1;
+
+		Some(self.tokens[i].kind.clone())
+	}
+
+	/// If f returns a value for the next token that isn't trivia, returns that value, advancing past the token. Otherwise, returns None
+	pub fn take_map<R>(&mut self, f: impl FnOnce(&TokenKind) -> Option<R>) -> Option<R> {
+		let i = self.next_index()?;
+		let ret = f(&self.tokens[i].kind)?;
+		self.index = i + 1;
+
+		Some(ret)
+	}
+
+	/// If the next token is the punctuation character c, return true
+	pub fn take_punct(&mut self, c: char) -> bool {
+		self.take_map(|kind| (kind == &TokenKind::Punct(c)).then_some(())).is_some()
+	}
+
+	/// If the next token is the identifier keyword, return true
+	pub fn take_keyword(&mut self, keyword: &str) -> bool {
+		self.take_map(|kind| matches!(kind, TokenKind::Identifier(ident) if ident == keyword).then_some(())).is_some()
+	}
+
+	pub fn take_identifier(&mut self) -> Option<String> {
+		self.take_map(|kind| match kind {
+			TokenKind::Identifier(ident) => Some(ident.clone()),
+			_ => None
+		})
+	}
+
+	pub fn take_literal(&mut self) -> Option<Literal> {
+		self.take_map(|kind| match kind {
+			TokenKind::Literal(literal) => Some(literal.clone()),
+			_ => None
+		})
+	}
+
+	/// Attempts to match with the given function, returning with the function return value, and not advancing in case of an Unrecognised
+	pub fn try_take<R, E>(&mut self, f: impl FnOnce(&mut Self) -> ScanResult<R, E>) -> ScanResult<R, E> {
+		let index = self.index;
+		let events = self.events.len();
+
+		match f(self) {
+			Unrecognised => {
+				self.index = index;
+				self.events.truncate(events);
+				Unrecognised
+			}
+			r => r
+		}
+	}
+
+	/// Attempts to match with the given function any number of times, returning a list of all return values and an error if one occurred.
+	/// Errors or Unrecognised cause this function to return.
+	pub fn take_any<R, E>(&mut self, mut f: impl FnMut(&mut Self) -> ScanResult<R, E>) -> (Vec<R>, Option<E>) {
+		let mut rs = Vec::new();
+		let mut err = None;
+
+		while match self.try_take(&mut f) {
+			Valid(r) => {
+				rs.push(r);
+				true
+			}
+			WithErr(e) => {
+				err = Some(e);
+				false
+			}
+			_ => false
+		} {}
+
+		(
+			rs,
+			err
+		)
+	}
+
+	/// Calls try_take on each function provided, returning the first Valid or WithErr value, returning Unrecognised if there are none
+	pub fn take_choice<R, E>(&mut self, fs: Vec<Box<dyn FnMut(&mut Self) -> ScanResult<R, E>>>) -> ScanResult<R, E> {
+		for mut f in fs {
+			match self.try_take(|tokens| {
+				f(tokens)
+			}) {
+				Valid(ret) => return Valid(ret),
+				WithErr(e) => return WithErr(e),
+				_ => ()
+			}
+		}
+
+		Unrecognised
+	}
+}
@@ -1,3 +1,5 @@
+use super::source_map::FileId;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParseTreeType {
 	None,
@@ -5,16 +7,64 @@ pub enum ParseTreeType {
 	Function,
 	Struct,
 	Enum,
+	Extern,
+	StaticAssert,
+	Cfg,
+	Attribute,
+	Item,
+	Type,
 	Identifier,
 	Literal,
 	Constructor,
-	FieldAccess
+	FieldAccess,
+	Token
+}
+
+/// A range of char indices in a source file, from start inclusive to end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+	pub file: FileId,
+	pub start: usize,
+	pub end: usize
+}
+
+impl Span {
+	pub fn new(file: FileId, start: usize, end: usize) -> Self {
+		Span { file, start, end }
+	}
+
+	/// An empty span at cursor, for errors that don't cover any source text such as a missing token at the end of a file
+	pub fn point(file: FileId, cursor: usize) -> Self {
+		Span::new(file, cursor, cursor)
+	}
+
+	/// Returns the span moved delta chars through the file, e.g. after an edit before it
+	pub fn shifted(self, delta: isize) -> Self {
+		Span::new(self.file, self.start.saturating_add_signed(delta), self.end.saturating_add_signed(delta))
+	}
+
+	/// Returns the span from the start of self to the end of other, which must be in the same file
+	pub fn to(self, other: Span) -> Self {
+		Span::new(self.file, self.start, other.end.max(self.start))
+	}
+}
+
+/// A value together with the span of source it was parsed from, for parts of items that aren't nodes themselves, such as struct fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+	pub value: T,
+	pub span: Span
+}
+
+impl<T> Spanned<T> {
+	pub fn new(value: T, span: Span) -> Self {
+		Spanned { value, span }
+	}
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParseTreeNode {
-	pub file_path: String,
-	pub cursor: usize,
+	pub span: Span,
 	pub tree: ParseTree
 }
 
@@ -26,32 +76,152 @@ pub enum ParseTree {
 	},
 	Function {
 		name: String,
-		body: im::Vector<ParseTreeNode>
+		body: im::Vector<ParseTreeNode>,
+		/// Whether the function is marked with `export`, to be callable from C
+		exported: bool,
+		/// Whether the function is a `const fn`, evaluated during analysis
+		comptime: bool,
+		attributes: im::Vector<Attribute>,
+		/// The text of the `##` doc comments before the function
+		doc: Option<String>
 	},
 	Struct {
 		name: String,
-		fields: im::OrdMap<String, String>
+		/// The type name of each field, spanning the field's `name: type`
+		fields: im::OrdMap<String, Spanned<String>>,
+		attributes: im::Vector<Attribute>,
+		/// The text of the `##` doc comments before the struct
+		doc: Option<String>,
+		/// The text of the `##` doc comments before each documented field
+		field_docs: im::OrdMap<String, String>
 	},
 	Enum {
 		name: String,
 		fields: im::OrdMap<String, String>
 	},
+	/// A C function named name in the dynamic library library, with the declared parameter and return type names
+	Extern {
+		name: String,
+		library: String,
+		params: im::Vector<Spanned<String>>,
+		returns: im::Vector<Spanned<String>>
+	},
+	/// An assertion evaluated during analysis, which fails analysis with the message if the body doesn't push true
+	StaticAssert {
+		message: Option<String>,
+		body: im::Vector<ParseTreeNode>
+	},
+	/// An item named name which is only compiled when the condition holds, removed or unwrapped by `parser::cfg::apply` before analysis
+	Cfg {
+		condition: CfgCondition,
+		name: String,
+		item: Box<ParseTreeNode>
+	},
 	Identifier(String),
 	Literal(Literal),
 	Constructor(String),
-	FieldAccess(String)
+	FieldAccess(String),
+	/// A placeholder for an item or block element that failed to parse, whose syntax error has already been reported
+	Invalid
 }
 
 impl ParseTree {
-	pub fn wrap(self, file_path: impl Into<String>, cursor: usize) -> ParseTreeNode {
+	pub fn wrap(self, span: Span) -> ParseTreeNode {
 		ParseTreeNode {
-			file_path: file_path.into(),
-			cursor,
+			span,
 			tree: self
 		}
 	}
 }
 
+impl ParseTreeNode {
+	/// Returns the node with its span and the spans of everything in it moved delta chars through the file
+	pub fn shifted(&self, delta: isize) -> ParseTreeNode {
+		let shift_all = |nodes: &im::Vector<ParseTreeNode>| nodes.iter().map(|node| node.shifted(delta)).collect();
+		let shift_types = |types: &im::Vector<Spanned<String>>| types.iter().map(|tname| Spanned::new(tname.value.clone(), tname.span.shifted(delta))).collect();
+
+		let tree = match &self.tree {
+			ParseTree::Module { name, elems } => ParseTree::Module {
+				name: name.clone(),
+				elems: elems.iter().map(|(name, node)| (name.clone(), node.shifted(delta))).collect()
+			},
+			ParseTree::Function { name, body, exported, comptime, attributes, doc } => ParseTree::Function {
+				name: name.clone(),
+				body: shift_all(body),
+				exported: *exported,
+				comptime: *comptime,
+				attributes: attributes.clone(),
+				doc: doc.clone()
+			},
+			ParseTree::Struct { name, fields, attributes, doc, field_docs } => ParseTree::Struct {
+				name: name.clone(),
+				fields: fields.iter().map(|(fname, ftype)| (fname.clone(), Spanned::new(ftype.value.clone(), ftype.span.shifted(delta)))).collect(),
+				attributes: attributes.clone(),
+				doc: doc.clone(),
+				field_docs: field_docs.clone()
+			},
+			ParseTree::Extern { name, library, params, returns } => ParseTree::Extern {
+				name: name.clone(),
+				library: library.clone(),
+				params: shift_types(params),
+				returns: shift_types(returns)
+			},
+			ParseTree::StaticAssert { message, body } => ParseTree::StaticAssert {
+				message: message.clone(),
+				body: shift_all(body)
+			},
+			ParseTree::Cfg { condition, name, item } => ParseTree::Cfg {
+				condition: condition.clone(),
+				name: name.clone(),
+				item: Box::new(item.shifted(delta))
+			},
+			tree => tree.clone()
+		};
+
+		tree.wrap(self.span.shifted(delta))
+	}
+}
+
+/// An attribute on a function or struct, e.g. `@inline` or `@deprecated("use bar instead")`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attribute {
+	/// Hints that calls to the function should be inlined when compiled
+	Inline,
+	/// Prevents calls to the function from being inlined when compiled
+	NoInline,
+	/// Marks the function as unlikely to be called, e.g. error paths
+	Cold,
+	/// Warns when the result of the function is immediately dropped
+	MustUse,
+	/// Warns when the function is called or the struct is constructed, with an optional message
+	Deprecated(Option<String>)
+}
+
+impl Attribute {
+	/// Returns the name the attribute is written with, e.g. `noinline`
+	pub fn name(&self) -> &'static str {
+		match self {
+			Attribute::Inline => "inline",
+			Attribute::NoInline => "noinline",
+			Attribute::Cold => "cold",
+			Attribute::MustUse => "must_use",
+			Attribute::Deprecated(_) => "deprecated"
+		}
+	}
+}
+
+/// The condition of a `@cfg(...)` attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum CfgCondition {
+	/// Holds when the flag is set, e.g. `debug`
+	Flag(String),
+	/// Holds when the key is set to the value, e.g. `target_os = "linux"`
+	KeyValue(String, String),
+	All(im::Vector<CfgCondition>),
+	Any(im::Vector<CfgCondition>),
+	Not(Box<CfgCondition>)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
 	U128(u128),
@@ -67,6 +237,9 @@ pub enum Literal {
 	F64(f64),
 	F32(f64),
 	Bool(bool),
+	Char(char),
 	String(String),
+	ByteString(Vec<u8>),
+	Array(Vec<Literal>),
 	FnPtr(String)
 }
\ No newline at end of file